@@ -0,0 +1,8 @@
+#include <stdlib.h>
+int main(int argc, char** argv) {
+  char* slots[8];
+  int i = argc & 7;
+  slots[i] = malloc(1);
+  free(slots[i]);
+  *slots[i] = 'a';
+}
@@ -0,0 +1,10 @@
+#include <stdlib.h>
+int main(int argc, char** argv) {
+  char* p = malloc(1);
+  if (argc == 1) {
+    free(p);
+  }
+  if (argc != 1) {
+    *p = 'a';
+  }
+}
@@ -0,0 +1,12 @@
+#include <stdlib.h>
+struct node {
+  struct node* next;
+  char* data;
+};
+int main() {
+  struct node* head = malloc(sizeof(struct node));
+  head->next = malloc(sizeof(struct node));
+  head->next->data = malloc(1);
+  free(head->next->data);
+  *head->next->data = 'a';
+}
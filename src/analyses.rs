@@ -1,5 +1,5 @@
 use std::fmt::Write;
-use bap::basic::{Image, Arch, Bap, BasicDisasm, Cast, BitSize};
+use bap::basic::{Image, Arch, Bap, BasicDisasm};
 use bap::high::bil::{Statement, Expression, Variable, Type, BinOp};
 use bap::high::bitvector::BitVector;
 use bap;
@@ -7,40 +7,31 @@ use ubvs::UpperBVSet;
 use sema::Sema;
 use std::cmp::min;
 use num::ToPrimitive;
-use holmes::pg::dyn::values::LargeBWrap;
 use var::HVar;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use var;
+use ids;
+use lift_cache::{LiftCache, Insn};
+use LiftErrors;
+use arith;
+use vsa;
+use cond::{self, Atom, Cond};
 
-macro_rules! get_image {
-    ($bap:expr, $contents:expr) => {{
-        match Image::from_data(&$bap, &$contents) {
-            Ok(i) => i,
-            Err(_) => return vec![]
-        }
-    }}
-}
-
-//TODO get first class fact IDs so that I don't need to engage in this farce
-pub fn hashify((i, n, a): (&u64, &String, &BitVector)) -> u64 {
-    use std::hash::{Hash, Hasher};
-    use std::collections::hash_map::DefaultHasher;
-    let mut hasher = DefaultHasher::new();
-    i.hash(&mut hasher);
-    n.hash(&mut hasher);
-    a.hash(&mut hasher);
-    hasher.finish()
+pub fn trace_len_inc(len: usize) -> Box<Fn(&u64) -> Vec<u64>> {
+    Box::new(move |i| if *i < len as u64 { vec![*i + 1] } else { vec![] })
 }
 
-pub fn trace_len_inc(len: usize) -> Box<Fn(&u64) -> Vec<u64>> {
+pub fn stack_len_inc(len: usize) -> Box<Fn(&u64) -> Vec<u64>> {
     Box::new(move |i| if *i < len as u64 { vec![*i + 1] } else { vec![] })
 }
 
-pub fn stack_len_inc(i: &u64) -> Vec<u64> {
-    if *i < 5 { vec![*i + 1] } else { vec![] }
+// True where the matching _inc would refuse to go further
+pub fn len_at_limit(len: usize) -> Box<Fn(&u64) -> bool> {
+    Box::new(move |i| *i >= len as u64)
 }
 
 pub fn fmt_str_vars(fmt: &String) -> Vec<HVar> {
@@ -57,20 +48,17 @@ pub fn fmt_str_vars(fmt: &String) -> Vec<HVar> {
     (0..args).map(|i| var::get_arg_n(i as u8)).collect()
 }
 
-pub fn unpack_deb(mut fd: &File) -> Vec<(String, LargeBWrap)> {
+pub fn unpack_deb(buf: &[u8]) -> Vec<(String, Vec<u8>)> {
     use std::fs::File;
     use std::io::prelude::*;
     use mktemp::Temp;
     use std::path::Path;
-    let mut buf = Vec::new();
-    fd.seek(SeekFrom::Start(0)).unwrap();
-    fd.read_to_end(&mut buf).unwrap();
     let deb_temp = Temp::new_file().unwrap();
     let deb_path_buf = deb_temp.to_path_buf();
     let deb_path = deb_path_buf.to_str().unwrap();
     {
         let mut deb_file = File::create(deb_path).unwrap();
-        deb_file.write_all(&buf).unwrap();
+        deb_file.write_all(buf).unwrap();
     }
     let unpack_temp_dir = Temp::new_dir().unwrap();
     let unpack_path_buf = unpack_temp_dir.to_path_buf();
@@ -102,30 +90,11 @@ pub fn unpack_deb(mut fd: &File) -> Vec<(String, LargeBWrap)> {
             let mut elf_file = File::open(path).unwrap();
             let mut out = Vec::new();
             elf_file.read_to_end(&mut out).unwrap();
-            (file_name, LargeBWrap { inner: out })
+            (file_name, out)
         })
         .collect()
 }
 
-fn compute_op(op: ::bap::basic::BinOp, lhs: BitVector, rhs: BitVector) -> Option<BitVector> {
-    use bap::basic::BinOp::*;
-    match op {
-        Add => Some(&lhs + &rhs),
-        _ => None
-    }
-}
-fn compute_cast(k: Cast, bs: BitSize, v: BitVector) -> Option<BitVector> {
-    match k {
-        Cast::Low => {
-            let mut bv = v.into_bitvec();
-            bv.truncate(bs as usize);
-            Some(BitVector::new(&bv))
-        }
-        Cast::Unsigned => Some(BitVector::new_unsigned(v.unum(), bs as usize)),
-        _ => None,
-    }
-}
-
 fn compute_expr(e: &Expression, ks: &HashMap<HVar, BitVector>) -> Option<BitVector> {
     use bap::high::bil::Expression::*;
     match *e {
@@ -133,20 +102,61 @@ fn compute_expr(e: &Expression, ks: &HashMap<HVar, BitVector>) -> Option<BitVect
             ks.get(&HVar {
                 inner: v.clone(),
                 offset: None,
+                array: None,
             }).cloned()
         }
         Load { index: ref idx, .. } => promote_idx(idx).and_then(|v| ks.get(&v)).cloned(),
         Const(ref bv) => Some(bv.clone()),
-        Cast { kind, width: bs, arg: ref expr } =>
-            match compute_expr(expr, ks) {
-                Some(bv) => compute_cast(kind, bs, bv),
-                None => None
-            },
-        BinOp { op, ref lhs, ref rhs } =>
+        Cast { kind, width: bs, arg: ref expr } => {
+            compute_expr(expr, ks).map(|bv| arith::cast(kind, bs, &bv))
+        }
+        BinOp { op, ref lhs, ref rhs } => {
             match (compute_expr(lhs, ks), compute_expr(rhs, ks)) {
-                (Some(lhs_v), Some(rhs_v)) => compute_op(op, lhs_v, rhs_v),
-                _ => None
-            },
+                (Some(lhs_v), Some(rhs_v)) => arith::binop(op, &lhs_v, &rhs_v),
+                _ => None,
+            }
+        }
+        UnOp { op, ref arg } => compute_expr(arg, ks).map(|bv| arith::unop(op, &bv)),
+        Let { ref bind, ref value, ref body } => {
+            let mut inner = ks.clone();
+            let var = HVar {
+                inner: bind.clone(),
+                offset: None,
+                array: None,
+            };
+            match compute_expr(value, ks) {
+                Some(bv) => inner.insert(var, bv),
+                None => inner.remove(&var),
+            };
+            compute_expr(body, &inner)
+        }
+        IfThenElse { ref cond, ref true_expr, ref false_expr } => {
+            match compute_expr(cond, ks) {
+                Some(c) => {
+                    if arith::is_true(&c) {
+                        compute_expr(true_expr, ks)
+                    } else {
+                        compute_expr(false_expr, ks)
+                    }
+                }
+                // Unknown condition, but both arms may still agree
+                None => {
+                    match (compute_expr(true_expr, ks), compute_expr(false_expr, ks)) {
+                        (Some(t), Some(f)) => if t == f { Some(t) } else { None },
+                        _ => None,
+                    }
+                }
+            }
+        }
+        Extract { low_bit, high_bit, ref arg } => {
+            compute_expr(arg, ks).map(|bv| arith::extract(low_bit as usize, high_bit as usize, &bv))
+        }
+        Concat { ref low, ref high } => {
+            match (compute_expr(high, ks), compute_expr(low, ks)) {
+                (Some(high_v), Some(low_v)) => Some(arith::concat(&high_v, &low_v)),
+                _ => None,
+            }
+        }
         _ => None,
     }
 }
@@ -162,6 +172,7 @@ fn const_prop_h(stmt: &Statement, ks: &mut HashMap<HVar, BitVector>) {
             let var = HVar {
                 inner: reg.clone(),
                 offset: None,
+                array: None,
             };
             match compute_expr(e, ks) {
                 Some(bv) => {
@@ -219,28 +230,15 @@ pub fn is_computed_jump(sema: &Sema) -> bool {
     return false
 }
 
-pub fn const_init(sema: &Sema) -> Vec<(HVar, BitVector)> {
-    let mut ks = HashMap::new();
-    for stmt in sema.stmts.iter() {
-        const_prop_h(stmt, &mut ks)
-    }
-    // No temporaries or flags
-    ks.into_iter()
-        .filter(|kv| {
-            !kv.0.inner.tmp && (kv.0.inner.type_ != bap::high::bil::Type::Immediate(1))
-        })
-        .collect()
-}
-
 fn stack_hvar(hv: &HVar) -> bool {
     let name = &hv.inner.name;
     (name == "RBP") || (name == "RSP")
 }
 
-fn heap_prop(stmt: &Statement, ks: &mut Vec<HashSet<HVar>>) {
+fn heap_prop(stmt: &Statement, ks: &mut Vec<HashSet<HVar>>, env: &vsa::Env) {
     let mut all_tracked = HashSet::new();
     for ass in ks.iter_mut() {
-        *ass = HashSet::from_iter(proc_stmt(ass.iter().cloned().collect::<Vec<_>>(), stmt).into_iter());
+        *ass = HashSet::from_iter(proc_stmt(ass.iter().cloned().collect::<Vec<_>>(), stmt, env).into_iter());
         all_tracked.extend(ass.iter().cloned());
     }
     match *stmt {
@@ -255,7 +253,8 @@ fn heap_prop(stmt: &Statement, ks: &mut Vec<HashSet<HVar>>) {
                 } => {
                     let hvar = HVar {
                         inner: lhs.clone(),
-                        offset: None
+                        offset: None,
+                        array: None,
                     };
                     if !all_tracked.contains(&hvar) {
                         // This variable doesn't contain a tracked pointer already
@@ -278,8 +277,10 @@ fn heap_prop(stmt: &Statement, ks: &mut Vec<HashSet<HVar>>) {
 
 pub fn heap_init(sema: &Sema) -> Vec<(u64, Vec<HVar>)> {
     let mut hs = Vec::new();
+    let mut env = vsa::Env::new();
     for stmt in sema.stmts.iter() {
-        heap_prop(stmt, &mut hs)
+        heap_prop(stmt, &mut hs, &env);
+        env.step(stmt);
     }
     // No temporaries or flags
     hs.into_iter()
@@ -291,59 +292,179 @@ pub fn heap_init(sema: &Sema) -> Vec<(u64, Vec<HVar>)> {
         .collect()
 }
 
-pub fn const_prop((sema, var, k): (&Sema, &HVar, &BitVector)) -> Vec<(HVar, BitVector)> {
+// Registers the System V ABI has callees preserve, plus the caller's own stack slots
+fn callee_saved(hv: &HVar) -> bool {
+    if hv.offset.is_some() {
+        return stack_hvar(hv);
+    }
+    match hv.inner.name.as_str() {
+        "RBX" | "RBP" | "RSP" | "R12" | "R13" | "R14" | "R15" => true,
+        _ => false,
+    }
+}
+
+fn const_visible(hv: &HVar) -> bool {
+    // No temporaries or flags
+    !hv.inner.tmp && (hv.inner.type_ != bap::high::bil::Type::Immediate(1))
+}
+
+/// Forward constant propagation over the CFG of one binary.
+///
+/// `blocks` are (address, semantics, fallthrough, is_call) and `edges` are `succ` facts. Values
+/// reaching a block must agree on every incoming edge to survive, and only callee-saved state
+/// survives a call. Blocks with no incoming edges start with nothing known. Returns the values
+/// known at the start of each block, and the targets of computed jumps that turn out constant.
+pub fn const_flow(
+    blocks: &[(BitVector, Sema, BitVector, bool)],
+    edges: &[(BitVector, BitVector, bool)],
+) -> (Vec<(BitVector, HVar, BitVector)>, Vec<(BitVector, BitVector)>) {
+    let index: HashMap<&BitVector, usize> = blocks.iter().enumerate().map(|(i, b)| (&b.0, i)).collect();
+    let mut succs: Vec<Vec<(usize, bool)>> = blocks.iter().map(|_| Vec::new()).collect();
+    let mut has_pred = vec![false; blocks.len()];
+    for &(ref src, ref dst, call) in edges.iter() {
+        if let (Some(&s), Some(&d)) = (index.get(src), index.get(dst)) {
+            // Call edges don't carry state into the callee; the return site gets it instead
+            if call {
+                continue;
+            }
+            let over_call = blocks[s].3 && blocks[s].2 == *dst;
+            if !succs[s].contains(&(d, over_call)) {
+                succs[s].push((d, over_call));
+            }
+            has_pred[d] = true;
+        }
+    }
+    // Return sites of direct calls only have the call edge, so link them here
+    for (s, block) in blocks.iter().enumerate() {
+        if block.3 {
+            if let Some(&d) = index.get(&block.2) {
+                if !succs[s].contains(&(d, true)) {
+                    succs[s].push((d, true));
+                    has_pred[d] = true;
+                }
+            }
+        }
+    }
+
+    let mut state: Vec<Option<HashMap<HVar, BitVector>>> = has_pred
+        .iter()
+        .map(|&p| if p { None } else { Some(HashMap::new()) })
+        .collect();
+    let mut work: Vec<usize> = (0..blocks.len()).filter(|&i| !has_pred[i]).collect();
+    let mut jumps = HashMap::new();
+    while let Some(i) = work.pop() {
+        // A revisit knows less, so the target may no longer be constant
+        jumps.remove(&i);
+        let mut ks = state[i].clone().unwrap();
+        for stmt in blocks[i].1.stmts.iter() {
+            if let Statement::Jump(ref e) = *stmt {
+                match *e {
+                    Expression::Const(_) => (),
+                    _ => {
+                        if let Some(tgt) = compute_expr(e, &ks) {
+                            jumps.insert(i, tgt);
+                        }
+                    }
+                }
+            }
+            const_prop_h(stmt, &mut ks)
+        }
+        for &(d, over_call) in succs[i].iter() {
+            let out: HashMap<HVar, BitVector> = ks.iter()
+                .filter(|&(hv, _)| !over_call || callee_saved(hv))
+                .map(|(hv, k)| (hv.clone(), k.clone()))
+                .collect();
+            // Keep only what every predecessor seen so far agrees on
+            let merged = match state[d] {
+                None => out,
+                Some(ref known) => known.iter()
+                    .filter(|&(hv, k)| out.get(hv) == Some(k))
+                    .map(|(hv, k)| (hv.clone(), k.clone()))
+                    .collect(),
+            };
+            let changed = match state[d] {
+                None => true,
+                Some(ref known) => known.len() != merged.len(),
+            };
+            if changed {
+                state[d] = Some(merged);
+                work.push(d);
+            }
+        }
+    }
+
+    let mut consts = Vec::new();
+    for (i, ks) in state.into_iter().enumerate() {
+        for (hv, k) in ks.unwrap_or_default().into_iter() {
+            if const_visible(&hv) {
+                consts.push((blocks[i].0.clone(), hv, k));
+            }
+        }
+    }
+    let jumps = jumps.into_iter().map(|(i, tgt)| (blocks[i].0.clone(), tgt)).collect();
+    (consts, jumps)
+}
+
+// What constant propagation knows at the end of sema, starting from nothing
+pub fn const_block(sema: &Sema) -> Vec<(HVar, BitVector)> {
     let mut ks = HashMap::new();
-    ks.insert(var.clone(), k.clone());
     for stmt in sema.stmts.iter() {
-        const_prop_h(stmt, &mut ks)
+        const_prop_h(stmt, &mut ks);
     }
-    // No temporaries or flags
-    ks.into_iter()
-        .filter(|kv| {
-            !kv.0.inner.tmp && (kv.0.inner.type_ != bap::high::bil::Type::Immediate(1))
-        })
-        .collect()
+    ks.into_iter().filter(|&(ref hv, _)| const_visible(hv)).collect()
 }
 
-pub fn rebase(
-    (base, end, addr, len): (&BitVector, &BitVector, &BitVector, &u64),
-) -> Vec<(u64, u64)> {
+// Offset of addr into the segment, and the offset of the end of the segment's data
+pub fn rebase((base, end, addr): (&BitVector, &BitVector, &BitVector)) -> Vec<(u64, u64)> {
     let addr = addr.to_u64().unwrap();
     let end = end.to_u64().unwrap();
     let base = base.to_u64().unwrap();
     if (addr >= base) && (addr < end) {
         let datlen = end - base;
         let start = addr - base;
-        let end = min(start + len, datlen);
-        vec![(start, end)]
+        vec![(start, datlen)]
     } else {
         vec![]
     }
 }
 
-static mut IDS: u64 = 0;
+// Calls into BAP this process has made, to keep an eye on how many round-trips lifting costs
+static BAP_CALLS: AtomicUsize = ATOMIC_USIZE_INIT;
 
-fn fresh() -> u64 {
-    unsafe {
-        IDS = IDS + 1;
-        IDS
-    }
+pub fn bap_calls() -> usize {
+    BAP_CALLS.load(Ordering::Relaxed)
 }
 
-pub fn seg_wrap(mut fd: &File) -> Vec<(u64, LargeBWrap, BitVector, BitVector, bool, bool, bool)> {
-    let mut contents = Vec::new();
-    fd.seek(SeekFrom::Start(0)).unwrap();
-    fd.read_to_end(&mut contents).unwrap();
+pub struct Ingest {
+    pub arch: Arch,
+    pub segments: Vec<(u64, Vec<u8>, BitVector, BitVector, bool, bool, bool)>,
+    pub symbols: Vec<(String, BitVector, BitVector)>,
+    pub pads: Vec<(String, BitVector)>,
+}
+
+// Everything we want from the image as a whole, from a single parse
+pub fn ingest(contents: &[u8]) -> Option<Ingest> {
+    let file_digest = ids::digest(contents);
+    let pads = get_pads(contents);
+    BAP_CALLS.fetch_add(1, Ordering::Relaxed);
     Bap::with(|bap| {
-        let image = get_image!(bap, contents);
-        let out = {
+        let image = match Image::from_data(&bap, contents) {
+            Ok(i) => i,
+            Err(_) => return None,
+        };
+        let arch = match image.arch() {
+            Ok(a) => a,
+            Err(_) => return None,
+        };
+        let segments = {
             let segs = image.segments();
             segs.iter()
-                .map(|seg| {
+                .enumerate()
+                .map(|(idx, seg)| {
                     let mem = seg.memory();
                     (
-                        fresh(),
-                        LargeBWrap { inner: mem.data() },
+                        ids::segment_id(&file_digest, idx as u64),
+                        mem.data(),
                         BitVector::from_basic(&mem.min_addr()),
                         BitVector::from_basic(&mem.max_addr()),
                         seg.is_readable(),
@@ -353,108 +474,487 @@ pub fn seg_wrap(mut fd: &File) -> Vec<(u64, LargeBWrap, BitVector, BitVector, bo
                 })
                 .collect()
         };
-        out
+        let symbols = {
+            let syms = image.symbols();
+            syms.iter()
+                .map(|x| {
+                    (
+                        x.name(),
+                        BitVector::from_basic(&x.memory().min_addr()),
+                        BitVector::from_basic(&x.memory().max_addr()),
+                    )
+                })
+                .collect()
+        };
+        Some(Ingest {
+            arch: arch,
+            segments: segments,
+            symbols: symbols,
+            pads: pads,
+        })
     })
 }
 
-pub fn stmt_succ(stmts: &[Statement]) -> (Vec<BitVector>, bool) {
-    if stmts.len() == 0 {
-        return (Vec::new(), true);
+// What each register holds so far in a block, in terms of the state on entry. None is a value
+// that can't be written that way.
+#[derive(Clone, Default)]
+struct Defs {
+    exprs: HashMap<Variable, Option<Expression>>,
+    // Registers not in exprs have lost their entry value too
+    lost: bool,
+    // Memory has been written, so loads no longer read entry state
+    mem_dirty: bool,
+}
+
+impl Defs {
+    fn subst(&self, e: &Expression) -> Option<Expression> {
+        use bap::high::bil::Expression::*;
+        match *e {
+            Var(ref v) => {
+                match self.exprs.get(v) {
+                    Some(def) => def.clone(),
+                    None if self.lost => None,
+                    None => Some(Var(v.clone())),
+                }
+            }
+            Const(ref bv) => Some(Const(bv.clone())),
+            Load { ref memory, ref index, ref endian, size } if !self.mem_dirty => {
+                self.subst(index).map(|index| {
+                    Load {
+                        memory: memory.clone(),
+                        index: Box::new(index),
+                        endian: endian.clone(),
+                        size: size,
+                    }
+                })
+            }
+            Cast { kind, width, ref arg } => {
+                self.subst(arg).map(|arg| {
+                    Cast {
+                        kind: kind,
+                        width: width,
+                        arg: Box::new(arg),
+                    }
+                })
+            }
+            BinOp { op, ref lhs, ref rhs } => {
+                match (self.subst(lhs), self.subst(rhs)) {
+                    (Some(lhs), Some(rhs)) => {
+                        Some(BinOp {
+                            op: op,
+                            lhs: Box::new(lhs),
+                            rhs: Box::new(rhs),
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            UnOp { op, ref arg } => self.subst(arg).map(|arg| UnOp { op: op, arg: Box::new(arg) }),
+            _ => None,
+        }
+    }
+    fn step(&mut self, stmt: &Statement) {
+        if let Statement::Move { ref lhs, ref rhs } = *stmt {
+            if is_reg(lhs) {
+                let def = self.subst(rhs);
+                self.exprs.insert(lhs.clone(), def);
+            } else if is_mem(lhs) {
+                self.mem_dirty = true;
+            }
+        }
+    }
+    // What is known after either of two arms has run
+    fn merge(&self, other: &Defs) -> Defs {
+        let mut exprs = self.exprs.clone();
+        for (v, def) in other.exprs.iter() {
+            if exprs.get(v) != Some(def) {
+                exprs.insert(v.clone(), None);
+            }
+        }
+        for (v, def) in exprs.iter_mut() {
+            if !other.exprs.contains_key(v) {
+                *def = None;
+            }
+        }
+        Defs {
+            exprs: exprs,
+            lost: self.lost || other.lost,
+            mem_dirty: self.mem_dirty || other.mem_dirty,
+        }
+    }
+}
+
+// The location and width a compared value is read from
+fn cond_loc(e: &Expression) -> Option<(HVar, usize)> {
+    match *e {
+        Expression::Var(ref v) if is_reg(v) && !v.tmp => {
+            let bits = match v.type_ {
+                Type::Immediate(bits) => bits as usize,
+                _ => return None,
+            };
+            Some((HVar { inner: v.clone(), offset: None, array: None }, bits))
+        }
+        Expression::Cast { kind: bap::basic::Cast::Low, width, ref arg } => {
+            cond_loc(arg).map(|(loc, _)| (loc, width as usize))
+        }
+        Expression::Load { ref index, size, .. } => promote_idx(index).map(|loc| (loc, size as usize)),
+        _ => None,
+    }
+}
+
+fn cmp_atom(lhs: &Expression, rhs: &Expression, eq: bool) -> Vec<Atom> {
+    let (x, k) = match (lhs, rhs) {
+        (x, &Expression::Const(ref k)) | (&Expression::Const(ref k), x) => (x, k.clone()),
+        _ => return Vec::new(),
+    };
+    // cmp leaves x - k == 0 behind, and test x == x & x
+    let (x, k) = match *x {
+        Expression::BinOp { op: BinOp::Sub, ref lhs, ref rhs } => {
+            match **rhs {
+                Expression::Const(ref j) => {
+                    match arith::binop(BinOp::Add, &k, j) {
+                        Some(k) => (&**lhs, k),
+                        None => return Vec::new(),
+                    }
+                }
+                _ => (x, k),
+            }
+        }
+        Expression::BinOp { op: BinOp::And, ref lhs, ref rhs } if lhs == rhs => (&**lhs, k),
+        _ => (x, k),
+    };
+    match cond_loc(x) {
+        Some((loc, bits)) if bits == arith::width(&k) => {
+            vec![Atom {
+                     loc: loc,
+                     bits: bits,
+                     eq: eq,
+                     value: k,
+                 }]
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Atoms implied by e being true (or false when not positive). Anything that isn't a conjunction
+// of comparisons against constants implies nothing.
+fn cond_atoms(e: &Expression, positive: bool) -> Vec<Atom> {
+    match *e {
+        Expression::UnOp { op: bap::basic::UnOp::Not, ref arg } => cond_atoms(arg, !positive),
+        Expression::BinOp { op: BinOp::And, ref lhs, ref rhs } if positive => {
+            let mut atoms = cond_atoms(lhs, true);
+            atoms.extend(cond_atoms(rhs, true));
+            atoms
+        }
+        Expression::BinOp { op: BinOp::Or, ref lhs, ref rhs } if !positive => {
+            let mut atoms = cond_atoms(lhs, false);
+            atoms.extend(cond_atoms(rhs, false));
+            atoms
+        }
+        Expression::BinOp { op: BinOp::Eq, ref lhs, ref rhs } => cmp_atom(lhs, rhs, positive),
+        Expression::BinOp { op: BinOp::Neq, ref lhs, ref rhs } => cmp_atom(lhs, rhs, !positive),
+        _ => {
+            match cond_loc(e) {
+                Some((loc, 1)) => vec![cond::flag(loc, positive)],
+                _ => Vec::new(),
+            }
+        }
+    }
+}
+
+fn guard_with(guard: &Cond, cond: &Option<Expression>, positive: bool) -> Option<Cond> {
+    match *cond {
+        Some(ref c) => {
+            cond_atoms(c, positive).into_iter().fold(Some(guard.clone()), |g, atom| g.and_then(|g| g.and(atom)))
+        }
+        None => Some(guard.clone()),
     }
+}
+
+// Walks stmts having got there under guard, collecting jump targets and the guard for each.
+// Returns the guard for falling off the end, if that can happen at all.
+fn guarded_succ(
+    stmts: &[Statement],
+    defs: &mut Defs,
+    mut guard: Cond,
+    out: &mut Vec<(BitVector, Cond)>,
+) -> Option<Cond> {
     use bap::high::bil::Statement::*;
-    match &stmts[0] {
-        &Jump(Expression::Const(ref v)) => (vec![v.clone()], false),
-        &Jump(_) => (vec![], false),
-        &While { cond: _, ref body } => {
-            let (mut tgts, fall) = stmt_succ(&body);
-            if fall {
-                let (mut tgts2, fall2) = stmt_succ(&stmts[1..]);
-                let mut tgt_res = Vec::new();
-                tgt_res.append(&mut tgts);
-                tgt_res.append(&mut tgts2);
-                (tgt_res, fall2)
-            } else {
-                (tgts, fall)
+    for stmt in stmts.iter() {
+        match *stmt {
+            Jump(Expression::Const(ref v)) => {
+                out.push((v.clone(), guard));
+                return None;
             }
+            Jump(_) => return None,
+            While { ref body, .. } => {
+                // The body may run any number of times, so nothing it touches is known
+                let mut body_defs = Defs { exprs: HashMap::new(), lost: true, mem_dirty: true };
+                if guarded_succ(body, &mut body_defs, guard.clone(), out).is_none() {
+                    return None;
+                }
+                *defs = body_defs;
+            }
+            IfThenElse { ref cond, ref then_clause, ref else_clause } => {
+                let cond = defs.subst(cond);
+                let mut then_defs = defs.clone();
+                let mut else_defs = defs.clone();
+                // An arm the guard rules out is never taken
+                let then_fall = guard_with(&guard, &cond, true)
+                    .and_then(|g| guarded_succ(then_clause, &mut then_defs, g, out));
+                let else_fall = guard_with(&guard, &cond, false)
+                    .and_then(|g| guarded_succ(else_clause, &mut else_defs, g, out));
+                *defs = then_defs.merge(&else_defs);
+                guard = match (then_fall, else_fall) {
+                    (Some(_), Some(_)) => guard,
+                    (Some(g), None) | (None, Some(g)) => g,
+                    (None, None) => return None,
+                };
+            }
+            _ => defs.step(stmt),
         }
-        &IfThenElse {
-            cond: _,
-            ref then_clause,
-            ref else_clause,
-        } => {
-            let (mut then_tgts, then_fall) = stmt_succ(&then_clause);
-            let (mut else_tgts, else_fall) = stmt_succ(&else_clause);
-            let fall = then_fall || else_fall;
-            let mut tgt_res = Vec::new();
-            tgt_res.append(&mut then_tgts);
-            tgt_res.append(&mut else_tgts);
-            if fall {
-                let (mut tgts2, fall2) = stmt_succ(&stmts[1..]);
-                tgt_res.append(&mut tgts2);
-                (tgt_res, fall2)
+    }
+    Some(guard)
+}
+
+/// Successors of a block, each with the condition on the block's entry state for taking it.
+pub fn successors((sema, fall_addr): (&Sema, &BitVector)) -> Vec<(BitVector, Cond)> {
+    let mut out = Vec::new();
+    if let Some(guard) = guarded_succ(&sema.stmts, &mut Defs::default(), Cond::new(), &mut out) {
+        out.push((fall_addr.clone(), guard));
+    }
+    out
+}
+
+// Whether writing bytes bytes at `at` may change what atom reads
+fn clobbers(at: &HVar, bytes: u64, atom: &Atom) -> bool {
+    if atom.loc.inner != at.inner || atom.loc.offset.is_none() {
+        return false;
+    }
+    match (atom.loc.offset.as_ref().and_then(|o| o.to_u64()), at.offset.as_ref().and_then(|o| o.to_u64())) {
+        (Some(a), Some(w)) => {
+            let (a, w) = (a as i64, w as i64);
+            a < w.wrapping_add(bytes as i64) && w < a.wrapping_add((atom.bits as i64 + 7) / 8)
+        }
+        _ => true,
+    }
+}
+
+// Drops atoms about anything stmts write. Over a call, the push of the return address and the
+// stack pointer change are undone by the return, so they are left out.
+fn kill_writes(cond: Cond, stmts: &[Statement], over_call: bool) -> Cond {
+    use bap::high::bil::Statement::*;
+    stmts.iter().fold(cond, |cond, stmt| match *stmt {
+        Move { lhs: ref reg, .. } if is_reg(reg) => {
+            if over_call && reg.name == "RSP" {
+                cond
             } else {
-                (tgt_res, fall)
+                cond.retain(|a| a.loc.inner != *reg)
             }
         }
-        _ => stmt_succ(&stmts[1..]),
+        Move { lhs: ref mem, rhs: Expression::Store { ref index, size, .. } } if is_mem(mem) => {
+            match promote_idx(index) {
+                Some(ref at) if over_call && at.inner.name == "RSP" && at.offset.as_ref().and_then(|o| o.to_u64()) == Some(0) => cond,
+                Some(at) => cond.retain(|a| !clobbers(&at, size as u64 / 8, a)),
+                None => cond.retain(|a| a.loc.offset.is_none()),
+            }
+        }
+        IfThenElse { ref then_clause, ref else_clause, .. } => {
+            kill_writes(kill_writes(cond, then_clause, over_call), else_clause, over_call)
+        }
+        While { ref body, .. } => kill_writes(cond, body, over_call),
+        _ => cond,
+    })
+}
+
+/// Conditions holding on entry to the next block, having taken an edge out of this one under
+/// edge. Nothing if the edge can't be taken.
+pub fn cond_step((conds, edge, sema): (&Cond, &Cond, &Sema)) -> Vec<Cond> {
+    conds.and_all(edge).map(|c| kill_writes(c, &sema.stmts, false)).into_iter().collect()
+}
+
+/// Conditions holding after a call block once the callee returns, keeping only what the callee
+/// must preserve.
+pub fn cond_call((conds, sema): (&Cond, &Sema)) -> Cond {
+    kill_writes(conds.clone(), &sema.stmts, true).retain(|a| callee_saved(&a.loc))
+}
+
+fn decode_insn(bap: &Bap, arch: Arch, bin: &[u8], addr: &BitVector) -> bap::basic::Result<Insn> {
+    let disas = BasicDisasm::new(bap, arch)?;
+    let code = disas.disasm(bin, addr.to_u64().unwrap())?;
+    let insn = code.insn();
+    let sema = insn.semantics();
+    Ok(Insn {
+        stmts: sema.iter().map(|bb| Statement::from_basic(&bb)).collect(),
+        len: code.len(),
+        disasm: insn.to_string(),
+        is_call: insn.is_call(),
+        is_ret: insn.is_return(),
+        may_jump: insn.may_affect_control_flow(),
+    })
+}
+
+fn lift_insn(cache: Option<&LiftCache>, arch: &Arch, bin: &[u8], addr: &BitVector) -> Option<Insn> {
+    let key = LiftCache::key(arch, bin, addr);
+    if let Some(hit) = cache.and_then(|c| c.get(&key)) {
+        return Some(hit);
+    }
+    BAP_CALLS.fetch_add(1, Ordering::Relaxed);
+    let insn = to_vec(Bap::with(|bap| decode_insn(&bap, *arch, bin, addr))).pop();
+    if let (Some(c), Some(insn)) = (cache, insn.as_ref()) {
+        c.put(&key, insn);
     }
+    insn
 }
 
-pub fn successors((sema, fall_addr): (&Sema, &BitVector)) -> Vec<BitVector> {
-    let (mut targets, fall) = stmt_succ(&sema.stmts);
-    if fall {
-        targets.push(fall_addr.clone());
+// The single instruction at addr, given the bytes from addr on, for the emulator, which keeps
+// its own table of what it has decoded
+pub fn lift_insn_at(arch: &Arch, bin: &[u8], addr: &BitVector) -> Option<Insn> {
+    lift_insn(None, arch, bin, addr)
+}
+
+// Fills bin with as much of the next 16 bytes as the segment has, zeroing the rest
+fn read_window(fd: &mut &File, bin: &mut [u8; 16], avail: usize) {
+    for b in bin.iter_mut() {
+        *b = 0;
     }
-    targets
+    fd.read_exact(&mut bin[..min(16, avail)]).unwrap();
 }
 
-pub fn lift_wrap(
-    (arch, addr, mut fd, start): (&Arch, &BitVector, &File, &u64),
-) -> Vec<(Sema, BitVector, String, bool, bool)> {
-    fd.seek(SeekFrom::Start(*start)).unwrap();
-    to_vec(Bap::with(move |bap| {
-        let mut bin: [u8; 16] = [0; 16];
-        let mut fd = fd;
-        let mut may_jump = false;
-        let mut stmts = Vec::new();
-        let mut is_call = false;
-        let mut is_ret = false;
-        let mut disasm = String::new();
-        let mut fall: BitVector = addr.clone();
-        let mut pre_read = 0;
-        let mut first = true;
-        let mut addr: BitVector = addr.clone();
-        while !may_jump {
-            fd.read_exact(&mut bin[pre_read..]).unwrap();
-            let disas = BasicDisasm::new(&bap, *arch)?;
-            let code = disas.disasm(&bin, addr.to_u64().unwrap())?;
-            let len = code.len();
-            let insn = code.insn();
-            let sema = insn.semantics();
-            if !first && (insn.is_call() || insn.is_return()) {
-                // We want to put calls + rets in their own BBs to make
-                // analysis rules a bit easier
+// The block at addr, if any of it decodes, and why it stopped short if it couldn't decode the
+// first instruction.
+fn lift_block(
+    cache: Option<&LiftCache>,
+    arch: &Arch,
+    addr: &BitVector,
+    mut fd: &File,
+    start: u64,
+    end: u64,
+) -> (Option<(Sema, BitVector, String, bool, bool)>, Option<String>) {
+    // Never read past the segment, whatever we were told
+    let end = min(end, fd.metadata().map(|m| m.len()).unwrap_or(end));
+    if start >= end {
+        return (None, Some(format!("{} is past the end of its segment", addr)));
+    }
+    let mut bin: [u8; 16] = [0; 16];
+    let mut may_jump = false;
+    let mut stmts = Vec::new();
+    let mut is_call = false;
+    let mut is_ret = false;
+    let mut disasm = String::new();
+    let mut fall: BitVector = addr.clone();
+    let mut pos = start;
+    let mut first = true;
+    let mut addr: BitVector = addr.clone();
+    while !may_jump {
+        let avail = (end - pos) as usize;
+        if avail == 0 {
+            // Ran off the end of the segment without a jump; fall points past the end and
+            // lifting there will say so
+            break;
+        }
+        fd.seek(SeekFrom::Start(pos)).unwrap();
+        read_window(&mut fd, &mut bin, avail);
+        let insn = match lift_insn(cache, arch, &bin, &addr) {
+            Some(ref insn) if insn.len > avail => {
+                if first {
+                    return (None, Some(format!("instruction at {} runs past the end of its segment", addr)));
+                }
                 break;
             }
-            first = false;
-            stmts.extend(sema.iter().map(|bb| Statement::from_basic(&bb)));
-            write!(&mut disasm, "{}\n", insn.to_string()).unwrap();
-            is_call = insn.is_call();
-            is_ret = insn.is_return();
-            fall = addr.clone() + len;
-            may_jump = insn.may_affect_control_flow();
-            if !may_jump {
-                addr = fall.clone();
-                pre_read = 16 - len;
-                for i in 0..pre_read {
-                    bin[i] = bin[i + len];
+            Some(insn) => insn,
+            None => {
+                if first {
+                    let avail = min(16, avail);
+                    return (None, Some(format!("undecodable bytes at {}: {}", addr, ids::hex(&bin[..avail]))));
                 }
+                // Keep what we have; the bad address becomes live through fall and gets its
+                // own error there
+                break;
             }
+        };
+        if !first && (insn.is_call || insn.is_ret) {
+            // We want to put calls + rets in their own BBs to make
+            // analysis rules a bit easier
+            break;
         }
+        first = false;
+        let len = insn.len;
+        stmts.extend(insn.stmts.into_iter());
+        write!(&mut disasm, "{}\n", insn.disasm).unwrap();
+        is_call = insn.is_call;
+        is_ret = insn.is_ret;
+        fall = addr.clone() + len;
+        may_jump = insn.may_jump;
+        addr = fall.clone();
+        pos += len as u64;
+    }
 
-        disasm.pop();
-        Ok((Sema { stmts: stmts }, fall, disasm, is_call, is_ret))
-    }))
+    disasm.pop();
+    (Some((Sema { stmts: stmts }, fall, disasm, is_call, is_ret)), None)
+}
+
+// Lifts the block at a live address, noting in errors why it couldn't if it couldn't. Holmes
+// doesn't allow multiple heads yet, so the reasons go out through errors rather than as a
+// second rule decoding everything again.
+pub fn lift_wrap(
+    cache: Option<LiftCache>,
+    errors: LiftErrors,
+) -> Box<Fn((&String, &Arch, &BitVector, &File, &u64, &u64)) -> Vec<(Sema, BitVector, String, bool, bool)>> {
+    Box::new(move |(name, arch, addr, fd, start, end)| {
+        let (block, err) = lift_block(cache.as_ref(), arch, addr, fd, *start, *end);
+        if let Some(reason) = err {
+            errors.push(name, addr, reason);
+        }
+        block.into_iter().collect()
+    })
+}
+
+// Decodes every byte offset of a segment exactly once, one instruction each, plus a row for the
+// segment's end so blocks can tell they ran into it. Which of these overlapping instructions are
+// real, and how they chain into blocks, is left to liveness.
+pub fn shingle_wrap(
+    cache: Option<LiftCache>,
+) -> Box<Fn((&Arch, &File, &BitVector)) -> Vec<(BitVector, bool, Sema, BitVector, String, bool, bool, bool)>> {
+    Box::new(move |(arch, fd, seg_start)| shingle_segment(cache.as_ref(), arch, fd, seg_start))
+}
+
+fn shingle_segment(
+    cache: Option<&LiftCache>,
+    arch: &Arch,
+    mut fd: &File,
+    seg_start: &BitVector,
+) -> Vec<(BitVector, bool, Sema, BitVector, String, bool, bool, bool)> {
+    let mut data = Vec::new();
+    fd.seek(SeekFrom::Start(0)).unwrap();
+    fd.read_to_end(&mut data).unwrap();
+    let gap = |addr: BitVector| (addr.clone(), false, Sema { stmts: Vec::new() }, addr, String::new(), false, false, true);
+    let mut out: Vec<_> = (0..data.len())
+        .map(|off| {
+            let addr = seg_start.clone() + off;
+            let avail = min(16, data.len() - off);
+            let mut bin: [u8; 16] = [0; 16];
+            bin[..avail].copy_from_slice(&data[off..off + avail]);
+            match lift_insn(cache, arch, &bin, &addr) {
+                // Anything that needed the padding isn't really there
+                Some(ref insn) if insn.len > avail => gap(addr),
+                Some(insn) => {
+                    let fall = addr.clone() + insn.len;
+                    (addr, true, Sema { stmts: insn.stmts }, fall, insn.disasm, insn.is_call, insn.is_ret, insn.may_jump)
+                }
+                None => gap(addr),
+            }
+        })
+        .collect();
+    out.push(gap(seg_start.clone() + data.len()));
+    out
+}
+
+// The block so far with one more instruction on the end, for building blocks out of shingles
+pub fn append_insn((sema, disasm, insn, insn_disasm): (&Sema, &String, &Sema, &String)) -> Vec<(Sema, String)> {
+    let mut stmts = sema.stmts.clone();
+    stmts.extend(insn.stmts.iter().cloned());
+    vec![(Sema { stmts: stmts }, format!("{}\n{}", disasm, insn_disasm))]
 }
 
 fn to_vec<T>(r: bap::basic::Result<T>) -> Vec<T> {
@@ -487,25 +987,8 @@ pub fn str_extract(
     }
 }
 
-// TODO: holmes doesn't allow multiple heads yet, so we lift twice to get the disasm
-pub fn disas_wrap((arch, addr, mut fd, start): (&Arch, &BitVector, &File, &u64)) -> Vec<String> {
-
-    let mut bin: [u8; 16] = [0; 16];
-    fd.seek(SeekFrom::Start(*start)).unwrap();
-    fd.read_exact(&mut bin).unwrap();
-
-    to_vec(Bap::with(|bap| {
-        let disas = BasicDisasm::new(&bap, *arch)?;
-        let out = {
-            let code = disas.disasm(&bin, addr.to_u64().unwrap())?;
-            code.insn().to_string()
-        };
-        Ok(out)
-    }))
-}
-
 pub fn succ_wrap_upper((sema, fall_addr): (&Sema, &BitVector)) -> UpperBVSet {
-    let bvs = successors((sema, fall_addr));
+    let bvs: Vec<BitVector> = successors((sema, fall_addr)).into_iter().map(|(tgt, _)| tgt).collect();
     // TODO allow empty vec for cases where program will actually terminate
     if bvs.len() == 0 {
         UpperBVSet::Top
@@ -514,54 +997,18 @@ pub fn succ_wrap_upper((sema, fall_addr): (&Sema, &BitVector)) -> UpperBVSet {
     }
 }
 
-pub fn sym_wrap(mut fd: &File) -> Vec<(String, BitVector, BitVector)> {
-    let mut b = Vec::new();
-    fd.seek(SeekFrom::Start(0)).unwrap();
-    fd.read_to_end(&mut b).unwrap();
-    Bap::with(|bap| {
-        let image = get_image!(bap, b);
-        let out = {
-            let syms = image.symbols();
-            let out = syms.iter()
-                .map(|x| {
-                    (
-                        x.name(),
-                        BitVector::from_basic(&x.memory().min_addr()),
-                        BitVector::from_basic(&x.memory().max_addr()),
-                    )
-                })
-                .collect();
-            out
-        };
-        out
-    })
-}
-
-pub fn get_arch_val(mut fd: &File) -> Vec<Arch> {
-    let mut b = Vec::new();
-    fd.seek(SeekFrom::Start(0)).unwrap();
-    fd.read_to_end(&mut b).unwrap();
-    Bap::with(|bap| {
-        let image = get_image!(bap, b);
-        vec![image.arch().unwrap()]
-    })
-}
-
 use std::process::Command;
 use num::{BigUint, FromPrimitive};
 // TODO - get rid of objdump
-pub fn get_pads(mut fd: &File) -> Vec<(String, BitVector)> {
+fn get_pads(buf: &[u8]) -> Vec<(String, BitVector)> {
     use mktemp::Temp;
     use std::io::prelude::*;
-    let mut buf = Vec::new();
-    fd.seek(SeekFrom::Start(0)).unwrap();
-    fd.read_to_end(&mut buf).unwrap();
     let elf_temp = Temp::new_file().unwrap();
     let elf_path_buf = elf_temp.to_path_buf();
     let elf_path = elf_path_buf.to_str().unwrap();
     {
         let mut elf_file = File::create(elf_path).unwrap();
-        elf_file.write_all(&buf).unwrap();
+        elf_file.write_all(buf).unwrap();
     }
     let out: String = String::from_utf8(
         Command::new("bash")
@@ -584,17 +1031,18 @@ pub fn get_pads(mut fd: &File) -> Vec<(String, BitVector)> {
         .collect()
 }
 
-fn hv_match(bad: &Vec<HVar>, e: &Expression) -> bool {
+fn hv_match(bad: &Vec<HVar>, e: &Expression, env: &vsa::Env) -> bool {
     match *e {
         Expression::Var(ref v) => {
             bad.contains(&HVar {
                 inner: v.clone(),
                 offset: None,
+                array: None,
             })
         }
         Expression::Load { index: ref idx, .. } => {
-            match promote_idx(idx) {
-                Some(hv) => bad.contains(&hv),
+            match promote_idx(idx).or_else(|| promote_array(idx, env)) {
+                Some(hv) => bad.iter().any(|b| mem_alias(b, &hv)),
                 None => false,
             }
         }
@@ -633,6 +1081,7 @@ fn promote_idx(idx: &Expression) -> Option<HVar> {
             Some(HVar {
                 inner: v.clone(),
                 offset: Some(BitVector::new_unsigned(BigUint::from_u32(0).unwrap(), 64)),
+                array: None,
             })
         }
         Expression::BinOp {
@@ -647,6 +1096,7 @@ fn promote_idx(idx: &Expression) -> Option<HVar> {
                             Some(HVar {
                                 inner: v.clone(),
                                 offset: Some(bv.clone()),
+                                array: None,
                             })
                         }
                         _ => None,
@@ -658,6 +1108,7 @@ fn promote_idx(idx: &Expression) -> Option<HVar> {
                             Some(HVar {
                                 inner: v.clone(),
                                 offset: Some(bv.clone()),
+                                array: None,
                             })
                         }
                         _ => None,
@@ -670,7 +1121,47 @@ fn promote_idx(idx: &Expression) -> Option<HVar> {
     }
 }
 
-fn proc_stmt(bad: Vec<HVar>, stmt: &Statement) -> Vec<HVar> {
+// For indices promote_idx can't follow, such as [rax + rcx*8]: the offsets from the base the
+// access may reach, as far as the value sets of the block so far can tell. Absolute addresses
+// are offsets from var::globals().
+pub fn promote_array(idx: &Expression, env: &vsa::Env) -> Option<HVar> {
+    env.region(idx).map(|region| {
+        let inner = region.base.unwrap_or_else(var::globals);
+        match region.offsets.as_constant() {
+            Some(off) => HVar {
+                inner: inner,
+                offset: Some(BitVector::new_unsigned(BigUint::from_u64(off as u64).unwrap(), 64)),
+                array: None,
+            },
+            None => HVar {
+                inner: inner,
+                offset: None,
+                array: Some(region.offsets),
+            },
+        }
+    })
+}
+
+fn mem_offsets(hv: &HVar) -> Option<vsa::StridedInterval> {
+    match (&hv.offset, &hv.array) {
+        (&Some(ref off), _) => off.to_u64().map(|n| vsa::StridedInterval::constant(n as i64)),
+        (&None, &Some(ref array)) => Some(array.clone()),
+        (&None, &None) => None,
+    }
+}
+
+// Whether reading accessed may read what was stored to tracked
+fn mem_alias(tracked: &HVar, accessed: &HVar) -> bool {
+    if *tracked == *accessed {
+        return true;
+    }
+    match (mem_offsets(tracked), mem_offsets(accessed)) {
+        (Some(t), Some(a)) => tracked.inner == accessed.inner && t.overlaps(&a),
+        _ => false,
+    }
+}
+
+fn proc_stmt(bad: Vec<HVar>, stmt: &Statement, env: &vsa::Env) -> Vec<HVar> {
     use bap::high::bil::Statement::*;
     match *stmt {
         // Register update
@@ -678,12 +1169,13 @@ fn proc_stmt(bad: Vec<HVar>, stmt: &Statement) -> Vec<HVar> {
             lhs: ref reg,
             rhs: ref e,
         } if is_reg(&reg) => {
-            if hv_match(&bad, &e) {
+            if hv_match(&bad, &e, env) {
                 add_hvar(
                     bad,
                     HVar {
                         inner: reg.clone(),
                         offset: None,
+                        array: None,
                     },
                 )
             } else {
@@ -692,6 +1184,7 @@ fn proc_stmt(bad: Vec<HVar>, stmt: &Statement) -> Vec<HVar> {
                     HVar {
                         inner: reg.clone(),
                         offset: None,
+                        array: None,
                     },
                 )
             }
@@ -709,10 +1202,20 @@ fn proc_stmt(bad: Vec<HVar>, stmt: &Statement) -> Vec<HVar> {
                     endian: _,
                     size: _,
                 } => {
-                    if hv_match(&bad, &val) {
-                        promote_idx(idx).map_or(bad.clone(), |hidx| add_hvar(bad, hidx))
-                    } else {
-                        promote_idx(idx).map_or(bad.clone(), |hidx| rem_hvar(bad, hidx))
+                    let tainted = hv_match(&bad, &val, env);
+                    match promote_idx(idx).or_else(|| promote_array(idx, env)) {
+                        Some(hidx) => {
+                            if tainted {
+                                add_hvar(bad, hidx)
+                            } else if hidx.array.is_none() {
+                                rem_hvar(bad, hidx)
+                            } else {
+                                // Only one element is overwritten, so the rest may still hold
+                                // a tracked pointer
+                                bad
+                            }
+                        }
+                        None => bad,
                     }
                 }
                 _ => bad,
@@ -722,31 +1225,130 @@ fn proc_stmt(bad: Vec<HVar>, stmt: &Statement) -> Vec<HVar> {
     }
 }
 
+// The cells a store writes var's value into, for every store into a field of base (an alias of
+// the object allocated at obj_site)
+pub fn store_cells(
+    (sema, var, base, obj_bin, obj_site): (&Sema, &HVar, &HVar, &String, &BitVector),
+) -> Vec<HVar> {
+    let mut vals = vec![var.clone()];
+    let mut bases = vec![base.clone()];
+    let mut cells = Vec::new();
+    let mut env = vsa::Env::new();
+    for stmt in sema.stmts.iter() {
+        if let Statement::Move { lhs: ref mem, rhs: Expression::Store { index: ref idx, value: ref val, .. } } = *stmt {
+            if is_mem(mem) && hv_match(&vals, val, &env) {
+                if let Some(HVar { inner, offset: Some(field), array: None }) = promote_idx(idx) {
+                    if bases.contains(&HVar { inner: inner, offset: None, array: None }) {
+                        cells = add_hvar(cells, var::heap_cell(obj_bin, obj_site, field));
+                    }
+                }
+            }
+        }
+        vals = proc_stmt(vals, stmt, &env);
+        bases = proc_stmt(bases, stmt, &env);
+        env.step(stmt);
+    }
+    cells
+}
+
+pub fn cell_in((cell, obj_bin, obj_site): (&HVar, &String, &BitVector)) -> bool {
+    match cell.offset {
+        Some(ref field) => *cell == var::heap_cell(obj_bin, obj_site, field.clone()),
+        None => false,
+    }
+}
+
+pub fn kept_cell((obj_bin, obj_site): (&String, &BitVector)) -> HVar {
+    var::kept_cell(obj_bin, obj_site)
+}
+
+// Follows a chain of field loads starting from base, e.g. base->f0->f1, through the block.
+// Returns whatever holds the end of the chain after the block, and whether it was dereferenced
+// along the way.
+fn heap_walk(sema: &Sema, base: &HVar, fields: &[BitVector]) -> (Vec<HVar>, bool) {
+    let mut levels = vec![vec![base.clone()]];
+    levels.extend(fields.iter().map(|_| Vec::new()));
+    let mut deref = false;
+    let mut env = vsa::Env::new();
+    for stmt in sema.stmts.iter() {
+        if levels[fields.len()].iter().any(|v| deref_var_step(stmt, v)) {
+            deref = true;
+        }
+        // Work out what is loaded before the load itself can clobber the base
+        let mut loaded = Vec::new();
+        if let Statement::Move { lhs: ref reg, rhs: Expression::Load { index: ref idx, .. } } = *stmt {
+            if let (true, Some(HVar { inner, offset: Some(off), array: None })) = (is_reg(reg), promote_idx(idx)) {
+                let base = HVar { inner: inner, offset: None, array: None };
+                for (level, field) in fields.iter().enumerate() {
+                    if *field == off && levels[level].contains(&base) {
+                        loaded.push(level + 1);
+                    }
+                }
+            }
+        }
+        levels = levels.into_iter().map(|vars| proc_stmt(vars, stmt, &env)).collect();
+        env.step(stmt);
+        if let Statement::Move { lhs: ref reg, .. } = *stmt {
+            for level in loaded {
+                let v = HVar { inner: reg.clone(), offset: None, array: None };
+                levels[level] = add_hvar(levels[level].clone(), v);
+            }
+        }
+    }
+    let out = levels.pop().unwrap().into_iter().filter(|v| v.not_temp()).collect();
+    (out, deref)
+}
+
+fn cell_field(cell: &HVar) -> BitVector {
+    cell.offset.clone().unwrap()
+}
+
+// Registers holding the contents of cell after the block, having loaded it out of base
+pub fn load_cells((sema, cell, base): (&Sema, &HVar, &HVar)) -> Vec<HVar> {
+    heap_walk(sema, base, &[cell_field(cell)]).0
+}
+
+// As load_cells, but where the object holding cell is itself loaded out of mid, a cell of base
+pub fn load_cells_via((sema, cell, mid, base): (&Sema, &HVar, &HVar, &HVar)) -> Vec<HVar> {
+    heap_walk(sema, base, &[cell_field(mid), cell_field(cell)]).0
+}
+
+pub fn cell_derefs((sema, cell, base): (&Sema, &HVar, &HVar)) -> bool {
+    heap_walk(sema, base, &[cell_field(cell)]).1
+}
+
+pub fn cell_derefs_via((sema, cell, mid, base): (&Sema, &HVar, &HVar, &HVar)) -> bool {
+    heap_walk(sema, base, &[cell_field(mid), cell_field(cell)]).1
+}
+
 pub fn xfer_taint((sema, var): (&Sema, &HVar)) -> Vec<HVar> {
-    sema.stmts
-        .iter()
-        .fold(vec![var.clone()], proc_stmt)
-        .into_iter()
-        .filter(|v| v.not_temp())
-        .collect()
+    let mut env = vsa::Env::new();
+    let mut vars = vec![var.clone()];
+    for stmt in sema.stmts.iter() {
+        vars = proc_stmt(vars, stmt, &env);
+        env.step(stmt);
+    }
+    vars.into_iter().filter(|v| v.not_temp()).collect()
 }
 
 pub fn deref_var((sema, var): (&Sema, &HVar)) -> bool {
     let mut vars = vec![var.clone()];
+    let mut env = vsa::Env::new();
     for stmt in sema.stmts.iter() {
         for var in vars.iter() {
             if deref_var_step(stmt, var) {
                 return true;
             }
         }
-        vars = proc_stmt(vars, stmt);
+        vars = proc_stmt(vars, stmt, &env);
+        env.step(stmt);
     }
     return false;
 }
 
 fn check_idx(idx: &Expression, var: &HVar) -> bool {
     let res = match *idx {
-        Expression::Var(ref v) => (var.offset == None) && (var.inner == *v),
+        Expression::Var(ref v) => (var.offset == None) && (var.array == None) && (var.inner == *v),
         Expression::BinOp {
             op: _,
             ref lhs,
@@ -0,0 +1,140 @@
+use bap::basic::{BinOp, UnOp, Cast, BitSize};
+use bap::high::bitvector::BitVector;
+use num::{BigInt, BigUint, FromPrimitive, Integer, One, ToPrimitive, Zero};
+use num::bigint::Sign;
+
+// BIL arithmetic over concrete values. Everything is modular in the width of the operands, and
+// signed operations read the top bit as the sign, as on x86.
+
+pub fn width(v: &BitVector) -> usize {
+    v.to_bitvec().len()
+}
+
+fn modulus(width: usize) -> BigUint {
+    BigUint::one() << width
+}
+
+pub fn from_unsigned(n: BigUint, width: usize) -> BitVector {
+    BitVector::new_unsigned(n % modulus(width), width)
+}
+
+pub fn from_signed(n: BigInt, width: usize) -> BitVector {
+    let m = BigInt::from_biguint(Sign::Plus, modulus(width));
+    let n = n.mod_floor(&m);
+    from_unsigned(n.to_biguint().unwrap(), width)
+}
+
+pub fn signed(v: &BitVector) -> BigInt {
+    let w = width(v);
+    let n = BigInt::from_biguint(Sign::Plus, v.unum());
+    if w > 0 && v.unum() >= (BigUint::one() << (w - 1)) {
+        n - BigInt::from_biguint(Sign::Plus, modulus(w))
+    } else {
+        n
+    }
+}
+
+fn from_bool(b: bool) -> BitVector {
+    from_unsigned(if b { BigUint::one() } else { BigUint::zero() }, 1)
+}
+
+fn shift_amount(rhs: &BitVector, width: usize) -> usize {
+    // Anything at least the width shifts everything out
+    let n = rhs.unum();
+    if n >= BigUint::from_usize(width).unwrap() { width } else { n.to_usize().unwrap() }
+}
+
+/// Evaluates a binary operator. Division by zero has no value.
+pub fn binop(op: BinOp, lhs: &BitVector, rhs: &BitVector) -> Option<BitVector> {
+    use bap::basic::BinOp::*;
+    let w = width(lhs);
+    let (l, r) = (lhs.unum(), rhs.unum());
+    Some(match op {
+        Add => from_unsigned(l + r, w),
+        Sub => from_signed(BigInt::from_biguint(Sign::Plus, l) - BigInt::from_biguint(Sign::Plus, r), w),
+        Mul => from_unsigned(l * r, w),
+        Div => {
+            if r.is_zero() {
+                return None;
+            }
+            from_unsigned(l / r, w)
+        }
+        SDiv => {
+            if r.is_zero() {
+                return None;
+            }
+            // Truncates towards zero, like idiv
+            from_signed(signed(lhs) / signed(rhs), w)
+        }
+        Mod => {
+            if r.is_zero() {
+                return None;
+            }
+            from_unsigned(l % r, w)
+        }
+        SMod => {
+            if r.is_zero() {
+                return None;
+            }
+            // Takes the sign of the dividend, like idiv
+            from_signed(signed(lhs) % signed(rhs), w)
+        }
+        LShift => from_unsigned(l << shift_amount(rhs, w), w),
+        RShift => from_unsigned(l >> shift_amount(rhs, w), w),
+        ARShift => {
+            let s = signed(lhs);
+            let n = shift_amount(rhs, w);
+            // Floor division by 2^n, so negative values fill with ones
+            from_signed(s.div_floor(&BigInt::from_biguint(Sign::Plus, modulus(n))), w)
+        }
+        And => from_unsigned(l & r, w),
+        Or => from_unsigned(l | r, w),
+        Xor => from_unsigned(l ^ r, w),
+        Eq => from_bool(l == r),
+        Neq => from_bool(l != r),
+        Lt => from_bool(l < r),
+        Le => from_bool(l <= r),
+        SLt => from_bool(signed(lhs) < signed(rhs)),
+        SLe => from_bool(signed(lhs) <= signed(rhs)),
+    })
+}
+
+pub fn unop(op: UnOp, v: &BitVector) -> BitVector {
+    let w = width(v);
+    match op {
+        UnOp::Neg => from_signed(-signed(v), w),
+        UnOp::Not => from_unsigned(modulus(w) - BigUint::one() - v.unum(), w),
+    }
+}
+
+pub fn cast(kind: Cast, bs: BitSize, v: &BitVector) -> BitVector {
+    let to = bs as usize;
+    let w = width(v);
+    match kind {
+        Cast::Low => from_unsigned(v.unum(), to),
+        Cast::Unsigned => from_unsigned(v.unum(), to),
+        Cast::Signed => from_signed(signed(v), to),
+        Cast::High => {
+            if to >= w {
+                from_unsigned(v.unum(), to)
+            } else {
+                from_unsigned(v.unum() >> (w - to), to)
+            }
+        }
+    }
+}
+
+// Bits low..=high of v
+pub fn extract(low: usize, high: usize, v: &BitVector) -> BitVector {
+    from_unsigned(v.unum() >> low, high + 1 - low)
+}
+
+// high's bits above low's
+pub fn concat(high: &BitVector, low: &BitVector) -> BitVector {
+    let w = width(low);
+    from_unsigned((high.unum() << w) | low.unum(), width(high) + w)
+}
+
+pub fn is_true(v: &BitVector) -> bool {
+    !v.unum().is_zero()
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::path::Path;
+use symbols;
+
+// Reading AddressSanitizer use-after-free reports and lining them up with our findings. Reports
+// are expected unsymbolized (ASAN_OPTIONS=symbolize=0), so every frame is module+offset, and the
+// offsets are the same virtual addresses we lift at. ASan moves the report call out of line, so
+// its PCs land near our blocks rather than in them, and findings are matched by function.
+
+/// One stack frame of a report
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub module: String,
+    pub offset: u64,
+}
+
+/// The three stacks of a heap-use-after-free report, innermost frame first
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+    pub alloc: Vec<Frame>,
+    pub free: Vec<Frame>,
+    pub use_: Vec<Frame>,
+}
+
+fn frame(line: &str) -> Option<Frame> {
+    let line = line.trim();
+    if !line.starts_with('#') {
+        return None;
+    }
+    let open = match line.rfind('(') {
+        Some(open) => open,
+        None => return None,
+    };
+    let inner = line[open + 1..].trim_right_matches(')');
+    let plus = match inner.rfind("+0x") {
+        Some(plus) => plus,
+        None => return None,
+    };
+    u64::from_str_radix(&inner[plus + 3..], 16).ok().map(|offset| {
+        Frame {
+            module: inner[..plus].to_string(),
+            offset: offset,
+        }
+    })
+}
+
+/// Every heap-use-after-free report in a program's stderr
+pub fn parse(stderr: &str) -> Vec<Report> {
+    let mut reports = Vec::new();
+    let mut cur: Option<Report> = None;
+    // Which stack the frames being read belong to: 0 use, 1 free, 2 alloc
+    let mut section = 0;
+    for line in stderr.lines() {
+        if line.contains("ERROR: AddressSanitizer: heap-use-after-free") {
+            reports.extend(cur.take());
+            cur = Some(Report::default());
+            section = 0;
+        } else if line.contains("ERROR: AddressSanitizer") {
+            reports.extend(cur.take());
+        } else if let Some(ref mut report) = cur {
+            if line.starts_with("freed by thread") {
+                section = 1;
+            } else if line.starts_with("previously allocated by thread") {
+                section = 2;
+            } else if let Some(f) = frame(line) {
+                match section {
+                    0 => report.use_.push(f),
+                    1 => report.free.push(f),
+                    _ => report.alloc.push(f),
+                }
+            }
+        }
+    }
+    reports.extend(cur);
+    reports
+}
+
+/// Innermost frame in the given binary, skipping ASan's own interceptors and libc
+pub fn site(frames: &[Frame], binary: &Path) -> Option<u64> {
+    frames.iter().find(|f| Path::new(&f.module).file_name() == binary.file_name()).map(|f| f.offset)
+}
+
+/// An address as function plus offset
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loc {
+    pub symbol: String,
+    pub offset: u64,
+}
+
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}+{:#x}", self.symbol, self.offset)
+    }
+}
+
+impl Loc {
+    /// addr against a binary's symbols, as symbols::load gives them
+    pub fn at(symbols: &[(String, u64, u64)], addr: u64) -> Option<Loc> {
+        symbols::locate(symbols, addr).map(|(name, offset)| {
+            Loc {
+                symbol: name.to_string(),
+                offset: offset,
+            }
+        })
+    }
+}
+
+/// Allocation, free and use of one use-after-free, from either side. We don't record which call
+/// frees, so findings have no free.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sites {
+    pub alloc: Loc,
+    pub free: Option<Loc>,
+    pub use_: Loc,
+}
+
+impl fmt::Display for Sites {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "alloc {}", self.alloc)?;
+        if let Some(ref free) = self.free {
+            write!(f, " free {}", free)?;
+        }
+        write!(f, " use {}", self.use_)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Comparison {
+    /// ASan report and the finding matched to it
+    pub agreed: Vec<(Sites, Sites)>,
+    /// ASan reports with no finding
+    pub missed: Vec<Sites>,
+    /// Findings with no ASan report. ASan stops at the first error of a run, so some of these
+    /// may be real ones it never got to.
+    pub extra: Vec<Sites>,
+}
+
+/// How compare decides a finding is the one ASan reported, for output that states it
+pub const MATCHING: &'static str = "findings are matched to reports by allocating and using function; \
+the free is not compared, as findings don't record which call freed";
+
+fn distance(a: &Loc, b: &Loc) -> u64 {
+    if a.offset > b.offset { a.offset - b.offset } else { b.offset - a.offset }
+}
+
+/// Pairs each report with a finding not already paired that allocates and uses in the same
+/// functions, preferring the one whose offsets are closest. Offsets only break ties, as ASan's
+/// PCs are near our blocks rather than in them, and the free is not compared at all, as findings
+/// have none; see MATCHING.
+pub fn compare(reports: &[Sites], findings: &[Sites]) -> Comparison {
+    let mut out = Comparison::default();
+    let mut used = vec![false; findings.len()];
+    for report in reports.iter() {
+        let best = findings.iter()
+            .enumerate()
+            .filter(|&(i, f)| !used[i] && f.alloc.symbol == report.alloc.symbol && f.use_.symbol == report.use_.symbol)
+            .min_by_key(|&(_, f)| distance(&f.alloc, &report.alloc) + distance(&f.use_, &report.use_));
+        match best {
+            Some((i, f)) => {
+                used[i] = true;
+                out.agreed.push((report.clone(), f.clone()));
+            }
+            None => out.missed.push(report.clone()),
+        }
+    }
+    for (i, f) in findings.iter().enumerate() {
+        if !used[i] {
+            out.extra.push(f.clone());
+        }
+    }
+    out
+}
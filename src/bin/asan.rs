@@ -0,0 +1,151 @@
+#[macro_use]
+extern crate holmes;
+extern crate getopts;
+extern crate env_logger;
+extern crate num;
+extern crate bap;
+use holmes::simple::*;
+use holmes::pg::dyn::Value;
+
+extern crate tiamat;
+use tiamat::asan::{self, Loc, Sites};
+use tiamat::db;
+
+use bap::high::bitvector::BitVector;
+use getopts::Options;
+use num::ToPrimitive;
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Builds the ASan variants of the samples, runs them, and checks what ASan reports against what
+// the analysis finds on the very same binaries.
+fn main() {
+    env_logger::init().unwrap();
+    let db_default_addr = db::default_addr();
+    let mut opts = Options::new();
+    opts.optopt("s", "samples", "directory of samples with an asan make target", "samples/use_after_free");
+    opts.optopt(
+        "d",
+        "database",
+        "database connection string",
+        &db_default_addr,
+    );
+    opts.optmulti(
+        "a",
+        "arg",
+        "also run each sample with this as its only argument, on top of a run with none",
+        "ARG",
+    );
+    opts.optflag("h", "help", "print usage and exit");
+    let mut args = env::args();
+    let prog_name = args.next().unwrap();
+    let matches = opts.parse(args).unwrap_or_else(|x| panic!(x));
+    if matches.opt_present("h") {
+        let brief = format!("{} -s SAMPLEDIR -d DBSTRING", prog_name);
+        println!("{}", opts.usage(&brief));
+        return;
+    }
+    let db_addr = matches.opt_str("d").unwrap_or(db_default_addr);
+    let dir = PathBuf::from(matches.opt_str("s").unwrap_or("samples/use_after_free".to_string()));
+    let status = Command::new("make").arg("-C").arg(&dir).arg("asan").status().unwrap();
+    if !status.success() {
+        panic!("Building ASan variants in {} failed", dir.display());
+    }
+    let mut bins: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "asan"))
+        .collect();
+    bins.sort();
+
+    let mut runs: Vec<Vec<String>> = vec![vec![]];
+    runs.extend(matches.opt_strs("a").into_iter().map(|arg| vec![arg]));
+    let mut reports: HashMap<PathBuf, Vec<asan::Report>> = HashMap::new();
+    for bin in bins.iter() {
+        for run in runs.iter() {
+            let out = Command::new(bin)
+                .args(run)
+                .env("ASAN_OPTIONS", "symbolize=0:detect_leaks=0")
+                .output()
+                .unwrap();
+            let found = reports.entry(bin.clone()).or_insert_with(Vec::new);
+            for report in asan::parse(&String::from_utf8_lossy(&out.stderr)) {
+                if !found.contains(&report) {
+                    found.push(report);
+                }
+            }
+        }
+    }
+
+    let mut core = Core::new().unwrap();
+    let db = db::connect(&db_addr).unwrap_or_else(|e| panic!(e));
+    let mut holmes = Engine::new(db, core.handle());
+    let in_paths = bins.iter().map(|bin| bin.to_string_lossy().into_owned()).collect();
+    tiamat::Pipeline::new(in_paths).run(&mut holmes, &mut core).unwrap();
+
+    let to_u64 = |v: &Value| v.get().downcast_ref::<BitVector>().unwrap().to_u64().unwrap();
+    let as_str = |v: &Value| v.get().downcast_ref::<String>().unwrap().clone();
+    let mut ids = HashMap::new();
+    for row in query!(holmes, binary(id, path, [_], [_])).unwrap() {
+        ids.insert(PathBuf::from(as_str(&row[1])), as_str(&row[0]));
+    }
+    let symbols = tiamat::symbols::load(&mut holmes).unwrap();
+    let mut findings: HashMap<String, Vec<Sites>> = HashMap::new();
+    let mut uafs = query!(holmes, use_after_free(name, src, [_], sink_name, sink)).unwrap();
+    uafs.sort();
+    uafs.dedup();
+    for row in uafs {
+        let id = as_str(&row[0]);
+        if as_str(&row[2]) != id {
+            continue;
+        }
+        let syms = symbols.get(&id).cloned().unwrap_or_default();
+        if let (Some(alloc), Some(use_)) = (Loc::at(&syms, to_u64(&row[1])), Loc::at(&syms, to_u64(&row[3]))) {
+            let sites = Sites { alloc: alloc, free: None, use_: use_ };
+            let found = findings.entry(id).or_insert_with(Vec::new);
+            if !found.contains(&sites) {
+                found.push(sites);
+            }
+        }
+    }
+
+    println!("Note: {}", asan::MATCHING);
+    let (mut agreed, mut missed, mut extra, mut found) = (0, 0, 0, 0);
+    for bin in bins.iter() {
+        let id = ids.get(bin).cloned().unwrap_or_default();
+        let syms = symbols.get(&id).cloned().unwrap_or_default();
+        let at = |frames: &[asan::Frame]| asan::site(frames, bin).and_then(|pc| Loc::at(&syms, pc));
+        let expected: Vec<Sites> = reports[bin]
+            .iter()
+            .filter_map(|r| match (at(&r.alloc), at(&r.use_)) {
+                (Some(alloc), Some(use_)) => Some(Sites { alloc: alloc, free: at(&r.free), use_: use_ }),
+                _ => None,
+            })
+            .collect();
+        let ours = findings.get(&id).cloned().unwrap_or_default();
+        let cmp = asan::compare(&expected, &ours);
+        println!("{}: {} agreed, {} missed, {} extra", bin.display(), cmp.agreed.len(), cmp.missed.len(), cmp.extra.len());
+        for &(ref theirs, ref ours) in cmp.agreed.iter() {
+            println!("  agreed {} / {}", theirs, ours);
+        }
+        for sites in cmp.missed.iter() {
+            println!("  missed {}", sites);
+        }
+        for sites in cmp.extra.iter() {
+            println!("  extra {}", sites);
+        }
+        agreed += cmp.agreed.len();
+        missed += cmp.missed.len();
+        extra += cmp.extra.len();
+        found += ours.len();
+    }
+    println!("Agreed: {}\nMissed: {}\nExtra: {}", agreed, missed, extra);
+    if found > 0 {
+        println!("Precision: {:.2}", (found - extra) as f64 / found as f64);
+    }
+    if agreed + missed > 0 {
+        println!("Recall: {:.2}", agreed as f64 / (agreed + missed) as f64);
+    }
+}
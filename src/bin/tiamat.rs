@@ -0,0 +1,261 @@
+#[macro_use]
+extern crate holmes;
+extern crate getopts;
+extern crate env_logger;
+use holmes::simple::*;
+
+extern crate tiamat;
+use tiamat::budget::Limits;
+use tiamat::db;
+use tiamat::grade::{self, Checker, CHECKERS};
+use tiamat::signature::{self, Signature};
+use tiamat::store;
+use tiamat::suppress;
+use tiamat::truth;
+
+use getopts::Options;
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+fn usage(prog_name: &str) -> ! {
+    println!(
+        "Usage: {} COMMAND [OPTIONS]\n\nCommands:\n    grade    score the checkers against the Juliet test suite\n    diff     compare the findings of two runs, as saved by uaf --findings",
+        prog_name
+    );
+    process::exit(1);
+}
+
+fn main() {
+    env_logger::init().unwrap();
+    let mut args = env::args();
+    let prog_name = args.next().unwrap();
+    match args.next().as_ref().map(|cmd| cmd.as_str()) {
+        Some("grade") => grade(&prog_name, args.collect()),
+        Some("diff") => diff(&prog_name, args.collect()),
+        _ => usage(&prog_name),
+    }
+}
+
+// The binary a Juliet Makefile builds, from its TARGET line
+fn make_target(dir: &Path) -> Option<PathBuf> {
+    let mut text = String::new();
+    if File::open(dir.join("Makefile")).and_then(|mut fd| fd.read_to_string(&mut text)).is_err() {
+        return None;
+    }
+    text.lines()
+        .find(|line| line.starts_with("TARGET="))
+        .map(|line| dir.join(line["TARGET=".len()..].trim()))
+}
+
+// Testcase binaries for a CWE. Large CWEs are split into s01, s02, ... with a Makefile each.
+fn cwe_targets(juliet: &Path, cwe: &str) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for entry in std::fs::read_dir(juliet).unwrap() {
+        let path = entry.unwrap().path();
+        if path.file_name().map_or(false, |name| name.to_string_lossy().starts_with(&format!("{}_", cwe))) {
+            dirs.push(path.clone());
+            for sub in std::fs::read_dir(&path).unwrap() {
+                let sub = sub.unwrap().path();
+                if sub.is_dir() {
+                    dirs.push(sub);
+                }
+            }
+        }
+    }
+    dirs.sort();
+    dirs.iter().filter_map(|dir| make_target(dir)).collect()
+}
+
+// Testcase binaries of the given CWEs, built first if need be
+fn juliet_bins(juliet: &Path, cwes: &[&str], build: bool) -> Vec<String> {
+    let mut bins = Vec::new();
+    for cwe in cwes.iter() {
+        for target in cwe_targets(juliet, cwe) {
+            if !target.exists() && build {
+                let dir = target.parent().unwrap();
+                if !Command::new("make").arg("-C").arg(dir).status().unwrap().success() {
+                    eprintln!("Building {} failed, skipping it", dir.display());
+                }
+            }
+            if target.exists() {
+                bins.push(target.to_string_lossy().into_owned());
+            } else {
+                eprintln!("No binary at {}, skipping it", target.display());
+            }
+        }
+    }
+    bins
+}
+
+fn grade(prog_name: &str, args: Vec<String>) {
+    let db_default_addr = db::default_addr();
+    let mut opts = Options::new();
+    opts.optopt("j", "juliet", "Juliet testcases directory", "samples/Juliet/testcases");
+    opts.optopt(
+        "d",
+        "database",
+        "database connection string",
+        &db_default_addr,
+    );
+    opts.optmulti("", "checker", "only run this checker, by default all of them", "NAME");
+    opts.optmulti("", "cwe", "only grade this CWE, by default every one a checker covers", "CWE416");
+    opts.optmulti(
+        "",
+        "truth",
+        "grade the binaries a truth file names, next to it, against it rather than Juliet",
+        "FILE",
+    );
+    opts.optmulti("", "suppress", "leave out findings accepted in this suppression file", "FILE");
+    opts.optflag("", "no-build", "skip testcase binaries that aren't built rather than running make");
+    opts.optopt("t", "tracelen", "maximum length of confirmation trace to consider", "30");
+    opts.optopt("b", "baseline", "compare against a baseline, exiting with failure on any regression", "FILE");
+    opts.optopt("w", "write-baseline", "write the scores as a baseline for later runs", "FILE");
+    opts.optflag("h", "help", "print usage and exit");
+    let matches = opts.parse(args).unwrap_or_else(|x| panic!(x));
+    if matches.opt_present("h") {
+        let brief = format!("{} grade [-j JULIETDIR] [-b BASELINE]", prog_name);
+        println!("{}", opts.usage(&brief));
+        return;
+    }
+    let db_addr = matches.opt_str("d").unwrap_or(db_default_addr);
+    let juliet = PathBuf::from(matches.opt_str("j").unwrap_or("samples/Juliet/testcases".to_string()));
+    let mut limits = Limits::default();
+    if let Some(t) = matches.opt_str("t") {
+        limits.trace_len = t.parse::<usize>().unwrap();
+    }
+    let only_checkers = matches.opt_strs("checker");
+    let only_cwes = matches.opt_strs("cwe");
+    let checkers: Vec<&Checker> = CHECKERS
+        .iter()
+        .filter(|c| only_checkers.is_empty() || only_checkers.iter().any(|name| name == c.name))
+        .collect();
+    let mut cwes: Vec<&str> = Vec::new();
+    for checker in checkers.iter() {
+        for cwe in checker.cwes.iter() {
+            if (only_cwes.is_empty() || only_cwes.iter().any(|c| c == cwe)) && !cwes.contains(cwe) {
+                cwes.push(*cwe);
+            }
+        }
+    }
+
+    let mut annotations = Vec::new();
+    let mut bins = Vec::new();
+    for path in matches.opt_strs("truth") {
+        let anns = truth::parse(&path, &store::read_text(&path)).unwrap_or_else(|e| panic!(e));
+        let dir = Path::new(&path).parent().unwrap().to_path_buf();
+        for ann in anns.iter() {
+            let bin = dir.join(&ann.binary).to_string_lossy().into_owned();
+            if !bins.contains(&bin) {
+                bins.push(bin);
+            }
+        }
+        annotations.extend(anns);
+    }
+    if annotations.is_empty() {
+        bins = juliet_bins(&juliet, &cwes, !matches.opt_present("no-build"));
+    }
+
+    let mut suppressions = Vec::new();
+    for path in matches.opt_strs("suppress") {
+        suppressions.extend(suppress::parse(&path, &store::read_text(&path)).unwrap_or_else(|e| panic!(e)));
+    }
+
+    let mut core = Core::new().unwrap();
+    let db = db::connect(&db_addr).unwrap_or_else(|e| panic!(e));
+    let mut holmes = Engine::new(db, core.handle());
+    let report = tiamat::Pipeline::new(bins)
+        .limits(limits)
+        .truth(annotations.clone())
+        .suppressions(suppressions)
+        .run(&mut holmes, &mut core)
+        .unwrap();
+    print!("{}", report);
+    let (bad_hits, good_hits) = tiamat::graded(&mut holmes).unwrap();
+    let mut rows = Vec::new();
+    if !annotations.is_empty() {
+        let unmatched = tiamat::unmatched(&mut holmes).unwrap();
+        rows.extend(grade::score_truth(&annotations, &bad_hits, &good_hits, &unmatched).into_iter().filter(|row| {
+            checkers.iter().any(|c| c.name == row.checker)
+        }));
+    } else {
+        let funcs: Vec<String> = query!(holmes, entry([_], name, [_], [_]))
+            .unwrap()
+            .into_iter()
+            .map(|row| row[0].get().downcast_ref::<String>().unwrap().clone())
+            .collect();
+        for checker in checkers.iter() {
+            rows.extend(grade::score(checker, &funcs, &bad_hits, &good_hits).into_iter().filter(|row| {
+                cwes.contains(&row.cwe.as_str())
+            }));
+        }
+    }
+    print!("{}", grade::table(&rows));
+
+    if let Some(path) = matches.opt_str("w") {
+        let mut fd = File::create(&path).unwrap();
+        write!(fd, "{}\n", store::to_text(&rows)).unwrap();
+    }
+    if let Some(path) = matches.opt_str("b") {
+        let baseline: Vec<grade::Row> = store::from_text(&store::read_text(&path))
+            .unwrap_or_else(|| panic!("{} is not a grade baseline", path));
+        let regressions = grade::regressions(&baseline, &rows);
+        for regression in regressions.iter() {
+            println!("regression {}", regression);
+        }
+        if !regressions.is_empty() {
+            process::exit(1);
+        }
+    }
+}
+
+fn diff(prog_name: &str, args: Vec<String>) {
+    let mut opts = Options::new();
+    opts.optflag("", "persisting", "list the findings in both runs too, not just their number");
+    opts.optmulti("", "suppress", "leave out findings accepted in this suppression file", "FILE");
+    opts.optflag("h", "help", "print usage and exit");
+    let matches = opts.parse(args).unwrap_or_else(|x| panic!(x));
+    if matches.opt_present("h") || matches.free.len() != 2 {
+        let brief = format!("{} diff OLD NEW", prog_name);
+        println!("{}", opts.usage(&brief));
+        return;
+    }
+    let mut suppressions = Vec::new();
+    for path in matches.opt_strs("suppress") {
+        suppressions.extend(suppress::parse(&path, &store::read_text(&path)).unwrap_or_else(|e| panic!(e)));
+    }
+    let mut suppressed = 0;
+    let (old, new) = {
+        let mut load = |path: &str| -> Vec<Signature> {
+            let sigs: Vec<Signature> = store::from_text(&store::read_text(path))
+                .unwrap_or_else(|| panic!("{} is not a findings file", path));
+            let before = sigs.len();
+            let kept: Vec<Signature> = sigs.into_iter().filter(|sig| suppress::find(&suppressions, sig).is_none()).collect();
+            suppressed += before - kept.len();
+            kept
+        };
+        (load(&matches.free[0]), load(&matches.free[1]))
+    };
+    let d = signature::diff(&old, &new);
+    println!("Added: {}\nRemoved: {}\nPersisting: {}", d.added.len(), d.removed.len(), d.persisting.len());
+    if suppressed > 0 {
+        println!("Suppressed: {}", suppressed);
+    }
+    for sig in d.added.iter() {
+        println!("+ {}", sig);
+    }
+    for sig in d.removed.iter() {
+        println!("- {}", sig);
+    }
+    if matches.opt_present("persisting") {
+        for &(ref old, ref new) in d.persisting.iter() {
+            if old == new {
+                println!("= {}", new);
+            } else {
+                println!("= {}\n  was {}", new, old);
+            }
+        }
+    }
+}
@@ -1,28 +1,22 @@
 #[macro_use]
 extern crate holmes;
 extern crate getopts;
-extern crate url;
 extern crate env_logger;
 extern crate time;
-use holmes::PgDB;
 use holmes::simple::*;
 extern crate log;
 extern crate bap;
 extern crate tiamat;
+use tiamat::db;
 extern crate num;
 
 use bap::high::bitvector::BitVector;
 
 use getopts::Options;
-use url::percent_encoding::{percent_encode, PATH_SEGMENT_ENCODE_SET};
 use std::env;
 use log::{LogRecord, LogLevelFilter};
 use env_logger::LogBuilder;
 use num::traits::ToPrimitive;
-fn url_encode(input: &[u8]) -> String {
-    percent_encode(input, PATH_SEGMENT_ENCODE_SET).to_string()
-}
-
 fn init_logger() {
     let format = |record: &LogRecord| {
         let t = time::now();
@@ -47,15 +41,7 @@ fn init_logger() {
 
 fn main() {
     init_logger();
-    let db_default_addr = match env::var("TIAMAT_PG_SOCK_DIR") {
-        Ok(dir) => {
-            format!(
-                "postgresql://holmes@{}/holmes",
-                url_encode(&dir.into_bytes())
-            )
-        }
-        _ => format!("postgres://holmes@%2Fvar%2Frun%2Fpostgresql/holmes"),
-    };
+    let db_default_addr = db::default_addr();
     let default_in = "a.out";
     let mut opts = Options::new();
     opts.optmulti("i", "in", "binary to process", default_in);
@@ -79,10 +65,10 @@ fn main() {
         println!("{}", opts.usage(&brief));
         return;
     }
-    let db_addr = matches.opt_str("d").unwrap_or(db_default_addr.to_string());
+    let db_addr = matches.opt_str("d").unwrap_or(db_default_addr);
 
     let mut core = Core::new().unwrap();
-    let db = PgDB::new(&db_addr).unwrap();
+    let db = db::connect(&db_addr).unwrap_or_else(|e| panic!(e));
     let mut holmes = Engine::new(db, core.handle());
     tiamat::schema::setup(&mut holmes).unwrap();
     predicate!(holmes, path_alias_u(uint64, uint64, var, bool)).unwrap();
@@ -1,25 +1,25 @@
 #[macro_use]
 extern crate holmes;
 extern crate getopts;
-extern crate url;
 extern crate env_logger;
 extern crate time;
-use holmes::PgDB;
 use holmes::simple::*;
 extern crate log;
 
 extern crate tiamat;
+use tiamat::db;
+use tiamat::lib_summary::{self, SummaryStore};
+use tiamat::lift_cache::LiftCache;
+use tiamat::store;
 
 use getopts::Options;
-use url::percent_encoding::{percent_encode, PATH_SEGMENT_ENCODE_SET};
 use std::io::Write;
 use std::env;
+use std::path::PathBuf;
 use log::{LogRecord, LogLevelFilter};
 use env_logger::LogBuilder;
-
-fn url_encode(input: &[u8]) -> String {
-    percent_encode(input, PATH_SEGMENT_ENCODE_SET).to_string()
-}
+use tiamat::budget::{Budgets, Limits, Stage};
+use std::time::Duration;
 
 fn init_logger() {
     let format = |record: &LogRecord| {
@@ -45,15 +45,7 @@ fn init_logger() {
 
 fn main() {
     init_logger();
-    let db_default_addr = match env::var("TIAMAT_PG_SOCK_DIR") {
-        Ok(dir) => {
-            format!(
-                "postgresql://holmes@{}/holmes",
-                url_encode(&dir.into_bytes())
-            )
-        }
-        _ => format!("postgres://holmes@%2Fvar%2Frun%2Fpostgresql/holmes"),
-    };
+    let db_default_addr = db::default_addr();
     let default_in = "a.out";
     let mut opts = Options::new();
     opts.optmulti("i", "in", "binary to process", default_in);
@@ -69,18 +61,112 @@ fn main() {
         "maximum length of confirmation trace to consider",
         "30",
     );
+    opts.optopt(
+        "",
+        "stack-depth",
+        "maximum call depth to follow a path into callees",
+        "5",
+    );
+    opts.optopt(
+        "",
+        "chop",
+        "maximum number of distinct functions a path may pass through",
+        "3",
+    );
     opts.optopt(
         "l",
         "limit",
         "max time, in seconds, to run before stopping",
         "3600",
     );
+    for stage in Stage::all().iter() {
+        opts.optopt(
+            "",
+            &format!("{}-limit", stage),
+            &format!("max time, in seconds, to spend in the {} stage", stage),
+            "SECS",
+        );
+        opts.optopt(
+            "",
+            &format!("{}-facts", stage),
+            &format!("max number of facts to derive in the {} stage", stage),
+            "N",
+        );
+    }
+    opts.optopt(
+        "c",
+        "lift-cache",
+        "directory of a lifting cache to share between runs, by default none",
+        "DIR",
+    );
+    opts.optflag(
+        "",
+        "shingle",
+        "decode every offset of executable segments up front instead of lifting on demand",
+    );
+    opts.optflag(
+        "",
+        "summaries",
+        "apply per-function summaries at calls instead of analysing callees per context",
+    );
     opts.optflag("h", "help", "print usage and exit");
     opts.optflag(
         "s",
         "skip",
         "skip over functions not present in the current binary",
     );
+    opts.optmulti(
+        "",
+        "lib-summary",
+        "apply stored summaries for a skipped library, given the library or its build-id",
+        "LIB",
+    );
+    opts.optmulti(
+        "",
+        "model",
+        "apply a hand-written model of library functions, in the format of models/libc.model",
+        "FILE",
+    );
+    opts.optflag("", "no-model", "don't apply the bundled libc/POSIX/GLib model");
+    opts.optflag(
+        "",
+        "symex",
+        "check each finding by symbolic execution, with the solver named by TIAMAT_SOLVER",
+    );
+    opts.optflag(
+        "",
+        "emulate",
+        "run each finding's binary from main under the emulator to see if the use touches freed memory",
+    );
+    opts.optmulti(
+        "",
+        "emu-arg",
+        "argument to pass the emulated program, after its name",
+        "ARG",
+    );
+    opts.optmulti(
+        "",
+        "truth",
+        "grade against the expected findings in this truth file rather than by Juliet function names",
+        "FILE",
+    );
+    opts.optmulti(
+        "",
+        "suppress",
+        "leave out findings accepted in this suppression file, see src/suppress.rs for the format",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "findings",
+        "save location-independent signatures of the findings, for tiamat diff",
+        "FILE",
+    );
+    opts.optflag(
+        "",
+        "export-summaries",
+        "compute function summaries and store them for every input with a build-id",
+    );
     let mut args = env::args();
     let prog_name = args.next().unwrap();
     let matches = opts.parse(args).unwrap_or_else(|x| panic!(x));
@@ -89,42 +175,114 @@ fn main() {
         println!("{}", opts.usage(&brief));
         return;
     }
-    let db_addr = matches.opt_str("d").unwrap_or(db_default_addr.to_string());
-    let trace_len = matches
-        .opt_str("t")
-        .unwrap_or("30".to_string())
-        .parse::<usize>()
-        .unwrap();
-    let limiter = matches.opt_str("l").map(|l| l.parse::<u64>().unwrap());
+    let db_addr = matches.opt_str("d").unwrap_or(db_default_addr);
+    let mut limits = Limits::default();
+    if let Some(t) = matches.opt_str("t") {
+        limits.trace_len = t.parse::<usize>().unwrap();
+    }
+    if let Some(depth) = matches.opt_str("stack-depth") {
+        limits.stack_depth = depth.parse::<usize>().unwrap();
+    }
+    if let Some(chop) = matches.opt_str("chop") {
+        limits.chop = chop.parse::<usize>().unwrap();
+    }
+    let mut budgets = Budgets::default();
+    budgets.total = matches.opt_str("l").map(|l| Duration::new(l.parse::<u64>().unwrap(), 0));
+    for stage in Stage::all().iter() {
+        let budget = budgets.get_mut(*stage);
+        budget.time = matches
+            .opt_str(&format!("{}-limit", stage))
+            .map(|l| Duration::new(l.parse::<u64>().unwrap(), 0));
+        budget.facts = matches
+            .opt_str(&format!("{}-facts", stage))
+            .map(|n| n.parse::<u64>().unwrap());
+    }
     let in_paths = matches.opt_strs("i");
+    let lift_cache = matches.opt_str("c").map(|dir| LiftCache::new(PathBuf::from(dir)));
+
+    let summary_store = SummaryStore::from_env();
+    let mut lib_summaries = Vec::new();
+    for lib in matches.opt_strs("lib-summary") {
+        match summary_store.as_ref().and_then(|store| store.lookup(&lib)) {
+            Some(summary) => lib_summaries.push(summary),
+            None => panic!("No stored summaries for {}", lib),
+        }
+    }
+    for path in matches.opt_strs("model") {
+        lib_summaries.push(lib_summary::parse_model(&path, &store::read_text(&path)).unwrap_or_else(|e| panic!(e)));
+    }
+    let mut truth = Vec::new();
+    for path in matches.opt_strs("truth") {
+        truth.extend(tiamat::truth::parse(&path, &store::read_text(&path)).unwrap_or_else(|e| panic!(e)));
+    }
+    let mut suppressions = Vec::new();
+    for path in matches.opt_strs("suppress") {
+        suppressions.extend(tiamat::suppress::parse(&path, &store::read_text(&path)).unwrap_or_else(|e| panic!(e)));
+    }
+    let export = matches.opt_present("export-summaries");
 
     let mut core = Core::new().unwrap();
-    let db = PgDB::new(&db_addr).unwrap();
+    let db = db::connect(&db_addr).unwrap_or_else(|e| panic!(e));
     let mut holmes = Engine::new(db, core.handle());
-    limiter.map(|l| holmes.limit_time(::std::time::Duration::new(l, 0)));
-    let uaf = tiamat::uaf(in_paths, trace_len, true);
-    uaf(&mut holmes, &mut core).unwrap();
-    if matches.opt_present("s") {
-        rule!(holmes, cmd_opt_skip_dyn: skip_func(name, addr) <= link_pad(name, [_], tgt)).unwrap();
+    let pipeline = tiamat::Pipeline::new(in_paths)
+        .limits(limits)
+        .lift_cache(lift_cache)
+        .shingle(matches.opt_present("shingle"))
+        .summaries(export || matches.opt_present("summaries"))
+        .skip_dyn(matches.opt_present("s"))
+        .model(!matches.opt_present("no-model"))
+        .lib_summaries(lib_summaries)
+        .budgets(budgets)
+        .symex(matches.opt_present("symex"))
+        .emulate(if matches.opt_present("emulate") { Some(matches.opt_strs("emu-arg")) } else { None })
+        .truth(truth)
+        .suppressions(suppressions);
+    let report = pipeline.run(&mut holmes, &mut core).unwrap();
+    if export {
+        let store = summary_store.expect("No summary directory, set TIAMAT_SUMMARIES");
+        for summary in lib_summary::collect(&mut holmes).unwrap() {
+            store.put(&summary).unwrap();
+            println!("Exported {} functions of {} ({})", summary.funcs.len(), summary.path, summary.build_id);
+        }
     }
     // Judge
-    {
-        use std::collections::HashSet;
-        let mut true_positives = HashSet::new();
-        for row in query!(holmes, true_positive([_], [_], parent))
-            .unwrap()
-            .into_iter()
-        {
-            true_positives.insert(row[0].get().downcast_ref::<String>().unwrap().clone());
+    let (true_positives, false_positives) = tiamat::graded(&mut holmes).unwrap();
+    println!("True Positives: {}\nFalse Positives: {}", true_positives.len(), false_positives.len());
+    // Stage timings and what didn't decode differ from run to run, so keep them out of the output
+    // tools/juliet.sh diffs
+    eprint!("{}", report);
+    let lift_errors = tiamat::lift_error_summary(&mut holmes).unwrap();
+    eprintln!("Undecodable: {}", lift_errors.iter().map(|&(_, ref errs)| errs.len()).sum::<usize>());
+    for (bin, errs) in lift_errors {
+        for (addr, reason) in errs {
+            eprintln!("lift_error {} {} {}", bin, addr, reason);
         }
-        let mut false_positives = HashSet::new();
-        for row in query!(holmes, false_positive([_], [_], parent))
-            .unwrap()
-            .into_iter()
-        {
-            false_positives.insert(row[0].get().downcast_ref::<String>().unwrap().clone());
+    }
+    if report.budget.truncated(Stage::Trace) {
+        // The trace stage didn't get to finish, so flows it didn't confirm may still be real
+        let unconfirmed = tiamat::unconfirmed(&mut holmes).unwrap();
+        println!("Unconfirmed: {}", unconfirmed.len());
+        for row in unconfirmed {
+            println!("unconfirmed {} {} {}", row[0], row[1], row[2]);
         }
-        println!("True Positives: {}\nFalse Positives: {}", true_positives.len(), false_positives.len());
+    }
+    for row in query!(holmes, suppressed(name, src, sink_name, sink, why)).unwrap() {
+        eprintln!("suppressed {} {} {} {} {}", row[0], row[1], row[2], row[3], row[4]);
+    }
+    if matches.opt_present("symex") {
+        for row in query!(holmes, symex_verdict(name, src, sink_name, sink, verdict, detail)).unwrap() {
+            eprintln!("symex {} {} {} {} {} {}", row[0], row[1], row[2], row[3], row[4], row[5]);
+        }
+    }
+    if matches.opt_present("emulate") {
+        for row in query!(holmes, emu_verdict(name, src, sink_name, sink, outcome, detail)).unwrap() {
+            eprintln!("emulated {} {} {} {} {} {}", row[0], row[1], row[2], row[3], row[4], row[5]);
+        }
+    }
+    if let Some(path) = matches.opt_str("findings") {
+        let sigs = tiamat::signatures(&mut holmes).unwrap();
+        let mut out_fd = std::fs::File::create(&path).unwrap();
+        write!(out_fd, "{}\n", tiamat::store::to_text(&sigs)).unwrap();
     }
     dump_profile(&holmes, "uaf");
     let min_len: u64 = query!(holmes, use_after_free {trace = trace} & trace {id = trace, len = len})
@@ -0,0 +1,259 @@
+use holmes::simple::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Holmes has no way to lift a time limit once set, so a stage without a budget that follows one
+// with a budget gets this instead.
+const UNBOUNDED_SECS: u64 = 60 * 60 * 24 * 365;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Lift,
+    Flow,
+    Trace,
+    Grade,
+}
+
+impl Stage {
+    pub fn all() -> [Stage; 4] {
+        [Stage::Lift, Stage::Flow, Stage::Trace, Stage::Grade]
+    }
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Stage::Lift => "lift",
+            Stage::Flow => "flow",
+            Stage::Trace => "trace",
+            Stage::Grade => "grade",
+        }
+    }
+}
+
+impl ::std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Limits on a single stage. `facts` bounds the number of derivations of the stage's main
+/// predicate (`lift`, `path_alias`, `path_alias_trace`, `use_after_free` respectively). A
+/// derivation is counted each time a rule's budget func runs, so re-deriving a fact that is
+/// already present counts again; the count is an upper bound on distinct facts, not the number
+/// of rows the stage adds.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    pub time: Option<Duration>,
+    pub facts: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Budgets {
+    pub lift: Budget,
+    pub flow: Budget,
+    pub trace: Budget,
+    pub grade: Budget,
+    // Wall time for the whole run, as set by -l
+    pub total: Option<Duration>,
+}
+
+impl Budgets {
+    pub fn get(&self, stage: Stage) -> &Budget {
+        match stage {
+            Stage::Lift => &self.lift,
+            Stage::Flow => &self.flow,
+            Stage::Trace => &self.trace,
+            Stage::Grade => &self.grade,
+        }
+    }
+    pub fn get_mut(&mut self, stage: Stage) -> &mut Budget {
+        match stage {
+            Stage::Lift => &mut self.lift,
+            Stage::Flow => &mut self.flow,
+            Stage::Trace => &mut self.trace,
+            Stage::Grade => &mut self.grade,
+        }
+    }
+}
+
+/// Context-sensitivity bounds. Unlike budgets these change what is derived, not just how far the
+/// run gets, so the report records which allocation sites each of them cut off.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    // Calls deep a path may go before we stop following it into callees
+    pub stack_depth: usize,
+    // Distinct functions a path may pass through
+    pub chop: usize,
+    // Steps a confirmation trace may take
+    pub trace_len: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            stack_depth: 5,
+            chop: ::chop::MAX_CHOP,
+            trace_len: 30,
+        }
+    }
+}
+
+// Shared between the engine's registered funcs and the tracker, so the closures can be handed
+// to holmes while we still read the count afterwards.
+#[derive(Debug, Clone)]
+pub struct FactCounter {
+    limit: Option<u64>,
+    count: Arc<AtomicUsize>,
+    tripped: Arc<AtomicBool>,
+}
+
+impl FactCounter {
+    pub fn new(limit: Option<u64>) -> Self {
+        FactCounter {
+            limit: limit,
+            count: Arc::new(AtomicUsize::new(0)),
+            tripped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+    pub fn check(&self) -> bool {
+        let n = self.count.fetch_add(1, Ordering::SeqCst) as u64;
+        match self.limit {
+            Some(limit) if n >= limit => {
+                self.tripped.store(true, Ordering::SeqCst);
+                false
+            }
+            _ => true,
+        }
+    }
+    // The argument is ignored, it is only there because funcs must take something
+    pub fn func(&self) -> Box<Fn(&u64) -> bool> {
+        let counter = self.clone();
+        Box::new(move |_| counter.check())
+    }
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::SeqCst) as u64
+    }
+    pub fn tripped(&self) -> bool {
+        self.tripped.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Truncation {
+    Time,
+    Facts,
+}
+
+#[derive(Debug, Clone)]
+pub struct StageReport {
+    pub stage: Stage,
+    pub elapsed: Duration,
+    // Derivations attempted, duplicates included, as counted against Budget::facts
+    pub facts: u64,
+    pub truncated: Option<Truncation>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub stages: Vec<StageReport>,
+}
+
+impl Report {
+    pub fn truncated(&self, stage: Stage) -> bool {
+        self.stages.iter().any(|s| s.stage == stage && s.truncated.is_some())
+    }
+    pub fn any_truncated(&self) -> bool {
+        self.stages.iter().any(|s| s.truncated.is_some())
+    }
+}
+
+impl ::std::fmt::Display for Report {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        for s in self.stages.iter() {
+            write!(f, "Stage {}: {}.{:03}s, {} derivations", s.stage, s.elapsed.as_secs(), s.elapsed.subsec_nanos() / 1000_000, s.facts)?;
+            match s.truncated {
+                Some(Truncation::Time) => write!(f, " (truncated: time)\n")?,
+                Some(Truncation::Facts) => write!(f, " (truncated: facts)\n")?,
+                None => write!(f, "\n")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the engine stage by stage, keeping each quiesce inside both its stage budget and the
+/// overall one, and records which stages ran out.
+pub struct Tracker {
+    budgets: Budgets,
+    started: Instant,
+    current: Option<(Stage, Instant, FactCounter, bool)>,
+    report: Report,
+}
+
+impl Tracker {
+    pub fn new(budgets: Budgets) -> Self {
+        Tracker {
+            budgets: budgets,
+            started: Instant::now(),
+            current: None,
+            report: Report::default(),
+        }
+    }
+    pub fn begin(&mut self, stage: Stage) -> FactCounter {
+        self.end();
+        let counter = FactCounter::new(self.budgets.get(stage).facts);
+        self.current = Some((stage, Instant::now(), counter.clone(), false));
+        counter
+    }
+    fn remaining(&self) -> Option<Duration> {
+        let total = self.budgets.total.map(|t| sub_or_zero(t, self.started.elapsed()));
+        let stage = self.current.as_ref().and_then(|&(stage, start, _, _)| {
+            self.budgets.get(stage).time.map(|t| sub_or_zero(t, start.elapsed()))
+        });
+        match (total, stage) {
+            (Some(t), Some(s)) => Some(::std::cmp::min(t, s)),
+            (t, None) => t,
+            (None, s) => s,
+        }
+    }
+    pub fn quiesce(&mut self, holmes: &mut Engine, core: &mut Core) {
+        let remaining = self.remaining();
+        let start = Instant::now();
+        holmes.limit_time(remaining.unwrap_or(Duration::new(UNBOUNDED_SECS, 0)));
+        core.run(holmes.quiesce()).unwrap();
+        if let Some(limit) = remaining {
+            if start.elapsed() >= limit {
+                if let Some((_, _, _, ref mut timed_out)) = self.current {
+                    *timed_out = true;
+                }
+            }
+        }
+    }
+    pub fn end(&mut self) {
+        if let Some((stage, start, counter, timed_out)) = self.current.take() {
+            let truncated = if timed_out {
+                Some(Truncation::Time)
+            } else if counter.tripped() {
+                Some(Truncation::Facts)
+            } else {
+                None
+            };
+            if let Some(t) = truncated {
+                warn!("Stage {} truncated ({:?})", stage, t);
+            }
+            self.report.stages.push(StageReport {
+                stage: stage,
+                elapsed: start.elapsed(),
+                facts: counter.count(),
+                truncated: truncated,
+            });
+        }
+    }
+    pub fn finish(mut self) -> Report {
+        self.end();
+        self.report
+    }
+}
+
+fn sub_or_zero(a: Duration, b: Duration) -> Duration {
+    if a > b { a - b } else { Duration::new(0, 0) }
+}
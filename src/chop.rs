@@ -6,8 +6,8 @@ use holmes::pg::RowIter;
 use holmes::pg::dyn::{Type, Value};
 use std::any::Any;
 use std::sync::Arc;
-use rustc_serialize::json::{Json, Decoder, ToJson, encode};
-use rustc_serialize::Decodable;
+use rustc_serialize::json::{Json, ToJson};
+use store;
 
 #[derive(Debug, Clone, Hash, PartialOrd, PartialEq, RustcDecodable, RustcEncodable, Eq)]
 pub struct Chop {
@@ -16,12 +16,12 @@ pub struct Chop {
 
 impl ToJson for Chop {
     fn to_json(&self) -> Json {
-        let buf = encode(self).unwrap();
-        Json::from_str(&buf).unwrap()
+        store::encode(self)
     }
 }
 
-const MAX_CHOP: usize = 3;
+// Default bound on the number of distinct functions a path may enter
+pub const MAX_CHOP: usize = 3;
 
 impl Chop {
     pub fn new() -> Self {
@@ -29,13 +29,13 @@ impl Chop {
             members: Vec::new()
         }
     }
-    pub fn check(&self, func: &BitVector) -> Vec<Chop> {
+    pub fn check(&self, func: &BitVector, max: usize) -> Vec<Chop> {
         let mut members = self.members.clone();
         if !members.contains(func) {
             members.push(func.clone());
             members.sort();
         }
-        if members.len() <= MAX_CHOP {
+        if members.len() <= max {
             vec![Chop { members: members } ]
         } else {
             Vec::new()
@@ -51,8 +51,7 @@ impl TypeT for ChopType {
     }
     fn extract(&self, rows: &mut RowIter) -> Option<Value> {
         let raw: Json = rows.next().unwrap();
-        let mut decoder = Decoder::new(raw);
-        Some(Arc::new(Chop::decode(&mut decoder).unwrap()))
+        Some(Arc::new(store::decode::<Chop>(raw).unwrap()))
     }
     fn repr(&self) -> &'static str {
         "jsonb"
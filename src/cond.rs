@@ -0,0 +1,162 @@
+use bap::high::bitvector::BitVector;
+use holmes::pg::dyn::values::{ValueT, ToValue};
+use holmes::pg::dyn::types::TypeT;
+use postgres::types::{ToSql, IsNull};
+use holmes::pg::RowIter;
+use holmes::pg::dyn::{Type, Value};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::sync::Arc;
+use rustc_serialize::json::{Json, ToJson};
+use num::{BigUint, One, Zero};
+use store;
+use var::HVar;
+
+/// `loc == value` (or `!=` when `eq` is unset), reading `bits` bits of loc
+#[derive(Debug, Clone, Hash, PartialOrd, PartialEq, RustcDecodable, RustcEncodable, Eq)]
+pub struct Atom {
+    pub loc: HVar,
+    pub bits: usize,
+    pub eq: bool,
+    pub value: BitVector,
+}
+
+impl Atom {
+    fn same_loc(&self, other: &Atom) -> bool {
+        self.loc == other.loc && self.bits == other.bits
+    }
+    fn contradicts(&self, other: &Atom) -> bool {
+        if !self.same_loc(other) {
+            return false;
+        }
+        match (self.eq, other.eq) {
+            (true, true) => self.value != other.value,
+            (true, false) | (false, true) => self.value == other.value,
+            // A flag can't differ from both 0 and 1
+            (false, false) => self.bits == 1 && self.value != other.value,
+        }
+    }
+}
+
+/// Conjunction of branch conditions taken so far, relative to the state at the current block.
+#[derive(Debug, Clone, Hash, PartialOrd, PartialEq, RustcDecodable, RustcEncodable, Eq)]
+pub struct Cond {
+    atoms: Vec<Atom>,
+}
+
+impl ToJson for Cond {
+    fn to_json(&self) -> Json {
+        store::encode(self)
+    }
+}
+
+impl Cond {
+    pub fn new() -> Self {
+        Cond { atoms: Vec::new() }
+    }
+    pub fn atoms(&self) -> &[Atom] {
+        &self.atoms
+    }
+    pub fn is_true(&self) -> bool {
+        self.atoms.is_empty()
+    }
+    /// Adds atom, or gives nothing if it contradicts what is already known
+    pub fn and(&self, atom: Atom) -> Option<Cond> {
+        if self.atoms.iter().any(|a| a.contradicts(&atom)) {
+            return None;
+        }
+        let mut atoms = self.atoms.clone();
+        if !atoms.contains(&atom) {
+            atoms.push(atom);
+            atoms.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        }
+        Some(Cond { atoms: atoms })
+    }
+    pub fn and_all(&self, other: &Cond) -> Option<Cond> {
+        let mut out = self.clone();
+        for atom in other.atoms.iter() {
+            out = match out.and(atom.clone()) {
+                Some(out) => out,
+                None => return None,
+            };
+        }
+        Some(out)
+    }
+    /// Forgets everything about locations `keep` rejects
+    pub fn retain<F: Fn(&Atom) -> bool>(&self, keep: F) -> Cond {
+        Cond { atoms: self.atoms.iter().filter(|a| keep(a)).cloned().collect() }
+    }
+}
+
+// Condition for a flag-like location being set or clear
+pub fn flag(loc: HVar, set: bool) -> Atom {
+    let value = if set { BigUint::one() } else { BigUint::zero() };
+    Atom {
+        loc: loc,
+        bits: 1,
+        eq: true,
+        value: BitVector::new_unsigned(value, 1),
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq)]
+pub struct CondType;
+impl TypeT for CondType {
+    fn name(&self) -> Option<&'static str> {
+        Some("cond")
+    }
+    fn extract(&self, rows: &mut RowIter) -> Option<Value> {
+        let raw: Json = rows.next().unwrap();
+        Some(Arc::new(store::decode::<Cond>(raw).unwrap()))
+    }
+    fn repr(&self) -> &'static str {
+        "jsonb"
+    }
+    typet_boiler!();
+}
+
+impl ValueT for Cond {
+    fn type_(&self) -> Type {
+        Arc::new(CondType)
+    }
+    fn get(&self) -> &Any {
+        self as &Any
+    }
+    fn to_sql(&self) -> Vec<&ToSql> {
+        vec![self]
+    }
+    valuet_boiler!();
+}
+
+impl ToSql for Cond {
+    accepts!(::postgres::types::JSONB, ::postgres::types::JSON);
+    to_sql_checked!();
+    fn to_sql(
+        &self,
+        ty: &::postgres::types::Type,
+        out: &mut Vec<u8>,
+    ) -> ::std::result::Result<IsNull, Box<::std::error::Error + Send + Sync>> {
+        self.to_json().to_sql(ty, out)
+    }
+}
+
+impl ToValue for Cond {
+    fn to_value(self) -> Value {
+        Arc::new(self)
+    }
+}
+
+impl ::std::fmt::Display for Cond {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        if self.atoms.is_empty() {
+            return write!(f, "true");
+        }
+        for (i, atom) in self.atoms.iter().enumerate() {
+            if i > 0 {
+                write!(f, " && ")?;
+            }
+            write!(f, "{}:{} {} {}", atom.loc, atom.bits, if atom.eq { "==" } else { "!=" }, atom.value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use holmes::PgDB;
+use url::percent_encoding::{percent_encode, PATH_SEGMENT_ENCODE_SET};
+use std::env;
+
+// Connecting the binaries to the fact database. Holmes only runs on Postgres, so this is just
+// the default connection string, shared rather than rebuilt by each binary.
+
+fn url_encode(input: &[u8]) -> String {
+    percent_encode(input, PATH_SEGMENT_ENCODE_SET).to_string()
+}
+
+/// Connection string to use when none is given: the socket in TIAMAT_PG_SOCK_DIR if set,
+/// otherwise the system one
+pub fn default_addr() -> String {
+    match env::var("TIAMAT_PG_SOCK_DIR") {
+        Ok(dir) => format!("postgresql://holmes@{}/holmes", url_encode(&dir.into_bytes())),
+        Err(_) => "postgres://holmes@%2Fvar%2Frun%2Fpostgresql/holmes".to_string(),
+    }
+}
+
+pub fn connect(addr: &str) -> ::std::result::Result<PgDB, String> {
+    PgDB::new(addr).map_err(|e| format!("{:?}", e))
+}
@@ -0,0 +1,130 @@
+use ids;
+
+// Just enough ELF parsing to pull out things BAP doesn't expose to us
+
+const SHT_NOTE: u64 = 7;
+const PT_NOTE: u64 = 4;
+const NT_GNU_BUILD_ID: u64 = 3;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+    wide: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 0x40 || &data[0..4] != b"\x7fELF" {
+            return None;
+        }
+        Some(Reader {
+            data: data,
+            wide: data[4] == 2,
+            big_endian: data[5] == 2,
+        })
+    }
+    fn uint(&self, off: usize, size: usize) -> Option<u64> {
+        if off.checked_add(size).map_or(true, |end| end > self.data.len()) {
+            return None;
+        }
+        let bytes = &self.data[off..off + size];
+        let fold = |acc: u64, b: &u8| (acc << 8) | (*b as u64);
+        Some(if self.big_endian {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        })
+    }
+    fn word(&self, off: usize) -> Option<u64> {
+        self.uint(off, if self.wide { 8 } else { 4 })
+    }
+    // (type, offset, size) for each section header
+    fn sections(&self) -> Vec<(u64, u64, u64)> {
+        let (shoff, shentsize, shnum, type_off, off_off, size_off) = if self.wide {
+            (self.word(0x28), self.uint(0x3A, 2), self.uint(0x3C, 2), 4, 0x18, 0x20)
+        } else {
+            (self.word(0x20), self.uint(0x2E, 2), self.uint(0x30, 2), 4, 0x10, 0x14)
+        };
+        self.table(shoff, shentsize, shnum, type_off, off_off, size_off)
+    }
+    // (type, offset, size) for each program header
+    fn segments(&self) -> Vec<(u64, u64, u64)> {
+        let (phoff, phentsize, phnum, type_off, off_off, size_off) = if self.wide {
+            (self.word(0x20), self.uint(0x36, 2), self.uint(0x38, 2), 0, 0x8, 0x20)
+        } else {
+            (self.word(0x1C), self.uint(0x2A, 2), self.uint(0x2C, 2), 0, 0x4, 0x10)
+        };
+        self.table(phoff, phentsize, phnum, type_off, off_off, size_off)
+    }
+    fn table(
+        &self,
+        base: Option<u64>,
+        entsize: Option<u64>,
+        num: Option<u64>,
+        type_off: usize,
+        off_off: usize,
+        size_off: usize,
+    ) -> Vec<(u64, u64, u64)> {
+        let (base, entsize, num) = match (base, entsize, num) {
+            (Some(b), Some(e), Some(n)) => (b as usize, e as usize, n as usize),
+            _ => return Vec::new(),
+        };
+        (0..num)
+            .filter_map(|i| {
+                let ent = base + i * entsize;
+                match (self.uint(ent + type_off, 4), self.word(ent + off_off), self.word(ent + size_off)) {
+                    (Some(t), Some(o), Some(s)) => Some((t, o, s)),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+    fn notes(&self, off: u64, size: u64) -> Vec<(String, u64, &'a [u8])> {
+        let mut out = Vec::new();
+        let mut cur = off as usize;
+        let end = (off + size) as usize;
+        let align = |x: usize| (x + 3) & !3;
+        while cur + 12 <= end {
+            let (namesz, descsz, type_) = match (self.uint(cur, 4), self.uint(cur + 4, 4), self.uint(cur + 8, 4)) {
+                (Some(n), Some(d), Some(t)) => (n as usize, d as usize, t),
+                _ => break,
+            };
+            let name_start = cur + 12;
+            let desc_start = name_start + align(namesz);
+            let desc_end = desc_start + descsz;
+            if desc_end > end || desc_end > self.data.len() {
+                break;
+            }
+            let name = self.data[name_start..name_start + namesz]
+                .iter()
+                .take_while(|b| **b != 0)
+                .map(|b| *b as char)
+                .collect();
+            out.push((name, type_, &self.data[desc_start..desc_end]));
+            cur = desc_start + align(descsz);
+        }
+        out
+    }
+}
+
+/// The GNU build-id note of an ELF image, as lowercase hex
+pub fn build_id(contents: &[u8]) -> Option<String> {
+    let reader = match Reader::new(contents) {
+        Some(r) => r,
+        None => return None,
+    };
+    // Stripped binaries may have lost their section headers, but the note segment stays
+    let notes = reader
+        .sections()
+        .into_iter()
+        .filter(|&(t, _, _)| t == SHT_NOTE)
+        .chain(reader.segments().into_iter().filter(|&(t, _, _)| t == PT_NOTE));
+    for (_, off, size) in notes {
+        for (name, type_, desc) in reader.notes(off, size) {
+            if name == "GNU" && type_ == NT_GNU_BUILD_ID {
+                return Some(ids::hex(desc));
+            }
+        }
+    }
+    None
+}
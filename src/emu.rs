@@ -0,0 +1,436 @@
+use bap::basic::{Arch, Endian};
+use bap::high::bil::{Statement, Expression};
+use bap::high::bitvector::BitVector;
+use num::ToPrimitive;
+use std::collections::{HashMap, HashSet};
+use analyses;
+use interp::{Fault, Machine, Next};
+
+// User-mode emulation of a whole sample binary on top of interp. Calls into the PLT go to a
+// small libc shim instead, which does its own heap bookkeeping so we can tell when a use lands
+// in a freed object. Nothing is ever reused by the shim's allocator, so a freed object keeps its
+// address and contents and the program runs on past the use.
+
+const STACK_TOP: u64 = 0x7fff_0000;
+const STACK_SIZE: u64 = 0x10000;
+const TLS_BASE: u64 = 0x7ffe_0000;
+const TLS_SIZE: u64 = 0x1000;
+const HEAP_BASE: u64 = 0x1000_0000;
+// Largest single allocation the shim will map
+const MAX_ALLOC: u64 = 1 << 20;
+// Where main returns to. Nothing is mapped there, so getting there ends the run.
+const EXIT_ADDR: u64 = 0;
+
+const GPRS: &'static [&'static str] = &["RAX", "RBX", "RCX", "RDX", "RSI", "RDI", "RBP", "RSP", "R8", "R9",
+                                        "R10", "R11", "R12", "R13", "R14", "R15"];
+const FLAGS: &'static [&'static str] = &["CF", "PF", "AF", "ZF", "SF", "OF", "DF"];
+
+/// An object from the shim's allocator
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    // Return address of the call that allocated it
+    pub site: u64,
+    pub base: u64,
+    pub size: u64,
+    pub freed: bool,
+}
+
+/// What to steer towards: the allocation's return site, the use block and where it ends, and the
+/// blocks the confirming trace passed through.
+#[derive(Debug, Clone, Default)]
+pub struct Witness {
+    pub source_ret: u64,
+    pub sink: u64,
+    pub sink_end: u64,
+    pub blocks: HashSet<u64>,
+}
+
+/// Picks the blocks of one trace out of the (block, steps, freed) facts of every trace from an
+/// allocation: the shortest that gets to sink with the object freed, walked back a step at a time
+/// through blocks `adjacent` says may run one after the other. The walk only leaves the object's
+/// freed side through one of the `frees` blocks, so it stays on a trace that frees it. Steering
+/// through the blocks of several traces at once could follow a path none of them took.
+pub fn single_trace(
+    facts: &[(u64, u64, bool)],
+    sink: u64,
+    frees: &HashSet<u64>,
+    adjacent: &Fn(u64, u64) -> bool,
+) -> HashSet<u64> {
+    let mut blocks = HashSet::new();
+    let end = facts.iter().filter(|&&(b, _, freed)| b == sink && freed).map(|&(_, steps, _)| steps).min();
+    let (mut cur, mut steps, mut freed) = match end {
+        Some(steps) => (sink, steps, true),
+        None => return blocks,
+    };
+    blocks.insert(sink);
+    while steps > 0 {
+        let found = {
+            let prev = |want: bool| {
+                facts.iter()
+                    .find(|&&(b, s, f)| s + 1 == steps && f == want && (f == freed || frees.contains(&b)) && adjacent(b, cur))
+                    .cloned()
+            };
+            match prev(freed) {
+                None if freed => prev(false),
+                found => found,
+            }
+        };
+        match found {
+            Some((b, s, f)) => {
+                blocks.insert(b);
+                cur = b;
+                steps = s;
+                freed = f;
+            }
+            None => break,
+        }
+    }
+    blocks
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The use touched the freed object without any branch being forced
+    Confirmed,
+    /// The use touched the freed object, but only after forcing this many branches. The state
+    /// after a forced branch may be one no input leads to, so this is weaker than Confirmed.
+    Forced(usize),
+    /// The use ran, after forcing this many branches, but never touched freed memory
+    Refuted(usize),
+    /// Why the run never got to the use
+    Unreached(String),
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Outcome::Confirmed => "confirmed",
+            Outcome::Forced(_) => "forced",
+            Outcome::Refuted(_) => "refuted",
+            Outcome::Unreached(_) => "unreached",
+        }
+    }
+    pub fn detail(&self) -> String {
+        match *self {
+            Outcome::Confirmed => String::new(),
+            Outcome::Forced(forced) | Outcome::Refuted(forced) => format!("{} branches forced", forced),
+            Outcome::Unreached(ref why) => why.clone(),
+        }
+    }
+}
+
+struct Decoded {
+    stmts: Vec<Statement>,
+    len: u64,
+    syscall: bool,
+}
+
+pub struct Emulator {
+    arch: Arch,
+    machine: Machine,
+    pads: HashMap<u64, String>,
+    symbols: HashMap<String, u64>,
+    heap: Vec<Chunk>,
+    brk: u64,
+    decoded: HashMap<u64, Decoded>,
+    // Library functions that were called and only got a do-nothing stub
+    pub stubbed: Vec<String>,
+}
+
+fn bv(n: u64) -> BitVector {
+    BitVector::from_u64(n, 64)
+}
+
+fn to_u64(v: &BitVector) -> u64 {
+    v.unum().to_u64().unwrap_or(0)
+}
+
+// Constant targets of the jumps under a conditional in stmts, if there is one
+fn branch_targets(stmts: &[Statement]) -> Vec<u64> {
+    let mut tgts = Vec::new();
+    for stmt in stmts.iter() {
+        if let Statement::IfThenElse { ref then_clause, ref else_clause, .. } = *stmt {
+            for inner in then_clause.iter().chain(else_clause.iter()) {
+                if let Statement::Jump(Expression::Const(ref tgt)) = *inner {
+                    tgts.push(to_u64(tgt));
+                }
+            }
+        }
+    }
+    tgts
+}
+
+impl Emulator {
+    /// Maps the image and sets up a stack for main, with args as the whole of argv. None if the
+    /// image won't parse.
+    pub fn load(contents: &[u8], args: &[String]) -> Option<Self> {
+        let image = match analyses::ingest(contents) {
+            Some(image) => image,
+            None => return None,
+        };
+        let mut machine = Machine::new();
+        for (_, data, start, end, _, _, _) in image.segments {
+            let (start, end) = (to_u64(&start), to_u64(&end));
+            machine.map(start, &data);
+            // Whatever the file doesn't supply (.bss) starts zeroed
+            let len = end.saturating_sub(start) + 1;
+            if len > data.len() as u64 {
+                machine.map(start + data.len() as u64, &vec![0; (len - data.len() as u64) as usize]);
+            }
+        }
+        for reg in GPRS.iter() {
+            machine.set_reg(reg, bv(0));
+        }
+        for flag in FLAGS.iter() {
+            machine.set_flag(flag, false);
+        }
+        for i in 0..16 {
+            machine.set_reg(&format!("YMM{}", i), BitVector::from_u64(0, 256));
+        }
+        machine.map(TLS_BASE, &vec![0; TLS_SIZE as usize]);
+        machine.set_reg("FS_BASE", bv(TLS_BASE));
+        machine.set_reg("GS_BASE", bv(0));
+        machine.map(STACK_TOP - STACK_SIZE, &vec![0; STACK_SIZE as usize]);
+        let mut emu = Emulator {
+            arch: image.arch,
+            machine: machine,
+            pads: image.pads.into_iter().map(|(name, addr)| (to_u64(&addr), name)).collect(),
+            symbols: image.symbols.into_iter().map(|(name, addr, _)| (name, to_u64(&addr))).collect(),
+            heap: Vec::new(),
+            brk: HEAP_BASE,
+            decoded: HashMap::new(),
+            stubbed: Vec::new(),
+        };
+        emu.setup_main(args);
+        Some(emu)
+    }
+    // Strings at the top of the stack, then argv and an empty envp under them, and finally the
+    // return address, so main starts as if it had just been called.
+    fn setup_main(&mut self, args: &[String]) {
+        let mut sp = STACK_TOP;
+        let mut argv = Vec::new();
+        for arg in args.iter() {
+            let mut bytes = arg.as_bytes().to_vec();
+            bytes.push(0);
+            sp -= bytes.len() as u64;
+            self.machine.map(sp, &bytes);
+            argv.push(sp);
+        }
+        sp &= !0xf;
+        // argv with its terminator, then an empty envp
+        let mut words = argv.clone();
+        words.push(0);
+        words.push(0);
+        if words.len() % 2 == 1 {
+            sp -= 8;
+        }
+        sp -= 8 * words.len() as u64;
+        for (i, word) in words.iter().enumerate() {
+            self.machine.write(sp + 8 * i as u64, &bv(*word), &Endian::Little);
+        }
+        self.machine.set_reg("RDI", bv(args.len() as u64));
+        self.machine.set_reg("RSI", bv(sp));
+        self.machine.set_reg("RDX", bv(sp + 8 * (argv.len() as u64 + 1)));
+        sp -= 8;
+        self.machine.write(sp, &bv(EXIT_ADDR), &Endian::Little);
+        self.machine.set_reg("RSP", bv(sp));
+        self.machine.accesses.clear();
+    }
+    pub fn heap(&self) -> &[Chunk] {
+        &self.heap
+    }
+    fn reg(&self, name: &str) -> u64 {
+        self.machine.reg(name).map(to_u64).unwrap_or(0)
+    }
+    fn set_reg(&mut self, name: &str, n: u64) {
+        self.machine.set_reg(name, bv(n));
+    }
+    fn alloc(&mut self, site: u64, size: u64) -> u64 {
+        if size > MAX_ALLOC {
+            return 0;
+        }
+        let base = self.brk;
+        self.machine.map(base, &vec![0; size as usize]);
+        // Leave a gap so running off the end of one object faults instead of hitting the next
+        self.brk = (base + size + 0x1f) & !0xf;
+        self.heap.push(Chunk { site: site, base: base, size: size, freed: false });
+        base
+    }
+    fn free(&mut self, ptr: u64) {
+        if let Some(chunk) = self.heap.iter_mut().find(|c| c.base == ptr && !c.freed) {
+            chunk.freed = true;
+        }
+    }
+    fn copy(&mut self, dst: u64, src: u64, len: u64) -> Result<(), Fault> {
+        for i in 0..len {
+            let b = self.machine.read(src + i, 1, &Endian::Little)?;
+            self.machine.write(dst + i, &b, &Endian::Little);
+        }
+        Ok(())
+    }
+    /// Runs the library function name in place of its PLT stub, returning to ret. Gives a reason
+    /// to stop if the function ends the program.
+    fn call_lib(&mut self, name: &str, ret: u64) -> Result<Option<String>, Fault> {
+        let sp = self.reg("RSP");
+        let (rdi, rsi, rdx) = (self.reg("RDI"), self.reg("RSI"), self.reg("RDX"));
+        let rax = match name {
+            "malloc" => self.alloc(ret, rdi),
+            "calloc" => self.alloc(ret, rdi.saturating_mul(rsi)),
+            "realloc" => {
+                let new = self.alloc(ret, rsi);
+                let old = self.heap.iter().find(|c| c.base == rdi && !c.freed).map(|c| c.size);
+                if let (Some(old), true) = (old, new != 0) {
+                    self.copy(new, rdi, ::std::cmp::min(old, rsi))?;
+                    self.free(rdi);
+                }
+                new
+            }
+            "free" | "qfree" | "g_free" => {
+                self.free(rdi);
+                0
+            }
+            "exit" | "_exit" | "abort" | "__stack_chk_fail" | "__assert_fail" => {
+                return Ok(Some(format!("called {}", name)))
+            }
+            "memset" => {
+                for i in 0..rdx {
+                    self.machine.write(rdi + i, &BitVector::from_u64(rsi & 0xff, 8), &Endian::Little);
+                }
+                rdi
+            }
+            "memcpy" | "memmove" => {
+                self.copy(rdi, rsi, rdx)?;
+                rdi
+            }
+            "strlen" => {
+                let mut len = 0;
+                while to_u64(&self.machine.read(rdi + len, 1, &Endian::Little)?) != 0 {
+                    len += 1;
+                }
+                len
+            }
+            _ => {
+                if !self.stubbed.iter().any(|s| s == name) {
+                    self.stubbed.push(name.to_string());
+                }
+                0
+            }
+        };
+        for reg in ["RCX", "RDX", "RSI", "RDI", "R8", "R9", "R10", "R11"].iter() {
+            self.set_reg(reg, 0);
+        }
+        self.set_reg("RAX", rax);
+        self.set_reg("RSP", sp + 8);
+        Ok(None)
+    }
+    // Linux x86-64 syscall numbers; anything not handled fails with ENOSYS
+    fn syscall(&mut self) -> Option<String> {
+        let rax = match self.reg("RAX") {
+            60 | 231 => return Some("exited".to_string()),
+            // write: claim it all went out
+            1 => self.reg("RDX"),
+            // brk: no change
+            12 => 0,
+            _ => (-38i64) as u64,
+        };
+        self.set_reg("RAX", rax);
+        self.set_reg("RCX", 0);
+        self.set_reg("R11", 0);
+        None
+    }
+    fn decode(&mut self, pc: u64) -> Result<&Decoded, String> {
+        if !self.decoded.contains_key(&pc) {
+            if self.machine.byte(pc).is_none() {
+                return Err(format!("jumped to unmapped {:#x}", pc));
+            }
+            let bin: Vec<u8> = (0..16).map(|i| self.machine.byte(pc + i).unwrap_or(0)).collect();
+            let insn = match analyses::lift_insn_at(&self.arch, &bin, &bv(pc)) {
+                Some(insn) => insn,
+                None => return Err(format!("undecodable at {:#x}", pc)),
+            };
+            self.decoded.insert(pc, Decoded {
+                syscall: insn.disasm.starts_with("syscall"),
+                stmts: insn.stmts,
+                len: insn.len as u64,
+            });
+        }
+        Ok(&self.decoded[&pc])
+    }
+    fn freed_at(&self, site: u64, addr: u64, bytes: usize) -> bool {
+        self.heap.iter().any(|c| {
+            c.site == site && c.freed && addr < c.base + c.size && c.base < addr + bytes as u64
+        })
+    }
+    /// Runs main for at most max_steps instructions. Once the object from the witness's source
+    /// is allocated, a conditional branch leaving the witness is turned around if its other
+    /// side stays on it.
+    pub fn run(&mut self, witness: &Witness, max_steps: usize) -> Outcome {
+        let mut pc = match self.symbols.get("main") {
+            Some(&main) => main,
+            None => return Outcome::Unreached("no main".to_string()),
+        };
+        let mut forced = 0;
+        let mut used = false;
+        let mut stop = "step bound reached".to_string();
+        for _ in 0..max_steps {
+            if pc == EXIT_ADDR {
+                stop = "main returned".to_string();
+                break;
+            }
+            if let Some(name) = self.pads.get(&pc).cloned() {
+                let sp = self.reg("RSP");
+                let ret = match self.machine.read(sp, 8, &Endian::Little) {
+                    Ok(ret) => to_u64(&ret),
+                    Err(fault) => return Outcome::Unreached(format!("{:?} calling {}", fault, name)),
+                };
+                match self.call_lib(&name, ret) {
+                    Ok(None) => pc = ret,
+                    Ok(Some(why)) => {
+                        stop = why;
+                        break;
+                    }
+                    Err(fault) => return Outcome::Unreached(format!("{:?} in {}", fault, name)),
+                }
+                continue;
+            }
+            let (stmts, len, syscall) = match self.decode(pc) {
+                Ok(d) => (d.stmts.clone(), d.len, d.syscall),
+                Err(why) => return Outcome::Unreached(why),
+            };
+            if syscall {
+                if let Some(why) = self.syscall() {
+                    stop = why;
+                    break;
+                }
+                pc += len;
+                continue;
+            }
+            self.machine.accesses.clear();
+            let mut next = match self.machine.exec(&stmts) {
+                Ok(Next::Fall) => pc + len,
+                Ok(Next::Jump(tgt)) => to_u64(&tgt),
+                Err(fault) => return Outcome::Unreached(format!("{:?} at {:#x}", fault, pc)),
+            };
+            if witness.sink <= pc && pc < witness.sink_end {
+                used = true;
+                let touched = self.machine.accesses.iter().any(|a| self.freed_at(witness.source_ret, a.addr, a.bytes));
+                if touched {
+                    return if forced == 0 { Outcome::Confirmed } else { Outcome::Forced(forced) };
+                }
+            }
+            let live = self.heap.iter().any(|c| c.site == witness.source_ret);
+            if live && !witness.blocks.contains(&next) {
+                let mut tgts = branch_targets(&stmts);
+                if !tgts.is_empty() {
+                    tgts.push(pc + len);
+                    if let Some(&alt) = tgts.iter().find(|&&t| t != next && witness.blocks.contains(&t)) {
+                        forced += 1;
+                        next = alt;
+                    }
+                }
+            }
+            pc = next;
+        }
+        if used { Outcome::Refuted(forced) } else { Outcome::Unreached(stop) }
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+use truth::Annotation;
+
+// Scoring against the Juliet test suite. Each testcase has a bad function holding the flaw and
+// good functions that don't, all named after the testcase. A testcase's bad side is a true
+// positive if something is reported under one of its bad functions and a false negative if not;
+// its good side is a false positive or a true negative the same way. C++ testcases put bad and
+// good in a namespace, so their mangled names don't parse and they aren't scored yet. Other
+// corpora are scored per annotation of a truth file instead.
+
+/// A checker and the Juliet CWEs it is meant to find
+pub struct Checker {
+    pub name: &'static str,
+    pub cwes: &'static [&'static str],
+}
+
+pub const CHECKERS: &'static [Checker] = &[Checker {
+    name: "uaf",
+    cwes: &["CWE416"],
+}];
+
+/// What a Juliet function name says about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Testcase {
+    pub cwe: String,
+    /// Name of the testcase, the same across all its files
+    pub name: String,
+    /// Flow variant, 01 being the baseline with no control or data flow around the flaw
+    pub variant: u32,
+    pub bad: bool,
+}
+
+/// Reads a function name such as CWE416_Use_After_Free__malloc_free_char_54b_badSink
+pub fn parse(func: &str) -> Option<Testcase> {
+    if !func.starts_with("CWE") {
+        return None;
+    }
+    let (side, bad) = match (func.rfind("_bad"), func.rfind("_good")) {
+        (Some(b), Some(g)) if b > g => (b, true),
+        (_, Some(g)) => (g, false),
+        (Some(b), None) => (b, true),
+        (None, None) => return None,
+    };
+    let stem = &func[..side];
+    let under = match stem.rfind('_') {
+        Some(under) => under,
+        None => return None,
+    };
+    // Testcases split over files have a letter after the variant, one per file
+    let digits = stem[under + 1..].trim_right_matches(|c: char| c.is_alphabetic());
+    let variant = match digits.parse::<u32>() {
+        Ok(variant) => variant,
+        Err(_) => return None,
+    };
+    Some(Testcase {
+        cwe: func[..func.find('_').unwrap()].to_string(),
+        name: stem[..under + 1 + digits.len()].to_string(),
+        variant: variant,
+        bad: bad,
+    })
+}
+
+/// The CWE a checker is meant to find, empty for a checker not in CHECKERS
+fn checker_cwe(checker: &str) -> String {
+    CHECKERS.iter().find(|c| c.name == checker).and_then(|c| c.cwes.first()).map_or(String::new(), |cwe| cwe.to_string())
+}
+
+/// Scores for one flow variant of one CWE under one checker, or for one binary of a truth file
+#[derive(Debug, Clone, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct Row {
+    pub checker: String,
+    pub cwe: String,
+    /// Juliet flow variant, None when scored from a truth file
+    pub variant: Option<u32>,
+    /// Binary as the truth file names it, None when scored from Juliet
+    pub binary: Option<String>,
+    pub true_pos: usize,
+    pub false_pos: usize,
+    pub false_neg: usize,
+    pub true_neg: usize,
+}
+
+impl Row {
+    fn new(checker: &str, cwe: &str, variant: Option<u32>, binary: Option<&str>) -> Self {
+        Row {
+            checker: checker.to_string(),
+            cwe: cwe.to_string(),
+            variant: variant,
+            binary: binary.map(|b| b.to_string()),
+            true_pos: 0,
+            false_pos: 0,
+            false_neg: 0,
+            true_neg: 0,
+        }
+    }
+    fn add(&mut self, other: &Row) {
+        self.true_pos += other.true_pos;
+        self.false_pos += other.false_pos;
+        self.false_neg += other.false_neg;
+        self.true_neg += other.true_neg;
+    }
+    pub fn precision(&self) -> Option<f64> {
+        let flagged = self.true_pos + self.false_pos;
+        if flagged == 0 { None } else { Some(self.true_pos as f64 / flagged as f64) }
+    }
+    pub fn recall(&self) -> Option<f64> {
+        let flawed = self.true_pos + self.false_neg;
+        if flawed == 0 { None } else { Some(self.true_pos as f64 / flawed as f64) }
+    }
+}
+
+/// Scores every testcase among funcs that belongs to the checker's CWEs. bad_hits and
+/// good_hits are the functions findings were reported under, as true_positive and
+/// false_positive give them.
+pub fn score(checker: &Checker, funcs: &[String], bad_hits: &HashSet<String>, good_hits: &HashSet<String>) -> Vec<Row> {
+    // (cwe, variant, testcase) to whether it has a bad side, and whether that side was hit,
+    // then the same for its good side
+    let mut cases: BTreeMap<(String, u32, String), (Option<bool>, Option<bool>)> = BTreeMap::new();
+    for func in funcs.iter() {
+        let tc = match parse(func) {
+            Some(ref tc) if checker.cwes.contains(&tc.cwe.as_str()) => tc.clone(),
+            _ => continue,
+        };
+        let sides = cases.entry((tc.cwe, tc.variant, tc.name)).or_insert((None, None));
+        if tc.bad {
+            let hit = sides.0.unwrap_or(false) || bad_hits.contains(func);
+            sides.0 = Some(hit);
+        } else {
+            let hit = sides.1.unwrap_or(false) || good_hits.contains(func);
+            sides.1 = Some(hit);
+        }
+    }
+    let mut rows: BTreeMap<(String, u32), Row> = BTreeMap::new();
+    for ((cwe, variant, _), (bad, good)) in cases {
+        let row = rows.entry((cwe.clone(), variant)).or_insert_with(|| Row::new(checker.name, &cwe, Some(variant), None));
+        match bad {
+            Some(true) => row.true_pos += 1,
+            Some(false) => row.false_neg += 1,
+            None => (),
+        }
+        match good {
+            Some(true) => row.false_pos += 1,
+            Some(false) => row.true_neg += 1,
+            None => (),
+        }
+    }
+    rows.into_iter().map(|(_, row)| row).collect()
+}
+
+/// Scores annotations by whether findings were reported under their labels, one row per checker
+/// and binary. unmatched is how many findings each checker made in each binary, by path, that no
+/// annotation covers; in a binary with annotations for that checker, those are false positives.
+pub fn score_truth(annotations: &[Annotation],
+                   bad_hits: &HashSet<String>,
+                   good_hits: &HashSet<String>,
+                   unmatched: &[(String, String, usize)])
+                   -> Vec<Row> {
+    let mut rows: BTreeMap<(String, String), Row> = BTreeMap::new();
+    for ann in annotations.iter() {
+        let key = (ann.checker.clone(), ann.binary.clone());
+        let row = rows.entry(key).or_insert_with(|| Row::new(&ann.checker, &checker_cwe(&ann.checker), None, Some(&ann.binary)));
+        match (ann.bad, ann.bad && bad_hits.contains(&ann.label) || !ann.bad && good_hits.contains(&ann.label)) {
+            (true, true) => row.true_pos += 1,
+            (true, false) => row.false_neg += 1,
+            (false, true) => row.false_pos += 1,
+            (false, false) => row.true_neg += 1,
+        }
+    }
+    for &(ref checker, ref path, count) in unmatched.iter() {
+        let found = rows.iter_mut().find(|&(&(ref c, ref binary), _)| c == checker && Path::new(path).ends_with(binary));
+        if let Some((_, row)) = found {
+            row.false_pos += count;
+        }
+    }
+    rows.into_iter().map(|(_, row)| row).collect()
+}
+
+fn ratio(r: Option<f64>) -> String {
+    r.map_or("-".to_string(), |r| format!("{:.2}", r))
+}
+
+/// The variant a row scores, or failing that its binary
+fn scored(row: &Row) -> String {
+    match (row.variant, row.binary.as_ref()) {
+        (Some(variant), _) => format!("{:02}", variant),
+        (None, Some(binary)) => binary.clone(),
+        (None, None) => "-".to_string(),
+    }
+}
+
+/// Renders rows as a table, with a total after each checker and CWE
+pub fn table(rows: &[Row]) -> String {
+    let mut out = String::new();
+    writeln!(out, "{:<8} {:<16} {:>7} {:>5} {:>5} {:>5} {:>5} {:>9} {:>6}",
+             "checker", "cwe", "variant", "TP", "FP", "FN", "TN", "precision", "recall").unwrap();
+    let line = |out: &mut String, row: &Row, variant: &str| {
+        writeln!(out, "{:<8} {:<16} {:>7} {:>5} {:>5} {:>5} {:>5} {:>9} {:>6}",
+                 row.checker, row.cwe, variant, row.true_pos, row.false_pos, row.false_neg, row.true_neg,
+                 ratio(row.precision()), ratio(row.recall())).unwrap();
+    };
+    let mut total: Option<Row> = None;
+    for row in rows.iter() {
+        if let Some(t) = total.take() {
+            if t.checker == row.checker && t.cwe == row.cwe {
+                total = Some(t);
+            } else {
+                line(&mut out, &t, "all");
+            }
+        }
+        line(&mut out, row, &scored(row));
+        match total {
+            Some(ref mut t) => t.add(row),
+            None => total = Some(row.clone()),
+        }
+    }
+    if let Some(t) = total {
+        line(&mut out, &t, "all");
+    }
+    out
+}
+
+/// Ways the current rows are worse than a baseline: fewer true positives, more false positives,
+/// or a variant or binary the baseline scored that is now missing
+pub fn regressions(baseline: &[Row], current: &[Row]) -> Vec<String> {
+    let mut out = Vec::new();
+    for base in baseline.iter() {
+        let cur = current.iter().find(|r| {
+            r.checker == base.checker && r.cwe == base.cwe && r.variant == base.variant && r.binary == base.binary
+        });
+        let at = match base.binary {
+            Some(ref binary) => format!("{} {} binary {}", base.checker, base.cwe, binary),
+            None => format!("{} {} variant {}", base.checker, base.cwe, scored(base)),
+        };
+        match cur {
+            None => out.push(format!("{}: no longer scored", at)),
+            Some(cur) => {
+                if cur.true_pos < base.true_pos {
+                    out.push(format!("{}: true positives {} -> {}", at, base.true_pos, cur.true_pos));
+                }
+                if cur.false_pos > base.false_pos {
+                    out.push(format!("{}: false positives {} -> {}", at, base.false_pos, cur.false_pos));
+                }
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,57 @@
+use bap::high::bitvector::BitVector;
+use sha2::{Sha256, Digest};
+
+// Fact identifiers derived from content, so that two runs over the same inputs produce the same
+// IDs regardless of load order, process, or compiler version.
+
+pub fn digest(bytes: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::default();
+    hasher.input(bytes);
+    hasher.result().to_vec()
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Binaries are identified by the SHA-256 of their contents, so the same file loaded under two
+// paths is one binary, and two builds at one path are two.
+pub fn binary_id(contents: &[u8]) -> String {
+    hex(&digest(contents))
+}
+
+fn truncate(hash: &[u8]) -> u64 {
+    hash[..8].iter().fold(0, |acc, b| (acc << 8) | (*b as u64))
+}
+
+fn input_u64(hasher: &mut Sha256, v: u64) {
+    let mut buf = [0; 8];
+    for i in 0..8 {
+        buf[i] = (v >> (56 - 8 * i)) as u8;
+    }
+    hasher.input(&buf);
+}
+
+// Length-prefixed so adjacent fields can't run into each other
+fn input_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    input_u64(hasher, bytes.len() as u64);
+    hasher.input(bytes);
+}
+
+pub fn segment_id(file_digest: &[u8], index: u64) -> u64 {
+    let mut hasher = Sha256::default();
+    input_bytes(&mut hasher, file_digest);
+    input_u64(&mut hasher, index);
+    truncate(&hasher.result())
+}
+
+// prev is itself the ID of the rest of the chain, so this commits to every frame below it
+pub fn stack_id((prev, bin, addr): (&u64, &String, &BitVector)) -> u64 {
+    let mut hasher = Sha256::default();
+    input_u64(&mut hasher, *prev);
+    input_bytes(&mut hasher, bin.as_bytes());
+    let bits = addr.to_bitvec();
+    input_u64(&mut hasher, bits.len() as u64);
+    input_bytes(&mut hasher, &bits.to_bytes());
+    truncate(&hasher.result())
+}
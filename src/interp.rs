@@ -0,0 +1,219 @@
+use bap::basic::Endian;
+use bap::high::bil::{Statement, Expression, Type};
+use bap::high::bitvector::BitVector;
+use num::{BigUint, ToPrimitive};
+use std::collections::HashMap;
+use arith;
+use sema::Sema;
+
+// A concrete interpreter for lifted blocks. It is the reference the abstract transfer functions
+// in analyses are meant to over-approximate, so it follows BIL to the letter rather than
+// guessing at anything it wasn't given: reading a register or byte nobody set is a fault.
+
+// Iterations a single while loop may take, since rep-prefixed instructions lift to loops
+const MAX_ITERS: usize = 1 << 16;
+
+/// Why a concrete run stopped early
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    UnsetReg(String),
+    UnsetMem(u64),
+    DivZero,
+    // A while loop ran past MAX_ITERS
+    Diverged,
+    Unsupported(String),
+}
+
+/// Where control goes once a block has run
+#[derive(Debug, Clone, PartialEq)]
+pub enum Next {
+    Fall,
+    Jump(BitVector),
+}
+
+/// One load or store, in the order the block performed them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Access {
+    pub addr: u64,
+    pub bytes: usize,
+    pub write: bool,
+}
+
+/// Register file (flags being the 1-bit registers) and sparse byte-addressed memory
+#[derive(Debug, Clone, Default)]
+pub struct Machine {
+    regs: HashMap<String, BitVector>,
+    mem: HashMap<u64, u8>,
+    pub accesses: Vec<Access>,
+}
+
+fn big(endian: &Endian) -> bool {
+    match *endian {
+        Endian::Big => true,
+        _ => false,
+    }
+}
+
+fn to_addr(bv: &BitVector) -> u64 {
+    // Addresses are 64 bits on everything we lift
+    bv.unum().to_u64().unwrap_or(0)
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Machine::default()
+    }
+    pub fn reg(&self, name: &str) -> Option<&BitVector> {
+        self.regs.get(name)
+    }
+    pub fn set_reg(&mut self, name: &str, val: BitVector) {
+        self.regs.insert(name.to_string(), val);
+    }
+    pub fn flag(&self, name: &str) -> Option<bool> {
+        self.regs.get(name).map(arith::is_true)
+    }
+    pub fn set_flag(&mut self, name: &str, set: bool) {
+        let val = if set { 1 } else { 0 };
+        self.set_reg(name, BitVector::from_u64(val, 1));
+    }
+    /// Makes bytes readable at addr, without recording an access
+    pub fn map(&mut self, addr: u64, bytes: &[u8]) {
+        for (i, b) in bytes.iter().enumerate() {
+            self.mem.insert(addr.wrapping_add(i as u64), *b);
+        }
+    }
+    pub fn byte(&self, addr: u64) -> Option<u8> {
+        self.mem.get(&addr).cloned()
+    }
+    pub fn read(&mut self, addr: u64, bytes: usize, endian: &Endian) -> Result<BitVector, Fault> {
+        let mut raw = Vec::with_capacity(bytes);
+        for i in 0..bytes as u64 {
+            match self.byte(addr.wrapping_add(i)) {
+                Some(b) => raw.push(b),
+                None => return Err(Fault::UnsetMem(addr.wrapping_add(i))),
+            }
+        }
+        if big(endian) {
+            raw.reverse();
+        }
+        self.accesses.push(Access { addr: addr, bytes: bytes, write: false });
+        Ok(BitVector::new_unsigned(BigUint::from_bytes_le(&raw), bytes * 8))
+    }
+    pub fn write(&mut self, addr: u64, val: &BitVector, endian: &Endian) {
+        let bytes = (arith::width(val) + 7) / 8;
+        let mut raw = val.unum().to_bytes_le();
+        raw.resize(bytes, 0);
+        if big(endian) {
+            raw.reverse();
+        }
+        self.map(addr, &raw);
+        self.accesses.push(Access { addr: addr, bytes: bytes, write: true });
+    }
+    pub fn eval(&mut self, e: &Expression) -> Result<BitVector, Fault> {
+        self.eval_in(e, &mut Vec::new())
+    }
+    fn eval_in(&mut self, e: &Expression, lets: &mut Vec<(String, BitVector)>) -> Result<BitVector, Fault> {
+        Ok(match *e {
+            Expression::Var(ref v) => {
+                if let Some(&(_, ref val)) = lets.iter().rev().find(|&&(ref name, _)| *name == v.name) {
+                    return Ok(val.clone());
+                }
+                match self.regs.get(&v.name) {
+                    Some(val) => val.clone(),
+                    None => return Err(Fault::UnsetReg(v.name.clone())),
+                }
+            }
+            Expression::Const(ref bv) => bv.clone(),
+            Expression::Load { ref index, ref endian, size, .. } => {
+                let addr = to_addr(&self.eval_in(index, lets)?);
+                self.read(addr, size as usize / 8, endian)?
+            }
+            Expression::BinOp { op, ref lhs, ref rhs } => {
+                let l = self.eval_in(lhs, lets)?;
+                let r = self.eval_in(rhs, lets)?;
+                match arith::binop(op, &l, &r) {
+                    Some(val) => val,
+                    None => return Err(Fault::DivZero),
+                }
+            }
+            Expression::UnOp { op, ref arg } => arith::unop(op, &self.eval_in(arg, lets)?),
+            Expression::Cast { kind, width, ref arg } => arith::cast(kind, width, &self.eval_in(arg, lets)?),
+            Expression::Let { ref bind, ref value, ref body } => {
+                let val = self.eval_in(value, lets)?;
+                lets.push((bind.name.clone(), val));
+                let res = self.eval_in(body, lets);
+                lets.pop();
+                res?
+            }
+            Expression::IfThenElse { ref cond, ref true_expr, ref false_expr } => {
+                if arith::is_true(&self.eval_in(cond, lets)?) {
+                    self.eval_in(true_expr, lets)?
+                } else {
+                    self.eval_in(false_expr, lets)?
+                }
+            }
+            Expression::Extract { low_bit, high_bit, ref arg } => {
+                arith::extract(low_bit as usize, high_bit as usize, &self.eval_in(arg, lets)?)
+            }
+            Expression::Concat { ref low, ref high } => {
+                let h = self.eval_in(high, lets)?;
+                let l = self.eval_in(low, lets)?;
+                arith::concat(&h, &l)
+            }
+            _ => return Err(Fault::Unsupported(format!("{:?}", e))),
+        })
+    }
+    fn step(&mut self, stmt: &Statement) -> Result<Option<BitVector>, Fault> {
+        match *stmt {
+            Statement::Move { ref lhs, rhs: Expression::Store { ref index, ref value, ref endian, .. } } => {
+                if let Type::Immediate(_) = lhs.type_ {
+                    return Err(Fault::Unsupported(format!("{:?}", stmt)));
+                }
+                let addr = to_addr(&self.eval(index)?);
+                let val = self.eval(value)?;
+                self.write(addr, &val, endian);
+            }
+            Statement::Move { ref lhs, ref rhs } => {
+                if let Type::Memory { .. } = lhs.type_ {
+                    return Err(Fault::Unsupported(format!("{:?}", stmt)));
+                }
+                let val = self.eval(rhs)?;
+                self.regs.insert(lhs.name.clone(), val);
+            }
+            Statement::Jump(ref tgt) => return self.eval(tgt).map(Some),
+            Statement::IfThenElse { ref cond, ref then_clause, ref else_clause } => {
+                let clause = if arith::is_true(&self.eval(cond)?) { then_clause } else { else_clause };
+                return self.exec(clause).map(|next| match next {
+                    Next::Jump(tgt) => Some(tgt),
+                    Next::Fall => None,
+                });
+            }
+            Statement::While { ref cond, ref body } => {
+                let mut iters = 0;
+                while arith::is_true(&self.eval(cond)?) {
+                    if iters == MAX_ITERS {
+                        return Err(Fault::Diverged);
+                    }
+                    iters += 1;
+                    if let Next::Jump(tgt) = self.exec(body)? {
+                        return Ok(Some(tgt));
+                    }
+                }
+            }
+            _ => return Err(Fault::Unsupported(format!("{:?}", stmt))),
+        }
+        Ok(None)
+    }
+    /// Runs stmts up to the first jump taken
+    pub fn exec(&mut self, stmts: &[Statement]) -> Result<Next, Fault> {
+        for stmt in stmts.iter() {
+            if let Some(tgt) = self.step(stmt)? {
+                return Ok(Next::Jump(tgt));
+            }
+        }
+        Ok(Next::Fall)
+    }
+    pub fn run(&mut self, sema: &Sema) -> Result<Next, Fault> {
+        self.exec(&sema.stmts)
+    }
+}
@@ -12,8 +12,11 @@ use holmes::simple::*;
 extern crate log;
 
 extern crate mktemp;
+extern crate url;
+extern crate sha2;
 
 use holmes::pg::dyn::values::LargeBWrap;
+use holmes::pg::dyn::Value;
 use bap::high::bitvector::BitVector;
 
 mod analyses;
@@ -23,28 +26,117 @@ pub mod bvlist;
 pub mod sema;
 pub mod var;
 pub mod chop;
+pub mod budget;
+pub mod store;
+pub mod db;
+pub mod ids;
+pub mod elf;
+pub mod lift_cache;
+pub mod lib_summary;
+pub mod arith;
+pub mod vsa;
+pub mod cond;
+pub mod symex;
+pub mod interp;
+pub mod emu;
+pub mod asan;
+pub mod grade;
+pub mod truth;
+pub mod signature;
+pub mod symbols;
+pub mod suppress;
+pub mod transfer;
 use chop::Chop;
+use cond::Cond;
+use sema::Sema;
+use budget::{Budgets, FactCounter, Limits, Stage, Tracker};
+use lift_cache::LiftCache;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+fn known_binaries(holmes: &mut Engine) -> Result<Vec<String>> {
+    Ok(query!(holmes, binary(id, [_], [_], [_]))?
+        .into_iter()
+        .map(|row| row[0].get().downcast_ref::<String>().unwrap().clone())
+        .collect())
+}
+
 pub fn load_files(holmes: &mut Engine, in_paths: &[String]) -> Result<()> {
-    let mut ins = Vec::new();
+    let mut known = known_binaries(holmes)?;
     for in_path in in_paths {
         use std::io::Read;
         let mut in_raw = Vec::new();
         let mut in_file = std::fs::File::open(&in_path).unwrap();
         in_file.read_to_end(&mut in_raw).unwrap();
-        ins.push((in_path, LargeBWrap { inner: in_raw }))
+        load_binary(holmes, &mut known, in_path.clone(), in_raw)?
     }
-    for (in_path, in_bin) in ins {
-        let in_path_owned = in_path.clone();
-        fact!(holmes, file(in_path_owned, in_bin))?
+    Ok(())
+}
+
+// Loads the ELF members of each deb_file as binaries of their own, through the same single
+// image parse as load_files
+pub fn load_debs(holmes: &mut Engine) -> Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut known = known_binaries(holmes)?;
+    for row in query!(holmes, deb_file([_], deb_bin))? {
+        let mut buf = Vec::new();
+        {
+            let mut fd: &std::fs::File = row[0].get().downcast_ref::<std::fs::File>().unwrap();
+            fd.seek(SeekFrom::Start(0)).unwrap();
+            fd.read_to_end(&mut buf).unwrap();
+        }
+        for (name, member) in analyses::unpack_deb(&buf) {
+            load_binary(holmes, &mut known, name, member)?
+        }
     }
     Ok(())
 }
 
+fn load_binary(holmes: &mut Engine, known: &mut Vec<String>, path: String, contents: Vec<u8>) -> Result<()> {
+    let id = ids::binary_id(&contents);
+    // Identical content is the same binary, whatever path it came in by
+    if known.contains(&id) {
+        info!("{} already loaded as {}", path, id);
+        return Ok(());
+    }
+    known.push(id.clone());
+    let size = contents.len() as u64;
+    let build_id = elf::build_id(&contents).unwrap_or(String::new());
+    let image = analyses::ingest(&contents);
+    {
+        let id = id.clone();
+        fact!(holmes, binary(id, path, size, build_id))?;
+    }
+    match image {
+        Some(image) => {
+            let image_arch = image.arch;
+            {
+                let id = id.clone();
+                fact!(holmes, arch(id, image_arch))?;
+            }
+            for (seg_id, data, start, end, r, w, x) in image.segments {
+                let id = id.clone();
+                let seg_contents = LargeBWrap { inner: data };
+                fact!(holmes, segment(id, seg_id, seg_contents, start, end, r, w, x))?
+            }
+            for (sym_name, addr, end) in image.symbols {
+                let id = id.clone();
+                fact!(holmes, entry(id, sym_name, addr, end))?
+            }
+            for (func_name, addr) in image.pads {
+                let id = id.clone();
+                fact!(holmes, link_pad(id, func_name, addr))?
+            }
+        }
+        None => warn!("{} ({}) could not be parsed as an image", path, id),
+    }
+    let in_bin = LargeBWrap { inner: contents };
+    fact!(holmes, file(id, in_bin))
+}
+
 // Feature summary:
-// MULTI: Will buy us low fixed factor speedups throughout
-// * Most of the gain could be found by merging is_call, lift, disas, and is_ret into a single
-// record, since all properties are set on the output of the lifter
-// * Gain that couldn't be gotten that way is mostly arch + segs, which are literally one-time deals
+// MULTI: Done
+// * is_call, lift, disas, and is_ret are a single record, produced by one decode per instruction
+// * arch, segments, symbols and pads come out of a single image parse in load_files
 // DBPRED: Will buy us next to nothing
 // * Only spot where this is useful at the moment is for looking up data in segments
 // * Analyzed binary groups have normally had no more than 6 segments, this isn't a scaling situation
@@ -53,96 +145,134 @@ pub fn load_files(holmes: &mut Engine, in_paths: &[String]) -> Result<()> {
 // * We currently _rarely_ access segments - just for ro string detection and once per instruction
 // to lift, so no more than once per offset of the binary
 // * If we ever add an executor which reads memory, this will become important
-// FACT_ID: Minor performance improvement, medium code cleanliness and repeatability improvement
-// * Segment ID becomes nondet if you load multiple binaries into the database, since order is not
-// gauranteed
-// * Using a hash for trace and stack IDs is icky
+// FACT_ID: Minor performance improvement, medium code cleanliness improvement
+// * Segment and stack IDs are content hashes (see ids), so they are stable, but still not the
+// real fact IDs
 // * While we have an index that will make lookup by trace_id fast, lookup by their literal primary
 // key will be faster.
-// SHINGLE: Medium speed improvement, minor accuracy improvement?
+// SHINGLE: Medium speed improvement, minor accuracy improvement? Opt-in via Pipeline::shingle
 // * Fewer jumps into bap
 // * No changes to Holmes required
 // * Avoid prematurely merging variable state sets (as often)
 // * Shorter traces
-pub fn basic_setup(holmes: &mut Engine) -> Result<()> {
+// Addresses lifting gives up on are noted in errors, see record_lift_errors. Shingling notes
+// none: most offsets it decodes at aren't instructions, so failing there says nothing.
+pub fn basic_setup(holmes: &mut Engine, budget: &FactCounter, errors: &LiftErrors, cache: Option<LiftCache>, shingle: bool) -> Result<()> {
     holmes_exec!(holmes, {
-        // MULTI, low fixed cost
-        func!(let get_arch_val : largebytes -> [uint64] = analyses::get_arch_val);
-        // MULTI, low fixed cost
-        // FACT_ID, small nondet
-        func!(let seg_wrap : largebytes -> [(largebytes, uint64, bitvector, bitvector, bool, bool, bool)] = analyses::seg_wrap);
-        func!(let find_succs : (sema, bitvector) -> [bitvector] = analyses::successors);
+        func!(let lift_budget : uint64 -> bool = budget.func());
+        func!(let find_succs : (sema, bitvector) -> [(bitvector, cond)] = analyses::successors);
         func!(let find_succs_upper : (sema, bitvector) -> ubvs = analyses::succ_wrap_upper);
-        // MULTI, low fixed cost
-        func!(let find_syms  : bytes -> [bitvector] = analyses::sym_wrap);
-        // SHINGLE MULTI, medium variable cost
-        // Shingling will change it to just a 4x cost
-        func!(let lift : (arch, bitvector, largebytes, uint64) -> [(sema, bitvector, string, bool, bool)] = analyses::lift_wrap);
-        // SHINGLE MULTI, medium variable cost
-        // Shingling will change it to just a 4x cost
-        func!(let disas : (arch, bitvector, largebytes, uint64) -> [string] = analyses::disas_wrap);
+        // SHINGLE, medium variable cost
+        // Not used when shingling, which decodes each offset exactly once up front
+        func!(let lift : (string, arch, bitvector, largebytes, uint64, uint64) -> [(sema, bitvector, string, bool, bool)] = analyses::lift_wrap(cache.clone(), errors.clone()));
         // DBPRED Could be eliminated with builtin lt predicate
-        func!(let rebase : (bitvector, bitvector, bitvector, uint64) -> [(uint64, uint64)] = analyses::rebase);
-        // Should be replaced with native impl
-        func!(let find_pads : largebytes -> [(string, bitvector)] = analyses::get_pads);
+        func!(let rebase : (bitvector, bitvector, bitvector) -> [(uint64, uint64)] = analyses::rebase);
         func!(let is_ret_reg : var -> bool = |v: &var::HVar| v == &var::get_ret());
-        func!(let unpack_deb : largebytes -> [(string, largebytes)] = analyses::unpack_deb);
         func!(let is_computed_jump : sema -> bool = analyses::is_computed_jump);
-        rule!(bap_dump_segments: segment(name, id, seg_contents, start, end, r, w, x) <= file(name, file_contents), {
-        let [ {id, seg_contents, start, end, r, w, x} ] = {seg_wrap([file_contents])}
-      });
-        rule!(objdump_dump_plt: link_pad(bin_name, func_name, addr)  <= file(bin_name, contents), {
-        let [ {func_name, addr} ] = {find_pads([contents])}
-      });
-        rule!(bap_dump_syms: entry(name, sym_name, addr, end) <= file(name, in_bin), {
-        let [ {sym_name, addr, end} ] = {find_syms([in_bin])}
-      });
         rule!(entries_live: live(name, addr) <= entry(name, [_], addr, [_]));
         rule!(segment_offsets: seglive(name, id, addr, start, end) <= live(name, addr) & segment(name, id, [_], seg_start, seg_end, [_], [_], [_]), {
-        let [ {start, end} ] = {rebase([seg_start], [seg_end], [addr], (16))}
+        let [ {start, end} ] = {rebase([seg_start], [seg_end], [addr])}
       });
-        rule!(bap_sema: lift {
-            binary = name,
-            address = addr,
-            bil = sema,
-            disassembly = disasm,
-            fallthrough = fall,
-            is_call = call,
-            is_ret = ret} <= seglive(name, id, addr, start, end) & segment(name, id, bin, [_], [_], [_], [_], [_]) & arch(name, arch), {
-         let [ {sema, fall, disasm, call, ret} ] = {lift([arch], [addr], [bin], [start])}
+        rule!(sema_succ: succ(name, src, sink, c, cond) <= lift {binary = name, address = src, bil = sema, fallthrough = fall, is_call = c}, {
+        let [ {sink, cond} ] = {find_succs([sema], [fall])}
       });
-        rule!(sema_succ: succ(name, src, sink, c) <= lift {binary = name, address = src, bil = sema, fallthrough = fall, is_call = c}, {
-        let [ sink ] = {find_succs([sema], [fall])}
-      });
-        rule!(skip_computed_calls: succ(name, src, fall, (false)) <= lift { binary = name, address = src, bil = sema, fallthrough = fall, is_call = (true)}, {
+        rule!(skip_computed_calls: succ(name, src, fall, (false), (Cond::new())) <= lift { binary = name, address = src, bil = sema, fallthrough = fall, is_call = (true)}, {
             let (true) = {is_computed_jump([sema])}
         });
         rule!(live_succ_live: live(name, sink) <= succ(name, src, sink, [_]));
         rule!(live_call_live: live(name, fall) <= lift { binary = name, address = src, fallthrough = fall, is_call = (true)});
-        rule!(bap_arch: arch(name, arch) <= file(name, contents), {
-        let [ arch ] = {get_arch_val([contents])}
-      });
 
         // FACT May be better implemented via a pre-populated predicate
         func!(let is_free_name : string -> bool = |s : &String| (s == "free") || (s == "qfree") || (s == "g_free"));
         // FACT May be better implemented via a pre-populated predicate
-        func!(let is_malloc_name : string -> bool = |s : &String| (s.contains("malloc")) || (s.contains("calloc")));
+        // GLib's container constructors count too, so call_keeps has an allocation to hang what
+        // the container holds off of
+        func!(let is_malloc_name : string -> bool = |s : &String| (s.contains("malloc")) || (s.contains("calloc")) || (s.contains("realloc")) || (s.starts_with("g_hash_table_new")) || (s.starts_with("g_ptr_array_new")));
         rule!(free_call_by_name: free_call(name, addr) <= link_pad(name, func_name, tgt) & succ(name, addr, tgt, (true)), {
             let (true) = {is_free_name([func_name])}
         });
         rule!(malloc_call_by_name: malloc_call(name, addr) <= link_pad(name, func_name, tgt) & succ(name, addr, tgt, (true)), {
             let (true) = {is_malloc_name([func_name])}
         });
-        rule!(puts_hack: func_uses(name, addr, (var::get_arg0())) <= link_pad(name, ("puts"), tgt) & succ(name, addr, tgt, (true)));
         rule!(skip_malloc: skip_func(name, addr) <= malloc_call(name, addr));
         rule!(skip_free: skip_func(name, addr) <= free_call(name, addr));
         //TODO This would be a place we want to circumscribe - we want to step over any function
         //that isn't present, but not the ones we have loaded up.
-        rule!(skip_dyn: skip_func(name, addr) <= link_pad(name, [_], tgt) & succ(name, addr, tgt, (true)));
-       rule!(unpack_deb: file(path, bin) <= deb_file([_], deb_bin), {
-            let [ {path, bin} ] = {unpack_deb([deb_bin])}
-        })
+        rule!(skip_dyn: skip_func(name, addr) <= link_pad(name, [_], tgt) & succ(name, addr, tgt, (true)))
     })?;
+    if shingle {
+        holmes_exec!(holmes, {
+            func!(let shingle : (arch, largebytes, bitvector) -> [(bitvector, bool, sema, bitvector, string, bool, bool, bool)] = analyses::shingle_wrap(cache.clone()));
+            func!(let append_insn : (sema, string, sema, string) -> [(sema, string)] = analyses::append_insn);
+            func!(let falls_through : bool -> bool = |may_jump: &bool| !*may_jump);
+            rule!(shingle_segment: shingle {
+                binary = name,
+                address = addr,
+                decoded = ok,
+                bil = sema,
+                fallthrough = fall,
+                disassembly = disasm,
+                is_call = call,
+                is_ret = ret,
+                may_jump = jump} <= segment(name, [_], bin, start, [_], [_], [_], (true)) & arch(name, arch), {
+             let [ {addr, ok, sema, fall, disasm, call, ret, jump} ] = {shingle([arch], [bin], [start])}
+          });
+            // Liveness just selects among the shingles, no trip back to BAP needed. The block at
+            // a live address grows one shingle at a time and is split the way lift_wrap splits
+            // it: after a jump, before a call or return, and before bytes that don't decode.
+            rule!(shingle_block_start: shingle_block(name, addr, sema, disasm, fall, call, ret, open) <= live(name, addr) & shingle {
+                binary = name,
+                address = addr,
+                decoded = (true),
+                bil = sema,
+                fallthrough = fall,
+                disassembly = disasm,
+                is_call = call,
+                is_ret = ret,
+                may_jump = jump}, {
+             let open = {falls_through([jump])}
+          });
+            rule!(shingle_block_grow: shingle_block(name, addr, sema2, disasm2, fall, (false), (false), open) <= shingle_block(name, addr, sema, disasm, next, [_], [_], (true)) & shingle {
+                binary = name,
+                address = next,
+                decoded = (true),
+                bil = insn,
+                fallthrough = fall,
+                disassembly = insn_disasm,
+                is_call = (false),
+                is_ret = (false),
+                may_jump = jump}, {
+             let [ {sema2, disasm2} ] = {append_insn([sema], [disasm], [insn], [insn_disasm])};
+             let open = {falls_through([jump])}
+          });
+            rule!(shingle_sema_jump: lift(name, addr, sema, fall, disasm, call, ret) <= shingle_block(name, addr, sema, disasm, fall, call, ret, (false)), {
+             let (true) = {lift_budget((0))}
+          });
+            rule!(shingle_sema_undecodable: lift(name, addr, sema, fall, disasm, call, ret) <= shingle_block(name, addr, sema, disasm, fall, call, ret, (true)) & shingle {binary = name, address = fall, decoded = (false)}, {
+             let (true) = {lift_budget((0))}
+          });
+            rule!(shingle_sema_call: lift(name, addr, sema, fall, disasm, call, ret) <= shingle_block(name, addr, sema, disasm, fall, call, ret, (true)) & shingle {binary = name, address = fall, is_call = (true)}, {
+             let (true) = {lift_budget((0))}
+          });
+            rule!(shingle_sema_ret: lift(name, addr, sema, fall, disasm, call, ret) <= shingle_block(name, addr, sema, disasm, fall, call, ret, (true)) & shingle {binary = name, address = fall, is_ret = (true)}, {
+             let (true) = {lift_budget((0))}
+          })
+        })?;
+    } else {
+        holmes_exec!(holmes, {
+            rule!(bap_sema: lift {
+                binary = name,
+                address = addr,
+                bil = sema,
+                disassembly = disasm,
+                fallthrough = fall,
+                is_call = call,
+                is_ret = ret} <= seglive(name, id, addr, start, end) & segment(name, id, bin, [_], [_], [_], [_], [_]) & arch(name, arch), {
+             let (true) = {lift_budget([start])};
+             let [ {sema, fall, disasm, call, ret} ] = {lift([name], [arch], [addr], [bin], [start], [end])}
+          })
+        })?;
+    }
     Ok(())
 }
 
@@ -157,29 +287,47 @@ pub fn setup_stage2(holmes: &mut Engine) -> Result<()> {
     })
 }
 
-pub fn uaf_stage1(holmes: &mut Engine) -> Result<()> {
+pub fn uaf_stage1(holmes: &mut Engine, budget: &FactCounter, summaries: bool, limits: &Limits) -> Result<()> {
+    let max_chop = limits.chop;
     holmes_exec!(holmes, {
+        func!(let flow_budget : uint64 -> bool = budget.func());
         func!(let xfer_taint : (sema, var) -> [var] = analyses::xfer_taint);
         func!(let deref_var : (sema, var) -> bool = analyses::deref_var);
-        func!(let stack_len_inc : uint64 -> [ uint64 ] = analyses::stack_len_inc);
+        func!(let stack_len_inc : uint64 -> [ uint64 ] = analyses::stack_len_inc(limits.stack_depth));
+        func!(let stack_full : uint64 -> bool = analyses::len_at_limit(limits.stack_depth));
         func!(let heap_init : sema -> [var] = analyses::heap_init);
-        // This function is a bit unholy, and is working around the lack of ability to refer to
-        // FactIds in the language itself.
-        // FACTID nondet across revisions of compiler, perf hit, harder to read output
-        func!(let hashify : (uint64, string, bitvector) -> uint64 = analyses::hashify);
-        func!(let chop_check: (chop, bitvector) -> [chop] = |(chop, func): (&Chop, &BitVector)| chop.check(func));
+        func!(let store_cells : (sema, var, var, string, bitvector) -> [var] = analyses::store_cells);
+        func!(let cell_in : (var, string, bitvector) -> bool = analyses::cell_in);
+        func!(let kept_cell : (string, bitvector) -> var = analyses::kept_cell);
+        func!(let load_cells : (sema, var, var) -> [var] = analyses::load_cells);
+        func!(let load_cells_via : (sema, var, var, var) -> [var] = analyses::load_cells_via);
+        func!(let cell_derefs : (sema, var, var) -> bool = analyses::cell_derefs);
+        func!(let cell_derefs_via : (sema, var, var, var) -> bool = analyses::cell_derefs_via);
+        // This is working around the lack of ability to refer to FactIds in the language itself.
+        // FACT_ID perf hit, harder to read output
+        func!(let stack_id : (uint64, string, bitvector) -> uint64 = ids::stack_id);
+        func!(let chop_check: (chop, bitvector) -> [chop] = move |(chop, func): (&Chop, &BitVector)| chop.check(func, max_chop));
+        func!(let chop_full: (chop, bitvector) -> bool = move |(chop, func): (&Chop, &BitVector)| chop.check(func, max_chop).is_empty());
 
-        rule!(flow_start: path_alias(src_name, addr, (0), (0), (Chop::new()), src_name, step, (var::get_ret()), (false)) <= malloc_call(src_name, addr) & lift(src_name, addr, [_], step));
+        rule!(flow_start: path_alias(src_name, addr, (0), (0), (Chop::new()), src_name, step, (var::get_ret()), (false)) <= malloc_call(src_name, addr) & lift(src_name, addr, [_], step), {
+            let (true) = {flow_budget((0))}
+        });
         rule!(flow_start_heap: path_alias(src_name, addr, sa, (0), (Chop::new()), src_name, step, heap_var, (false)) <= lift(src_name, addr, sema, step), {
-            let [ {sa, [heap_var]} ] = {heap_init([sema])}
+            let [ {sa, [heap_var]} ] = {heap_init([sema])};
+            let (true) = {flow_budget([sa])}
+        });
+        rule!(flow_free: path_alias(src_name, src, sa, stack, chop, free_name, next, af, (true)) <= path_alias(src_name, src, sa, stack, chop, free_name, free_addr, af, [_]) & path_alias(src_name, src, sa, stack, chop, free_name, free_addr, (var::get_arg0()), [_]) & free_call(free_name, free_addr) & lift(free_name, free_addr, [_], next), {
+            let (true) = {flow_budget([stack])}
         });
-        rule!(flow_free: path_alias(src_name, src, sa, stack, chop, free_name, next, af, (true)) <= path_alias(src_name, src, sa, stack, chop, free_name, free_addr, af, [_]) & path_alias(src_name, src, sa, stack, chop, free_name, free_addr, (var::get_arg0()), [_]) & free_call(free_name, free_addr) & lift(free_name, free_addr, [_], next));
         // TODO THIS CANNOT EXIST IN NORMAL CODE - IT WILL MAKE FREE FREE THE CONTENTS OF RSI,
         // WHICH WERE NOT PASSED TO IT
-        rule!(flow_free_2_hack: path_alias(src_name, src, sa, stack, chop, free_name, next, af, (true)) <= path_alias(src_name, src, sa, stack, chop, free_name, free_addr, af, [_]) & path_alias(src_name, src, sa, stack, chop, free_name, free_addr, (var::get_arg_n(1)), [_]) & free_call(free_name, free_addr) & lift(free_name, free_addr, [_], next));
+        rule!(flow_free_2_hack: path_alias(src_name, src, sa, stack, chop, free_name, next, af, (true)) <= path_alias(src_name, src, sa, stack, chop, free_name, free_addr, af, [_]) & path_alias(src_name, src, sa, stack, chop, free_name, free_addr, (var::get_arg_n(1)), [_]) & free_call(free_name, free_addr) & lift(free_name, free_addr, [_], next), {
+            let (true) = {flow_budget([stack])}
+        });
         // If there's a successor, follow that and transfer taint (but not if it's a call)
         rule!(flow_prop: path_alias(name, src, sa, stack, chop, cur_name, fut, var2, t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, var, t) & lift(cur_name, cur, sema, [_]) & succ(cur_name, cur, fut, (false)), {
-          let [ var2 ] = {xfer_taint([sema], [var])}
+          let [ var2 ] = {xfer_taint([sema], [var])};
+          let (true) = {flow_budget([stack])}
       });
 
         // Normally, if we don't have the function present, we have to stop the path analysis
@@ -188,62 +336,225 @@ pub fn uaf_stage1(holmes: &mut Engine) -> Result<()> {
         // TODO do we need to xfer taint here? Maybe omit
         rule!(flow_skip_func: path_alias(name, src, sa, stack, chop, cur_name, fall, var2, t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, var, t) & skip_func(cur_name, cur) & lift(cur_name, cur, sema, fall) , {
           let [ var2 ] = {xfer_taint([sema], [var])};
-          let (false) = {is_ret_reg([var2])}
+          let (false) = {is_ret_reg([var2])};
+          let (true) = {flow_budget([stack])}
       });
 
+        // Field-sensitive heap: storing a tracked pointer into a field of another allocation adds
+        // that allocation site and field as an alias, and loading the field back out through any
+        // alias of the same site recovers it. Loads are followed two deep within a block, so
+        // a->next->data works even when a is the only thing live at the start of it. The object's
+        // aliases must be in the same calling context as the tracked pointer, or a register
+        // holding the object in one caller would be taken for it in another.
+        rule!(flow_heap_store: path_alias(name, src, sa, stack, chop, cur_name, fut, cell, t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, var, t) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, base, [_]) & lift(cur_name, cur, sema, [_]) & succ(cur_name, cur, fut, (false)), {
+            let [ cell ] = {store_cells([sema], [var], [base], [obj_name], [obj_src])};
+            let (true) = {flow_budget([stack])}
+        });
+        rule!(flow_heap_load: path_alias(name, src, sa, stack, chop, cur_name, fut, var, t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, cell, t) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, base, [_]) & lift(cur_name, cur, sema, [_]) & succ(cur_name, cur, fut, (false)), {
+            let (true) = {cell_in([cell], [obj_name], [obj_src])};
+            let [ var ] = {load_cells([sema], [cell], [base])};
+            let (true) = {flow_budget([stack])}
+        });
+        rule!(flow_heap_load_via: path_alias(name, src, sa, stack, chop, cur_name, fut, var, t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, cell, t) & path_alias(mid_name, mid_src, [_], stack, [_], cur_name, cur, mid, [_]) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, base, [_]) & lift(cur_name, cur, sema, [_]) & succ(cur_name, cur, fut, (false)), {
+            let (true) = {cell_in([cell], [mid_name], [mid_src])};
+            let (true) = {cell_in([mid], [obj_name], [obj_src])};
+            let [ var ] = {load_cells_via([sema], [cell], [mid], [base])};
+            let (true) = {flow_budget([stack])}
+        });
+
+        // Calls with a known effect on their arguments, from function or library summaries
+        rule!(flow_summary_ret: path_alias(name, src, sa, stack, chop, cur_name, fall, (var::get_ret()), t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, var, t) & call_returns(cur_name, cur, var) & lift(cur_name, cur, [_], fall), {
+            let (true) = {flow_budget([stack])}
+        });
+        rule!(flow_summary_free: path_alias(name, src, sa, stack, chop, cur_name, fall, af, (true)) <= path_alias(name, src, sa, stack, chop, cur_name, cur, af, [_]) & path_alias(name, src, sa, stack, chop, cur_name, cur, var, [_]) & call_frees(cur_name, cur, var) & lift(cur_name, cur, [_], fall), {
+            let (true) = {flow_budget([stack])}
+        });
+        // Library containers: what is put in one becomes a cell of the container's allocation,
+        // and what is fetched back out may be any cell of it. The container must be live in the
+        // same calling context as the pointer.
+        rule!(flow_call_keeps: path_alias(name, src, sa, stack, chop, cur_name, fall, cell, t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, var, t) & call_keeps(cur_name, cur, var) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, (var::get_arg0()), [_]) & lift(cur_name, cur, [_], fall), {
+            let cell = {kept_cell([obj_name], [obj_src])};
+            let (true) = {flow_budget([stack])}
+        });
+        rule!(flow_call_yields: path_alias(name, src, sa, stack, chop, cur_name, fall, (var::get_ret()), t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, cell, t) & call_yields(cur_name, cur, arg) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, arg, [_]) & lift(cur_name, cur, [_], fall), {
+            let (true) = {cell_in([cell], [obj_name], [obj_src])};
+            let (true) = {flow_budget([stack])}
+        });
+
         fact!(stack(0, 0, "", (BitVector::nil()), 0));
 
+        rule!(flow_final: use_after_free_flow(name, src, sa, stack, other, loc, var) <= path_alias(name, src, sa, stack, [_], other, loc, var, (true)) & lift(other, loc, sema, [_]), {
+          let (true) = {deref_var([sema], [var])}
+        });
+        // puts uses the variable, but we're not anlyzing libc for now
+        rule!(flow_final_func_use: use_after_free_flow(name, src, sa, stack, other, loc, var) <= path_alias(name, src, sa, stack, [_], other, loc, var, (true)) & func_uses(other, loc, var));
+        // Freed pointer loaded out of the heap and used in the same block
+        rule!(flow_final_heap: use_after_free_flow(name, src, sa, stack, other, loc, cell) <= path_alias(name, src, sa, stack, [_], other, loc, cell, (true)) & path_alias(obj_name, obj_src, [_], stack, [_], other, loc, base, [_]) & lift(other, loc, sema, [_]), {
+            let (true) = {cell_in([cell], [obj_name], [obj_src])};
+            let (true) = {cell_derefs([sema], [cell], [base])}
+        });
+        rule!(flow_final_heap_via: use_after_free_flow(name, src, sa, stack, other, loc, cell) <= path_alias(name, src, sa, stack, [_], other, loc, cell, (true)) & path_alias(mid_name, mid_src, [_], stack, [_], other, loc, mid, [_]) & path_alias(obj_name, obj_src, [_], stack, [_], other, loc, base, [_]) & lift(other, loc, sema, [_]), {
+            let (true) = {cell_in([cell], [mid_name], [mid_src])};
+            let (true) = {cell_in([mid], [obj_name], [obj_src])};
+            let (true) = {cell_derefs_via([sema], [cell], [mid], [base])}
+        })
+    })?;
+    if summaries {
+        function_summaries(holmes)?;
+        holmes_exec!(holmes, {
+            // Calls are stepped over, applying the callee's summary rather than walking its body
+            rule!(flow_summary_over: path_alias(name, src, sa, stack, chop, cur_name, fall, var2, t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, var, t) & call_site(cur_name, cur, [_], [_]) & lift(cur_name, cur, sema, fall), {
+                let [ var2 ] = {xfer_taint([sema], [var])};
+                let (false) = {is_ret_reg([var2])};
+                let (true) = {flow_budget([stack])}
+            })
+        })?;
+        return Ok(());
+    }
+    holmes_exec!(holmes, {
         // If we're at a call site, create a stack record
         rule!(flow_stack_push: stack(stack2, stack, cur_name, fall, len2) <= path_alias([_], [_], [_], stack, [_], cur_name, cur, var, [_]) & lift(cur_name, cur, sema, fall) & call_site(cur_name, cur, next_name, [_]) & stack{id = stack, len = len}, {
             let [ len2 ] = {stack_len_inc([len])};
-            let stack2 = {hashify([stack], [cur_name], [fall])}
+            let stack2 = {stack_id([stack], [cur_name], [fall])}
         });
         // If it's a call, a call_site instance will be generated, resolving dynamic calls if
         // needed. Add this onto the stack so any returns actually go here rather than anywhere
         rule!(flow_call: path_alias(name, src, sa, stack2, chop2, next_name, fut, var2, t) <= path_alias(name, src, sa, stack, chop, cur_name, cur, var, t) & lift(cur_name, cur, sema, fall) & call_site(cur_name, cur, next_name, fut) & stack(stack2, stack, cur_name, fall), {
             let [ chop2 ] = {chop_check([chop], [fut])};
-            let [ var2 ] = {xfer_taint([sema], [var])}
+            let [ var2 ] = {xfer_taint([sema], [var])};
+            let (true) = {flow_budget([stack2])}
         });
         // If it's a return and we have a stack, pop it
-        rule!(flow_ret_pop: path_alias(src_name, src_addr, sa, stack2, chop, dst_name, dst_addr, var, t) <= path_alias(src_name, src_addr, sa, stack, chop, ret_name, ret_addr, var, t) & lift {binary = ret_name, address = ret_addr, is_ret = (true)} & stack(stack, stack2, dst_name, dst_addr));
-        rule!(flow_final: use_after_free_flow(name, src, sa, stack, other, loc, var) <= path_alias(name, src, sa, stack, [_], other, loc, var, (true)) & lift(other, loc, sema, [_]), {
-          let (true) = {deref_var([sema], [var])}
+        rule!(flow_ret_pop: path_alias(src_name, src_addr, sa, stack2, chop, dst_name, dst_addr, var, t) <= path_alias(src_name, src_addr, sa, stack, chop, ret_name, ret_addr, var, t) & lift {binary = ret_name, address = ret_addr, is_ret = (true)} & stack(stack, stack2, dst_name, dst_addr), {
+            let (true) = {flow_budget([stack2])}
         });
-        // puts uses the variable, but we're not anlyzing libc for now
-        rule!(flow_final_func_use: use_after_free_flow(name, src, sa, stack, other, loc, var) <= path_alias(name, src, sa, stack, [_], other, loc, var, (true)) & func_uses(other, loc, var))
-
+        // Record calls the bounds kept a path from following
+        rule!(flow_cut_stack: cutoff(("stack_depth"), name, src) <= path_alias(name, src, [_], stack, [_], cur_name, cur, [_], [_]) & call_site(cur_name, cur, [_], [_]) & stack{id = stack, len = len}, {
+            let (true) = {stack_full([len])}
+        });
+        rule!(flow_cut_chop: cutoff(("chop"), name, src) <= path_alias(name, src, [_], [_], chop, cur_name, cur, [_], [_]) & call_site(cur_name, cur, [_], fut), {
+            let (true) = {chop_full([chop], [fut])}
+        })
     })?;
     Ok(())
 }
 
+// Which arguments each function frees, returns, or dereferences, computed once per function
+// from its entry rather than once per calling context. Calls inside the function are stepped
+// over using the callee's own summary. Uses the funcs registered by uaf_stage1.
+pub fn function_summaries(holmes: &mut Engine) -> Result<()> {
+    holmes_exec!(holmes, {
+        fact!(summary_arg((var::get_arg_n(0))));
+        fact!(summary_arg((var::get_arg_n(1))));
+        fact!(summary_arg((var::get_arg_n(2))));
+        fact!(summary_arg((var::get_arg_n(3))));
+        fact!(summary_arg((var::get_arg_n(4))));
+        fact!(summary_arg((var::get_arg_n(5))));
+        rule!(summary_start: summary_alias(bin, entry, arg, entry, arg, (false)) <= func(bin, entry, entry) & summary_arg(arg));
+        rule!(summary_prop: summary_alias(bin, entry, arg, fut, var2, t) <= summary_alias(bin, entry, arg, cur, var, t) & lift(bin, cur, sema, [_]) & succ(bin, cur, fut, (false)), {
+            let [ var2 ] = {xfer_taint([sema], [var])}
+        });
+        rule!(summary_free: summary_alias(bin, entry, arg, next, af, (true)) <= summary_alias(bin, entry, arg, free_addr, af, [_]) & summary_alias(bin, entry, arg, free_addr, (var::get_arg0()), [_]) & free_call(bin, free_addr) & lift(bin, free_addr, [_], next));
+        rule!(summary_skip_func: summary_alias(bin, entry, arg, fall, var2, t) <= summary_alias(bin, entry, arg, cur, var, t) & skip_func(bin, cur) & lift(bin, cur, sema, fall), {
+            let [ var2 ] = {xfer_taint([sema], [var])};
+            let (false) = {is_ret_reg([var2])}
+        });
+        rule!(summary_call_over: summary_alias(bin, entry, arg, fall, var2, t) <= summary_alias(bin, entry, arg, cur, var, t) & call_site(bin, cur, [_], [_]) & lift(bin, cur, sema, fall), {
+            let [ var2 ] = {xfer_taint([sema], [var])};
+            let (false) = {is_ret_reg([var2])}
+        });
+        rule!(summary_call_ret: summary_alias(bin, entry, arg, fall, (var::get_ret()), t) <= summary_alias(bin, entry, arg, cur, var, t) & call_returns(bin, cur, var) & lift(bin, cur, [_], fall));
+        rule!(summary_call_free: summary_alias(bin, entry, arg, fall, af, (true)) <= summary_alias(bin, entry, arg, cur, af, [_]) & summary_alias(bin, entry, arg, cur, var, [_]) & call_frees(bin, cur, var) & lift(bin, cur, [_], fall));
+
+        rule!(summary_frees: summary_frees(bin, entry, arg) <= summary_alias(bin, entry, arg, [_], [_], (true)));
+        rule!(summary_returns: summary_returns(bin, entry, arg) <= summary_alias(bin, entry, arg, ret_addr, (var::get_ret()), [_]) & lift {binary = bin, address = ret_addr, is_ret = (true)});
+        rule!(summary_derefs: summary_derefs(bin, entry, arg) <= summary_alias(bin, entry, arg, cur, var, [_]) & lift(bin, cur, sema, [_]), {
+            let (true) = {deref_var([sema], [var])}
+        });
+        rule!(summary_derefs_func_uses: summary_derefs(bin, entry, arg) <= summary_alias(bin, entry, arg, cur, var, [_]) & func_uses(bin, cur, var));
+
+        rule!(call_frees: call_frees(bin, addr, arg) <= call_site(bin, addr, callee, entry) & summary_frees(callee, entry, arg));
+        rule!(call_returns: call_returns(bin, addr, arg) <= call_site(bin, addr, callee, entry) & summary_returns(callee, entry, arg));
+        rule!(call_derefs: func_uses(bin, addr, arg) <= call_site(bin, addr, callee, entry) & summary_derefs(callee, entry, arg))
+    })
+}
+
 pub fn uaf_stage2(holmes: &mut Engine) -> Result<()> {
     holmes_exec!(holmes, {
         // If it's a return and an empty stack, return anywhere we were called
         rule!(flow_ret_notarget: path_alias(src_name, src_addr, sa, (0), chop2, call_name, dst_addr, var, t) <= path_alias(src_name, src_addr, sa, (0), chop, ret_name, ret_addr, var, t) & func(ret_name, func_addr, ret_addr) & call_site(call_name, call_addr, ret_name, func_addr) & lift {binary = ret_name, address = ret_addr, is_ret = (true)} & lift(call_name, call_addr, [_], dst_addr), {
-            let [ chop2 ] = {chop_check([chop], [func_addr])}
+            let [ chop2 ] = {chop_check([chop], [func_addr])};
+            let (true) = {flow_budget((0))}
         })
     })
 }
 
-pub fn const_prop(holmes: &mut Engine) -> Result<()> {
-    // This is woefully inadequate for true constant propagation - since each instruction can read
-    // multiple variables, and I don't have any kind of aggregation, monotonic or otherwise
-    // available, I'd have to make a custom aggregation rule to do that. This would work, but would
-    // slow down operation, and right now I don't really care about tracking through ops, just
-    // through movs
-    // This is also _wrong_ at the moment in that it's strict linear stepping - it ignores any
-    // notion of jumps or calls or anything else. This is in large part because it is only an
-    // advisory hack to discover the addresses of RO string constants for printf at the moment >_>
+// Runs outside the engine: agreeing at merge points needs every predecessor of a block at once,
+// which rules can't express without aggregation. Needs lifting to have quiesced. Each round
+// asserts the computed jumps it can resolve as succ edges and quiesces, so the blocks they reach
+// are lifted and take part in the next round; once a round finds no new jump, poss_const is
+// asserted for the start of each block. Facts can't be retracted, so a jump resolved in an early
+// round keeps its edge even if a later predecessor makes its target vary.
+pub fn const_prop(holmes: &mut Engine, tracker: &mut Tracker, core: &mut Core) -> Result<()> {
     holmes_exec!(holmes, {
-        func!(let const_init : sema -> [(var, bitvector)] = analyses::const_init);
-        func!(let const_prop : (sema, var, bitvector) -> [(var, bitvector)] = analyses::const_prop);
-        rule!(const_init: poss_const(name, addr, var, k) <= lift(name, [_], sema, addr), {
-            let [{var, k}] = {const_init([sema])}
-        });
-        rule!(const_prop: poss_const(name, addr, var2, k2) <= lift(name, prev, sema, addr) & poss_const(name, prev, var, k), {
-            let [{var2, k2}] = {const_prop([sema], [var], [k])}
-        })
-    })
+        // Computed jumps and calls whose target constant propagation pinned down
+        rule!(const_jump_succ: succ(name, addr, tgt, call, (Cond::new())) <= const_jump(name, addr, tgt) & lift {binary = name, address = addr, is_call = call})
+    })?;
+    let mut resolved = HashSet::new();
+    loop {
+        let mut consts = Vec::new();
+        let mut fresh = false;
+        for (name, blocks, edges) in const_inputs(holmes)? {
+            let (ks, jumps) = analyses::const_flow(&blocks, &edges);
+            for (addr, tgt) in jumps {
+                if resolved.insert((name.clone(), addr.clone(), tgt.clone())) {
+                    fresh = true;
+                    let name = name.clone();
+                    fact!(holmes, const_jump(name, addr, tgt))?;
+                }
+            }
+            consts.push((name, ks));
+        }
+        if !fresh {
+            for (name, ks) in consts {
+                for (addr, var, k) in ks {
+                    let name = name.clone();
+                    fact!(holmes, poss_const(name, addr, var, k))?;
+                }
+            }
+            return Ok(());
+        }
+        tracker.quiesce(holmes, core);
+    }
+}
+
+// Blocks and succ edges of each binary, as const_flow takes them
+fn const_inputs(holmes: &mut Engine) -> Result<Vec<(String, Vec<(BitVector, Sema, BitVector, bool)>, Vec<(BitVector, BitVector, bool)>)>> {
+    let mut bins: Vec<(String, Vec<(BitVector, Sema, BitVector, bool)>, Vec<(BitVector, BitVector, bool)>)> = Vec::new();
+    for row in query!(holmes, lift {binary = name, address = addr, bil = sema, fallthrough = fall, is_call = call})? {
+        let name = row[0].get().downcast_ref::<String>().unwrap().clone();
+        let block = (
+            row[1].get().downcast_ref::<BitVector>().unwrap().clone(),
+            row[2].get().downcast_ref::<Sema>().unwrap().clone(),
+            row[3].get().downcast_ref::<BitVector>().unwrap().clone(),
+            *row[4].get().downcast_ref::<bool>().unwrap(),
+        );
+        match bins.iter().position(|b| b.0 == name) {
+            Some(idx) => bins[idx].1.push(block),
+            None => bins.push((name, vec![block], Vec::new())),
+        }
+    }
+    for row in query!(holmes, succ(name, src, dst, call))? {
+        let name = row[0].get().downcast_ref::<String>().unwrap();
+        if let Some(idx) = bins.iter().position(|b| &b.0 == name) {
+            bins[idx].2.push((
+                row[1].get().downcast_ref::<BitVector>().unwrap().clone(),
+                row[2].get().downcast_ref::<BitVector>().unwrap().clone(),
+                *row[3].get().downcast_ref::<bool>().unwrap(),
+            ));
+        }
+    }
+    Ok(bins)
 }
 
 pub fn str_const(holmes: &mut Engine) -> Result<()> {
@@ -273,46 +584,131 @@ pub fn printf_formats(holmes: &mut Engine) -> Result<()> {
 }
 
 
-pub fn uaf_trace_stage1(holmes: &mut Engine, trace_len: usize) -> Result<()> {
+pub fn uaf_trace_stage1(holmes: &mut Engine, trace_len: usize, budget: &FactCounter, summaries: bool) -> Result<()> {
     holmes_exec!(holmes, {
+        func!(let trace_budget : uint64 -> bool = budget.func());
         func!(let trace_inc_len : uint64 -> [ uint64 ] = analyses::trace_len_inc(trace_len));
+        func!(let trace_full : uint64 -> bool = analyses::len_at_limit(trace_len));
+        func!(let cond_step : (cond, cond, sema) -> [cond] = analyses::cond_step);
+        func!(let cond_call : (cond, sema) -> cond = analyses::cond_call);
+
+        // Only where the trace could otherwise have gone on
+        rule!(trace_cut_len: cutoff(("trace_len"), name, src) <= path_alias_trace(name, src, [_], [_], cur_name, cur, [_], [_], len) & succ(cur_name, cur, [_], [_]), {
+            let (true) = {trace_full([len])}
+        });
 
-        rule!(trace_start: path_alias_trace(src_name, addr, alias_set, (0), src_name, naddr, var, (false), (0)) <= use_after_free_flow {source_binary = src_name, source = addr, alias_set = alias_set} & path_alias(src_name, addr, sa, [_], [_], src_name, naddr, var) & succ_over(src_name, addr, naddr));
+        rule!(trace_start: path_alias_trace(src_name, addr, alias_set, (0), src_name, naddr, var, (false), (0), (Cond::new())) <= use_after_free_flow {source_binary = src_name, source = addr, alias_set = alias_set} & path_alias(src_name, addr, sa, [_], [_], src_name, naddr, var) & succ_over(src_name, addr, naddr), {
+            let (true) = {trace_budget((0))}
+        });
 
-        rule!(trace_free: path_alias_trace(src_name, src, sa, stack, free_name, next, af, (true), len2) <= path_alias_trace(src_name, src, sa, stack, free_name, free_addr, af, [_], len) & path_alias_trace(src_name, src, sa, stack, free_name, free_addr, (var::get_arg0()), [_], len) & free_call(free_name, free_addr) & lift(free_name, free_addr, [_], next), {
-            let [ len2 ] = {trace_inc_len([len])}
+        rule!(trace_free: path_alias_trace(src_name, src, sa, stack, free_name, next, af, (true), len2, cond2) <= path_alias_trace(src_name, src, sa, stack, free_name, free_addr, af, [_], len, cond) & path_alias_trace(src_name, src, sa, stack, free_name, free_addr, (var::get_arg0()), [_], len, cond) & free_call(free_name, free_addr) & lift(free_name, free_addr, sema, next), {
+            let cond2 = {cond_call([cond], [sema])};
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
         });
         
         // TODO THIS CANNOT EXIST IN NORMAL CODE - IT WILL MAKE FREE FREE THE CONTENTS OF RSI,
         // WHICH WERE NOT PASSED TO IT
-        rule!(trace_free_2_hack: path_alias_trace(src_name, src, sa, stack, free_name, next, af, (true), len2) <= path_alias_trace(src_name, src, sa, stack, free_name, free_addr, af, [_], len) & path_alias_trace(src_name, src, sa, stack, free_name, free_addr, (var::get_arg_n(1)), [_], len) & free_call(free_name, free_addr) & lift(free_name, free_addr, [_], next), {
-            let [len2] = {trace_inc_len([len])}
+        rule!(trace_free_2_hack: path_alias_trace(src_name, src, sa, stack, free_name, next, af, (true), len2, cond2) <= path_alias_trace(src_name, src, sa, stack, free_name, free_addr, af, [_], len, cond) & path_alias_trace(src_name, src, sa, stack, free_name, free_addr, (var::get_arg_n(1)), [_], len, cond) & free_call(free_name, free_addr) & lift(free_name, free_addr, sema, next), {
+            let cond2 = {cond_call([cond], [sema])};
+            let [len2] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
         });
-        // If there's a successor, follow that and transfer taint (but not if it's a call)
-        rule!(trace_prop: path_alias_trace(name, src, sa, stack, cur_name, fut, var2, t, len2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len) & lift(cur_name, cur, sema, [_]) & succ(cur_name, cur, fut, (false)), {
+        // If there's a successor, follow that and transfer taint (but not if it's a call). Edges
+        // the branch conditions so far rule out are dropped.
+        rule!(trace_prop: path_alias_trace(name, src, sa, stack, cur_name, fut, var2, t, len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len, cond) & lift(cur_name, cur, sema, [_]) & succ(cur_name, cur, fut, (false), edge), {
+          let [ cond2 ] = {cond_step([cond], [edge], [sema])};
           let [ var2 ] = {xfer_taint([sema], [var])};
-          let [len2] = {trace_inc_len([len])}
+          let [len2] = {trace_inc_len([len])};
+          let (true) = {trace_budget([len2])}
         });
 
         // Normally, if we don't have the function present, we have to stop the path analysis
         // In the case of malloc, we special case to just filter out the return variable
         // TODO clobber return _and_ standard clobbers
         // TODO do we need to xfer taint here? Maybe omit
-        rule!(trace_skip_func: path_alias_trace(name, src, sa, stack, cur_name, fall, var2, t, len2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len) & skip_func(cur_name, cur) & lift(cur_name, cur, sema, fall) , {
+        rule!(trace_skip_func: path_alias_trace(name, src, sa, stack, cur_name, fall, var2, t, len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len, cond) & skip_func(cur_name, cur) & lift(cur_name, cur, sema, fall) , {
           let [ var2 ] = {xfer_taint([sema], [var])};
+          let cond2 = {cond_call([cond], [sema])};
           let (false) = {is_ret_reg([var2])};
-          let [ len2 ] = {trace_inc_len([len])}
+          let [ len2 ] = {trace_inc_len([len])};
+          let (true) = {trace_budget([len2])}
       });
 
+        // Heap cells as in flow_heap_store/flow_heap_load. The object's aliases come from the flow
+        // stage, as only the freed allocation is traced; both stages share stack ids, so the
+        // calling context still has to match.
+        rule!(trace_heap_store: path_alias_trace(name, src, sa, stack, cur_name, fut, cell, t, len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len, cond) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, base, [_]) & lift(cur_name, cur, sema, [_]) & succ(cur_name, cur, fut, (false), edge), {
+            let [ cond2 ] = {cond_step([cond], [edge], [sema])};
+            let [ cell ] = {store_cells([sema], [var], [base], [obj_name], [obj_src])};
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
+        });
+        rule!(trace_heap_load: path_alias_trace(name, src, sa, stack, cur_name, fut, var, t, len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, cell, t, len, cond) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, base, [_]) & lift(cur_name, cur, sema, [_]) & succ(cur_name, cur, fut, (false), edge), {
+            let [ cond2 ] = {cond_step([cond], [edge], [sema])};
+            let (true) = {cell_in([cell], [obj_name], [obj_src])};
+            let [ var ] = {load_cells([sema], [cell], [base])};
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
+        });
+        rule!(trace_heap_load_via: path_alias_trace(name, src, sa, stack, cur_name, fut, var, t, len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, cell, t, len, cond) & path_alias(mid_name, mid_src, [_], stack, [_], cur_name, cur, mid, [_]) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, base, [_]) & lift(cur_name, cur, sema, [_]) & succ(cur_name, cur, fut, (false), edge), {
+            let [ cond2 ] = {cond_step([cond], [edge], [sema])};
+            let (true) = {cell_in([cell], [mid_name], [mid_src])};
+            let (true) = {cell_in([mid], [obj_name], [obj_src])};
+            let [ var ] = {load_cells_via([sema], [cell], [mid], [base])};
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
+        });
+
+        // Calls with a known effect on their arguments, from function or library summaries
+        rule!(trace_summary_ret: path_alias_trace(name, src, sa, stack, cur_name, fall, (var::get_ret()), t, len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len, cond) & call_returns(cur_name, cur, var) & lift(cur_name, cur, sema, fall), {
+            let cond2 = {cond_call([cond], [sema])};
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
+        });
+        rule!(trace_summary_free: path_alias_trace(name, src, sa, stack, cur_name, fall, af, (true), len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, af, [_], len, cond) & path_alias_trace(name, src, sa, stack, cur_name, cur, var, [_], len, cond) & call_frees(cur_name, cur, var) & lift(cur_name, cur, sema, fall), {
+            let cond2 = {cond_call([cond], [sema])};
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
+        });
+        rule!(trace_call_keeps: path_alias_trace(name, src, sa, stack, cur_name, fall, cell, t, len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len, cond) & call_keeps(cur_name, cur, var) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, (var::get_arg0()), [_]) & lift(cur_name, cur, sema, fall), {
+            let cell = {kept_cell([obj_name], [obj_src])};
+            let cond2 = {cond_call([cond], [sema])};
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
+        });
+        rule!(trace_call_yields: path_alias_trace(name, src, sa, stack, cur_name, fall, (var::get_ret()), t, len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, cell, t, len, cond) & call_yields(cur_name, cur, arg) & path_alias(obj_name, obj_src, [_], stack, [_], cur_name, cur, arg, [_]) & lift(cur_name, cur, sema, fall), {
+            let (true) = {cell_in([cell], [obj_name], [obj_src])};
+            let cond2 = {cond_call([cond], [sema])};
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
+        })
+    })?;
+    if summaries {
+        holmes_exec!(holmes, {
+            rule!(trace_summary_over: path_alias_trace(name, src, sa, stack, cur_name, fall, var2, t, len2, cond2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len, cond) & call_site(cur_name, cur, [_], [_]) & lift(cur_name, cur, sema, fall), {
+                let [ var2 ] = {xfer_taint([sema], [var])};
+                let cond2 = {cond_call([cond], [sema])};
+                let (false) = {is_ret_reg([var2])};
+                let [ len2 ] = {trace_inc_len([len])};
+                let (true) = {trace_budget([len2])}
+            })
+        })?;
+        return Ok(());
+    }
+    holmes_exec!(holmes, {
         // If it's a call, a call_site instance will be generated, resolving dynamic calls if
-        // needed. Add this onto the stack so any returns actually go here rather than anywhere
-        rule!(flow_call: path_alias_trace(name, src, sa, stack2, next_name, fut, var2, t, len2) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len) & lift(cur_name, cur, sema, fall) & call_site(cur_name, cur, next_name, fut) & stack(stack2, stack, cur_name, fall), {
+        // needed. Add this onto the stack so any returns actually go here rather than anywhere.
+        // Conditions on the caller's state don't carry into the callee.
+        rule!(flow_call: path_alias_trace(name, src, sa, stack2, next_name, fut, var2, t, len2, (Cond::new())) <= path_alias_trace(name, src, sa, stack, cur_name, cur, var, t, len) & lift(cur_name, cur, sema, fall) & call_site(cur_name, cur, next_name, fut) & stack(stack2, stack, cur_name, fall), {
             let [ var2 ] = {xfer_taint([sema], [var])};
-            let [ len2 ] = {trace_inc_len([len])}
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
         });
         // If it's a return and we have a stack, pop it
-        rule!(trace_ret_pop: path_alias_trace(src_name, src_addr, sa, stack2, dst_name, dst_addr, var, t, len2) <= path_alias_trace(src_name, src_addr, sa, stack, ret_name, ret_addr, var, t, len) & lift {binary = ret_name, address = ret_addr, is_ret = (true)} & stack(stack, stack2, dst_name, dst_addr), {
-            let [ len2 ] = {trace_inc_len([len])}
+        rule!(trace_ret_pop: path_alias_trace(src_name, src_addr, sa, stack2, dst_name, dst_addr, var, t, len2, (Cond::new())) <= path_alias_trace(src_name, src_addr, sa, stack, ret_name, ret_addr, var, t, len) & lift {binary = ret_name, address = ret_addr, is_ret = (true)} & stack(stack, stack2, dst_name, dst_addr), {
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
         })
     })?;
     Ok(())
@@ -320,77 +716,714 @@ pub fn uaf_trace_stage1(holmes: &mut Engine, trace_len: usize) -> Result<()> {
 
 pub fn uaf_trace_stage2(holmes: &mut Engine) -> Result<()> {
     holmes_exec!(holmes, { 
-        rule!(trace_ret_notarget: path_alias_trace(src_name, src_addr, sa, (0), call_name, dst_addr, var, t, len2) <= path_alias_trace(src_name, src_addr, sa, (0), ret_name, ret_addr, var, t, len) & func(ret_name, func_addr, ret_addr) & call_site(call_name, call_addr, ret_name, func_addr) & lift {binary = ret_name, address = ret_addr, is_ret = (true)} & lift(call_name, call_addr, [_], dst_addr), {
-            let [ len2 ] = {trace_inc_len([len])}
+        rule!(trace_ret_notarget: path_alias_trace(src_name, src_addr, sa, (0), call_name, dst_addr, var, t, len2, (Cond::new())) <= path_alias_trace(src_name, src_addr, sa, (0), ret_name, ret_addr, var, t, len) & func(ret_name, func_addr, ret_addr) & call_site(call_name, call_addr, ret_name, func_addr) & lift {binary = ret_name, address = ret_addr, is_ret = (true)} & lift(call_name, call_addr, [_], dst_addr), {
+            let [ len2 ] = {trace_inc_len([len])};
+            let (true) = {trace_budget([len2])}
         })
     })
 }
 
-pub fn grading(holmes: &mut Engine) -> Result<()> {
+// Turns traces that reach a use after the free into findings
+pub fn finalize(holmes: &mut Engine, budget: &FactCounter) -> Result<()> {
     holmes_exec!(holmes, {
+        func!(let grade_budget : uint64 -> bool = budget.func());
         rule!(uaf_finalize: use_after_free(name, src, sa, other, loc, var, stack, len) <= path_alias_trace(name, src, sa, stack, other, loc, var, (true), len) & lift(other, loc, sema, [_]), {
-          let (true) = {deref_var([sema], [var])}
+          let (true) = {deref_var([sema], [var])};
+          let (true) = {grade_budget([len])}
         });
         // puts uses the variable, but we're not anlyzing libc for now
-        rule!(uaf_finalize_func_uses: use_after_free(name, src, sa, other, loc, var, stack, len) <= path_alias_trace(name, src, sa, stack, other, loc, var, (true), len) & func_uses(other, loc, var));
-        rule!(uaf_true_pos: true_positive(name, src, parent) <= use_after_free(name, src, sa, [_], [_], [_], stack, [_]) & bad_stack(stack, parent));
-        rule!(uaf_false_pos: false_positive(name, src, parent) <= use_after_free(name, src, sa, [_], [_], [_], stack, [_]) & good_stack(stack, parent));
-        rule!(uaf_bad_stack_prop: bad_stack(stack, parent) <= bad_stack(sub, parent) & stack(stack, sub, [_], [_]));
-        rule!(uaf_good_stack_prop: good_stack(stack, parent) <= good_stack(sub, parent) & stack(stack, sub, [_], [_]));
-        func!(let has_substr : (string, string) -> bool = |(hay, need) : (&String, &String)| hay.contains(need));
-        rule!(uaf_bad_stack_base: bad_stack(stack, func_name) <= stack(stack, [_], name, addr) & func(name, func_addr, addr) & entry(name, func_name, func_addr, [_]), {
-            let (true) = {has_substr([func_name], ("_bad"))}
-        });
-        rule!(uaf_good_stack_base: good_stack(stack, func_name) <= stack(stack, [_], name, addr) & func(name, func_addr, addr) & entry(name, func_name, func_addr, [_]), {
-            let (true) = {has_substr([func_name], ("_good"))}
-        });
-        rule!(uaf_true_pos_top: true_positive(name, src, func_name) <= use_after_free(name, src, sa, name, loc) & func(name, func_addr, loc) & entry(name, func_name, func_addr, [_]), {
-            let (true) = {has_substr([func_name], ("_bad"))}
-        });
-        rule!(uaf_false_pos_top: false_positive(name, src, func_name) <= use_after_free(name, src, sa, name, loc) & func(name, func_addr, loc) & entry(name, func_name, func_addr, [_]), {
-            let (true) = {has_substr([func_name], ("_good"))}
+        rule!(uaf_finalize_func_uses: use_after_free(name, src, sa, other, loc, var, stack, len) <= path_alias_trace(name, src, sa, stack, other, loc, var, (true), len) & func_uses(other, loc, var), {
+          let (true) = {grade_budget([len])}
+        });
+        rule!(uaf_finalize_heap: use_after_free(name, src, sa, other, loc, cell, stack, len) <= path_alias_trace(name, src, sa, stack, other, loc, cell, (true), len) & path_alias(obj_name, obj_src, [_], stack, [_], other, loc, base, [_]) & lift(other, loc, sema, [_]), {
+          let (true) = {cell_in([cell], [obj_name], [obj_src])};
+          let (true) = {cell_derefs([sema], [cell], [base])};
+          let (true) = {grade_budget([len])}
+        });
+        rule!(uaf_finalize_heap_via: use_after_free(name, src, sa, other, loc, cell, stack, len) <= path_alias_trace(name, src, sa, stack, other, loc, cell, (true), len) & path_alias(mid_name, mid_src, [_], stack, [_], other, loc, mid, [_]) & path_alias(obj_name, obj_src, [_], stack, [_], other, loc, base, [_]) & lift(other, loc, sema, [_]), {
+          let (true) = {cell_in([cell], [mid_name], [mid_src])};
+          let (true) = {cell_in([mid], [obj_name], [obj_src])};
+          let (true) = {cell_derefs_via([sema], [cell], [mid], [base])};
+          let (true) = {grade_budget([len])}
         })
     })
 }
 
+// Grades the findings suppress left unsuppressed, against expected facts when truth is set and
+// otherwise by Juliet's naming
+pub fn grading(holmes: &mut Engine, truth: bool) -> Result<()> {
+    if truth {
+        holmes_exec!(holmes, {
+            func!(let overlaps : (bitvector, bitvector, bitvector, bitvector) -> bool = |(start, end, lo, hi) : (&BitVector, &BitVector, &BitVector, &BitVector)| {
+                use num::ToPrimitive;
+                let (start, end, lo, hi) = (start.to_u64().unwrap(), end.to_u64().unwrap(), lo.to_u64().unwrap(), hi.to_u64().unwrap());
+                start <= hi && lo < end
+            });
+            rule!(truth_true_pos: true_positive(name, src, label) <= use_after_free(name, src, [_], name, sink) & unsuppressed(name, src, name, sink) & expected(name, ("uaf"), (true), label, alo, ahi, ulo, uhi) & lift(name, src, [_], src_end) & lift(name, sink, [_], sink_end), {
+                let (true) = {overlaps([src], [src_end], [alo], [ahi])};
+                let (true) = {overlaps([sink], [sink_end], [ulo], [uhi])}
+            });
+            rule!(truth_false_pos: false_positive(name, src, label) <= use_after_free(name, src, [_], name, sink) & unsuppressed(name, src, name, sink) & expected(name, ("uaf"), (false), label, alo, ahi, ulo, uhi) & lift(name, src, [_], src_end) & lift(name, sink, [_], sink_end), {
+                let (true) = {overlaps([src], [src_end], [alo], [ahi])};
+                let (true) = {overlaps([sink], [sink_end], [ulo], [uhi])}
+            })
+        })
+    } else {
+        // Juliet: whether the function a finding sits under is one of a testcase's bad or good ones
+        holmes_exec!(holmes, {
+            rule!(uaf_true_pos: true_positive(name, src, parent) <= use_after_free(name, src, sa, other, loc, [_], stack, [_]) & unsuppressed(name, src, other, loc) & bad_stack(stack, parent));
+            rule!(uaf_false_pos: false_positive(name, src, parent) <= use_after_free(name, src, sa, other, loc, [_], stack, [_]) & unsuppressed(name, src, other, loc) & good_stack(stack, parent));
+            rule!(uaf_bad_stack_prop: bad_stack(stack, parent) <= bad_stack(sub, parent) & stack(stack, sub, [_], [_]));
+            rule!(uaf_good_stack_prop: good_stack(stack, parent) <= good_stack(sub, parent) & stack(stack, sub, [_], [_]));
+            func!(let has_substr : (string, string) -> bool = |(hay, need) : (&String, &String)| hay.contains(need));
+            rule!(uaf_bad_stack_base: bad_stack(stack, func_name) <= stack(stack, [_], name, addr) & func(name, func_addr, addr) & entry(name, func_name, func_addr, [_]), {
+                let (true) = {has_substr([func_name], ("_bad"))}
+            });
+            rule!(uaf_good_stack_base: good_stack(stack, func_name) <= stack(stack, [_], name, addr) & func(name, func_addr, addr) & entry(name, func_name, func_addr, [_]), {
+                let (true) = {has_substr([func_name], ("_good"))}
+            });
+            rule!(uaf_true_pos_top: true_positive(name, src, func_name) <= use_after_free(name, src, sa, name, loc) & unsuppressed(name, src, name, loc) & func(name, func_addr, loc) & entry(name, func_name, func_addr, [_]), {
+                let (true) = {has_substr([func_name], ("_bad"))}
+            });
+            rule!(uaf_false_pos_top: false_positive(name, src, func_name) <= use_after_free(name, src, sa, name, loc) & unsuppressed(name, src, name, loc) & func(name, func_addr, loc) & entry(name, func_name, func_addr, [_]), {
+                let (true) = {has_substr([func_name], ("_good"))}
+            })
+        })
+    }
+}
+
 pub fn uaf(in_paths: Vec<String>, trace_len: usize, kprop: bool) -> Box<Fn(&mut Engine, &mut Core) -> Result<()>> {
-    Box::new(move |holmes, core| {
+    let pipeline = Pipeline::new(in_paths).trace_len(trace_len).const_prop(kprop);
+    Box::new(move |holmes, core| pipeline.run(holmes, core).map(|_| ()))
+}
+
+/// What a pipeline run found out besides the findings themselves
+#[derive(Debug, Clone, Default)]
+pub struct RunReport {
+    // Time and derivations of each stage, and which ran out
+    pub budget: budget::Report,
+    // Bound name and how many allocation sites had a path stopped by it
+    pub cutoffs: Vec<(String, usize)>,
+    // The sites themselves: bound name, binary and allocation site
+    pub cut_sites: Vec<(String, String, BitVector)>,
+    // Symbolic execution verdict and how many findings got it, when that was run
+    pub verdicts: Vec<(String, usize)>,
+    // Emulation outcome and how many findings got it, when that was run
+    pub emulated: Vec<(String, usize)>,
+    // Labels of ground-truth annotations whose binary or sites couldn't be found
+    pub unresolved: Vec<String>,
+    // Findings left out because a suppression file accepted them
+    pub suppressed: usize,
+}
+
+impl ::std::fmt::Display for RunReport {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
+        write!(f, "{}", self.budget)?;
+        for &(ref bound, sources) in self.cutoffs.iter() {
+            write!(f, "Cut off by {}: {} sources\n", bound, sources)?;
+            for &(_, ref bin, ref site) in self.cut_sites.iter().filter(|&&(ref b, _, _)| b == bound) {
+                write!(f, "  {} {}\n", bin, site)?;
+            }
+        }
+        for &(ref verdict, findings) in self.verdicts.iter() {
+            write!(f, "Symbolically {}: {} findings\n", verdict, findings)?;
+        }
+        for &(ref outcome, findings) in self.emulated.iter() {
+            write!(f, "Emulation {}: {} findings\n", outcome, findings)?;
+        }
+        if self.suppressed > 0 {
+            write!(f, "Suppressed: {} findings\n", self.suppressed)?;
+        }
+        for label in self.unresolved.iter() {
+            write!(f, "Unresolved annotation: {}\n", label)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    in_paths: Vec<String>,
+    limits: Limits,
+    kprop: bool,
+    lift_cache: Option<LiftCache>,
+    shingle: bool,
+    summaries: bool,
+    skip_dyn: bool,
+    model: bool,
+    lib_summaries: Vec<lib_summary::LibSummary>,
+    budgets: Budgets,
+    symex: bool,
+    emulate: Option<Vec<String>>,
+    truth: Vec<truth::Annotation>,
+    suppressions: Vec<suppress::Suppression>,
+}
+
+impl Pipeline {
+    pub fn new(in_paths: Vec<String>) -> Self {
+        Pipeline {
+            in_paths: in_paths,
+            limits: Limits::default(),
+            kprop: true,
+            lift_cache: None,
+            shingle: false,
+            summaries: false,
+            skip_dyn: false,
+            model: true,
+            lib_summaries: Vec::new(),
+            budgets: Budgets::default(),
+            symex: false,
+            emulate: None,
+            truth: Vec::new(),
+            suppressions: Vec::new(),
+        }
+    }
+    pub fn trace_len(mut self, trace_len: usize) -> Self {
+        self.limits.trace_len = trace_len;
+        self
+    }
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+    pub fn const_prop(mut self, kprop: bool) -> Self {
+        self.kprop = kprop;
+        self
+    }
+    // Look lifted instructions up in, and add them to, a cache shared with other runs
+    pub fn lift_cache(mut self, cache: Option<LiftCache>) -> Self {
+        self.lift_cache = cache;
+        self
+    }
+    pub fn shingle(mut self, shingle: bool) -> Self {
+        self.shingle = shingle;
+        self
+    }
+    // Apply per-function summaries at call sites instead of re-walking callees per context
+    pub fn summaries(mut self, summaries: bool) -> Self {
+        self.summaries = summaries;
+        self
+    }
+    // Step over calls to functions not present in the loaded binaries
+    pub fn skip_dyn(mut self, skip_dyn: bool) -> Self {
+        self.skip_dyn = skip_dyn;
+        self
+    }
+    // Whether to apply the bundled libc/POSIX/GLib model at calls into those libraries
+    pub fn model(mut self, model: bool) -> Self {
+        self.model = model;
+        self
+    }
+    // Summaries of libraries that aren't loaded, applied at calls into them
+    pub fn lib_summaries(mut self, lib_summaries: Vec<lib_summary::LibSummary>) -> Self {
+        self.lib_summaries = lib_summaries;
+        self
+    }
+    pub fn budgets(mut self, budgets: Budgets) -> Self {
+        self.budgets = budgets;
+        self
+    }
+    // Check each finding by symbolic execution once grading is done
+    pub fn symex(mut self, symex: bool) -> Self {
+        self.symex = symex;
+        self
+    }
+    // Run each finding's binary under the emulator, with these arguments after the program name
+    pub fn emulate(mut self, args: Option<Vec<String>>) -> Self {
+        self.emulate = args;
+        self
+    }
+    // Grade against these annotations instead of by Juliet function names
+    pub fn truth(mut self, truth: Vec<truth::Annotation>) -> Self {
+        self.truth = truth;
+        self
+    }
+    // Leave findings these match out of grading, confirmation and the report
+    pub fn suppressions(mut self, suppressions: Vec<suppress::Suppression>) -> Self {
+        self.suppressions = suppressions;
+        self
+    }
+    pub fn run(&self, holmes: &mut Engine, core: &mut Core) -> Result<RunReport> {
+        let trace_len = self.limits.trace_len;
+        let mut tracker = Tracker::new(self.budgets.clone());
         schema::setup(holmes)?;
-        load_files(holmes, &in_paths)?;
+        load_files(holmes, &self.in_paths)?;
+        load_debs(holmes)?;
         info!("Files loaded");
-        basic_setup(holmes)?;
-        core.run(holmes.quiesce()).unwrap();
+        let lift_budget = tracker.begin(Stage::Lift);
+        let lift_errors = LiftErrors::default();
+        basic_setup(holmes, &lift_budget, &lift_errors, self.lift_cache.clone(), self.shingle)?;
+        if self.skip_dyn {
+            rule!(holmes, skip_dyn_all: skip_func(name, addr) <= link_pad(name, [_], tgt))?;
+        }
+        let mut lib_summaries = self.lib_summaries.clone();
+        if self.model {
+            lib_summaries.push(lib_summary::bundled_model());
+        }
+        lib_summary::apply(holmes, &lib_summaries)?;
+        tracker.quiesce(holmes, core);
         info!("Basic analysis complete");
+        let unresolved = load_truth(holmes, &self.truth)?;
         setup_stage2(holmes)?;
-        core.run(holmes.quiesce()).unwrap();
+        tracker.quiesce(holmes, core);
         info!("Basic analysis post-processing complete");
-        if kprop {
-            const_prop(holmes)?;
-            core.run(holmes.quiesce()).unwrap();
+        if self.kprop {
+            const_prop(holmes, &mut tracker, core)?;
+            tracker.quiesce(holmes, core);
             info!("Constant propagation complete");
             str_const(holmes)?;
-            core.run(holmes.quiesce()).unwrap();
+            tracker.quiesce(holmes, core);
             info!("String constant detection complete");
             printf_formats(holmes)?;
-            core.run(holmes.quiesce()).unwrap();
+            tracker.quiesce(holmes, core);
             info!("Printf-like argument usage information detected");
         }
-        uaf_stage1(holmes)?;
-        core.run(holmes.quiesce()).unwrap();
+        info!("{} BAP round-trips so far", analyses::bap_calls());
+        let flow_budget = tracker.begin(Stage::Flow);
+        uaf_stage1(holmes, &flow_budget, self.summaries, &self.limits)?;
+        tracker.quiesce(holmes, core);
         info!("UAF Stage 1 complete");
         uaf_stage2(holmes)?;
-        core.run(holmes.quiesce()).unwrap();
+        tracker.quiesce(holmes, core);
         info!("UAF Stage 2 complete");
         info!("Starting trace with length {}", trace_len);
-        uaf_trace_stage1(holmes, trace_len)?;
-        core.run(holmes.quiesce()).unwrap();
+        let trace_budget = tracker.begin(Stage::Trace);
+        uaf_trace_stage1(holmes, trace_len, &trace_budget, self.summaries)?;
+        tracker.quiesce(holmes, core);
         info!("UAF Tracing Stage 1 complete");
         uaf_trace_stage2(holmes)?;
-        core.run(holmes.quiesce()).unwrap();
+        tracker.quiesce(holmes, core);
         info!("UAF Tracing Stage 2 complete");
-        grading(holmes)?;
-        core.run(holmes.quiesce()).unwrap();
+        let grade_budget = tracker.begin(Stage::Grade);
+        finalize(holmes, &grade_budget)?;
+        tracker.quiesce(holmes, core);
+        // Suppressions are settled before grading, so its rules only judge what is left
+        let suppressed = suppress(holmes, &self.suppressions)?;
+        grading(holmes, !self.truth.is_empty())?;
+        tracker.quiesce(holmes, core);
         info!("Grading Complete");
-        Ok(())
-    })
+        let mut report = RunReport { budget: tracker.finish(), ..RunReport::default() };
+        // Nothing is lifted after this, as everything live was reached by grading
+        record_lift_errors(holmes, &lift_errors)?;
+        report.cut_sites = cut_sites(holmes)?;
+        report.cutoffs = cutoffs(&report.cut_sites);
+        report.unresolved = unresolved;
+        report.suppressed = suppressed;
+        if self.symex {
+            report.verdicts = symex_confirm(holmes, &symex::Solver::from_env(), &self.limits)?;
+            info!("Symbolic confirmation complete");
+        }
+        if let Some(ref args) = self.emulate {
+            report.emulated = emulate_findings(holmes, args, EMU_STEPS)?;
+            info!("Emulation complete");
+        }
+        Ok(report)
+    }
+}
+
+// Asserts an expected fact for each annotation whose binary is loaded and whose sites resolve,
+// and gives the labels of the rest
+pub fn load_truth(holmes: &mut Engine, annotations: &[truth::Annotation]) -> Result<Vec<String>> {
+    use std::collections::HashMap;
+    use std::path::Path;
+    if annotations.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut bins = Vec::new();
+    for row in query!(holmes, binary(id, path, [_], [_]))? {
+        let id = row[0].get().downcast_ref::<String>().unwrap().clone();
+        bins.push((id, row[1].get().downcast_ref::<String>().unwrap().clone()));
+    }
+    let symbols = symbols::load(holmes)?;
+    // Line tables are only read for binaries that have line sites
+    let mut lines: HashMap<String, Vec<(String, u32, u64)>> = HashMap::new();
+    let mut unresolved = Vec::new();
+    for ann in annotations.iter() {
+        let bin = bins.iter().find(|&&(_, ref path)| {
+            Path::new(path).file_name().map_or(false, |name| name.to_string_lossy() == ann.binary)
+        });
+        let &(ref id, ref path) = match bin {
+            Some(bin) => bin,
+            None => {
+                unresolved.push(ann.label.clone());
+                continue;
+            }
+        };
+        let syms = symbols.get(id).cloned().unwrap_or_default();
+        let wants_lines = |site: &Option<truth::Site>| match *site {
+            Some(truth::Site::Line(..)) => true,
+            _ => false,
+        };
+        if !lines.contains_key(id) && (wants_lines(&ann.alloc) || wants_lines(&ann.use_)) {
+            lines.insert(id.clone(), truth::lines(Path::new(path)));
+        }
+        let table = lines.get(id).cloned().unwrap_or_default();
+        let range = |site: &Option<truth::Site>| match *site {
+            Some(ref site) => truth::resolve(site, &syms, &table),
+            None => Some((0, u64::max_value())),
+        };
+        let (alloc, use_) = match (range(&ann.alloc), range(&ann.use_)) {
+            (Some(alloc), Some(use_)) => (alloc, use_),
+            _ => {
+                unresolved.push(ann.label.clone());
+                continue;
+            }
+        };
+        let bv = |addr: u64| BitVector::from_u64(addr, 64);
+        let (name, checker, bad, label) = (id.clone(), ann.checker.clone(), ann.bad, ann.label.clone());
+        let (alloc_lo, alloc_hi, use_lo, use_hi) = (bv(alloc.0), bv(alloc.1), bv(use_.0), bv(use_.1));
+        fact!(holmes, expected(name, checker, bad, label, alloc_lo, alloc_hi, use_lo, use_hi))?;
+    }
+    Ok(unresolved)
+}
+
+// Runs symbolic execution from the allocation site of each use-after-free to its use, records a
+// symex_verdict for each, and gives how many got each verdict.
+pub fn symex_confirm(holmes: &mut Engine, solver: &symex::Solver, limits: &Limits) -> Result<Vec<(String, usize)>> {
+    use std::collections::HashMap;
+    let mut progs: HashMap<String, symex::Program> = HashMap::new();
+    for row in query!(holmes, lift(name, addr, sema, fall, [_], is_call, is_ret))? {
+        let name = row[0].get().downcast_ref::<String>().unwrap().clone();
+        let addr = row[1].get().downcast_ref::<BitVector>().unwrap().clone();
+        let block = symex::Block {
+            sema: row[2].get().downcast_ref::<Sema>().unwrap().clone(),
+            fall: row[3].get().downcast_ref::<BitVector>().unwrap().clone(),
+            is_call: *row[4].get().downcast_ref::<bool>().unwrap(),
+            is_ret: *row[5].get().downcast_ref::<bool>().unwrap(),
+        };
+        progs.entry(name).or_insert_with(symex::Program::default).blocks.insert(addr, block);
+    }
+    let mut frees = Vec::new();
+    for row in query!(holmes, free_call(name, addr))? {
+        frees.push((row[0].clone(), row[1].clone(), var::get_arg0()));
+    }
+    for row in query!(holmes, call_frees(name, addr, arg))? {
+        frees.push((row[0].clone(), row[1].clone(), row[2].get().downcast_ref::<var::HVar>().unwrap().clone()));
+    }
+    for (name, addr, arg) in frees {
+        let name = name.get().downcast_ref::<String>().unwrap().clone();
+        let addr = addr.get().downcast_ref::<BitVector>().unwrap().clone();
+        let args = progs.entry(name).or_insert_with(symex::Program::default).frees.entry(addr).or_insert_with(Vec::new);
+        if !args.contains(&arg) {
+            args.push(arg);
+        }
+    }
+    let suppressed = suppressed(holmes)?;
+    let mut findings = query!(holmes, use_after_free(name, src, [_], sink_name, sink, loc))?;
+    findings.sort();
+    findings.dedup();
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for row in findings {
+        if suppressed.contains(&row[..4].to_vec()) {
+            continue;
+        }
+        let name = row[0].get().downcast_ref::<String>().unwrap().clone();
+        let src = row[1].get().downcast_ref::<BitVector>().unwrap().clone();
+        let sink_name = row[2].get().downcast_ref::<String>().unwrap().clone();
+        let sink = row[3].get().downcast_ref::<BitVector>().unwrap().clone();
+        let loc = row[4].get().downcast_ref::<var::HVar>().unwrap().clone();
+        let verdict = match progs.get(&name) {
+            // The executor only follows one binary's code
+            Some(prog) if sink_name == name => {
+                symex::confirm(prog, &src, &sink, &loc, solver, limits.trace_len, limits.stack_depth)
+            }
+            _ => symex::Verdict::Unknown("use is in another binary".to_string()),
+        };
+        count_outcome(&mut counts, verdict.name());
+        let (kind, detail) = (verdict.name().to_string(), verdict.detail());
+        fact!(holmes, symex_verdict(name, src, sink_name, sink, kind, detail))?;
+    }
+    counts.sort();
+    Ok(counts)
+}
+
+// Instructions the emulator runs per finding before giving up on reaching the use
+const EMU_STEPS: usize = 1000000;
+
+fn count_outcome(counts: &mut Vec<(String, usize)>, kind: &str) {
+    match counts.iter().position(|&(ref k, _)| k == kind) {
+        Some(idx) => counts[idx].1 += 1,
+        None => counts.push((kind.to_string(), 1)),
+    }
+}
+
+// Runs each use-after-free's binary from main under the emulator, steering along the blocks its
+// trace went through, records an emu_verdict for each and gives how many got each outcome.
+pub fn emulate_findings(holmes: &mut Engine, args: &[String], max_steps: usize) -> Result<Vec<(String, usize)>> {
+    use std::collections::{HashMap, HashSet};
+    use num::ToPrimitive;
+    let to_u64 = |v: &Value| v.get().downcast_ref::<BitVector>().unwrap().to_u64().unwrap_or(0);
+    let mut paths = HashMap::new();
+    for row in query!(holmes, binary(id, path, [_], [_]))? {
+        let id = row[0].get().downcast_ref::<String>().unwrap().clone();
+        paths.insert(id, row[1].get().downcast_ref::<String>().unwrap().clone());
+    }
+    let mut falls = HashMap::new();
+    let mut calls = HashSet::new();
+    let mut rets = HashSet::new();
+    for row in query!(holmes, lift(name, addr, [_], fall, [_], call, ret))? {
+        let name = row[0].get().downcast_ref::<String>().unwrap().clone();
+        let addr = to_u64(&row[1]);
+        if *row[3].get().downcast_ref::<bool>().unwrap() {
+            calls.insert((name.clone(), addr));
+        }
+        if *row[4].get().downcast_ref::<bool>().unwrap() {
+            rets.insert((name.clone(), addr));
+        }
+        falls.insert((name, addr), to_u64(&row[2]));
+    }
+    let mut frees = HashMap::new();
+    let free_rows = query!(holmes, free_call(name, addr))?.into_iter().chain(query!(holmes, call_frees(name, addr, [_]))?);
+    for row in free_rows {
+        let name = row[0].get().downcast_ref::<String>().unwrap().clone();
+        frees.entry(name).or_insert_with(HashSet::new).insert(to_u64(&row[1]));
+    }
+    let mut edges = HashSet::new();
+    for row in query!(holmes, succ(name, src, dst))? {
+        let name = row[0].get().downcast_ref::<String>().unwrap().clone();
+        edges.insert((name, to_u64(&row[1]), to_u64(&row[2])));
+    }
+    // Block, steps taken and whether the object was freed yet, for every trace from a source
+    let mut traces: HashMap<(String, u64, u64), Vec<(u64, u64, bool)>> = HashMap::new();
+    for row in query!(holmes, path_alias_trace(name, src, sa, [_], cur_name, cur, [_], freed, steps))? {
+        if row[0] != row[3] {
+            continue;
+        }
+        let key = (
+            row[0].get().downcast_ref::<String>().unwrap().clone(),
+            to_u64(&row[1]),
+            *row[2].get().downcast_ref::<u64>().unwrap(),
+        );
+        let fact = (to_u64(&row[4]), *row[6].get().downcast_ref::<u64>().unwrap(), *row[5].get().downcast_ref::<bool>().unwrap());
+        let facts = traces.entry(key).or_insert_with(Vec::new);
+        if !facts.contains(&fact) {
+            facts.push(fact);
+        }
+    }
+    let suppressed = suppressed(holmes)?;
+    let mut findings = query!(holmes, use_after_free(name, src, sa, sink_name, sink))?;
+    findings.sort();
+    findings.dedup();
+    let mut counts = Vec::new();
+    for row in findings {
+        if suppressed.contains(&vec![row[0].clone(), row[1].clone(), row[3].clone(), row[4].clone()]) {
+            continue;
+        }
+        let name = row[0].get().downcast_ref::<String>().unwrap().clone();
+        let src = row[1].get().downcast_ref::<BitVector>().unwrap().clone();
+        let sa = *row[2].get().downcast_ref::<u64>().unwrap();
+        let sink_name = row[3].get().downcast_ref::<String>().unwrap().clone();
+        let sink = row[4].get().downcast_ref::<BitVector>().unwrap().clone();
+        let (src64, sink64) = (to_u64(&row[1]), to_u64(&row[4]));
+        let outcome = if sink_name != name {
+            emu::Outcome::Unreached("use is in another binary".to_string())
+        } else {
+            // A trace moves along succ edges, from a call to its return site when stepping over
+            // it, and out of a return to wherever the stack says
+            let adjacent = |from: u64, to: u64| {
+                edges.contains(&(name.clone(), from, to)) || rets.contains(&(name.clone(), from)) ||
+                    (calls.contains(&(name.clone(), from)) && falls.get(&(name.clone(), from)) == Some(&to))
+            };
+            let facts = traces.get(&(name.clone(), src64, sa)).cloned().unwrap_or_default();
+            let witness = emu::Witness {
+                source_ret: falls.get(&(name.clone(), src64)).cloned().unwrap_or(0),
+                sink: sink64,
+                sink_end: falls.get(&(name.clone(), sink64)).cloned().unwrap_or(sink64 + 1),
+                blocks: emu::single_trace(&facts, sink64, &frees.get(&name).cloned().unwrap_or_default(), &adjacent),
+            };
+            let path = paths.get(&name).cloned().unwrap_or_default();
+            let mut argv = vec![path.clone()];
+            argv.extend(args.iter().cloned());
+            let mut contents = Vec::new();
+            let read = std::fs::File::open(&path).and_then(|mut fd| {
+                use std::io::Read;
+                fd.read_to_end(&mut contents)
+            });
+            match read.ok().and_then(|_| emu::Emulator::load(&contents, &argv)) {
+                Some(mut emulator) => emulator.run(&witness, max_steps),
+                None => emu::Outcome::Unreached(format!("couldn't load {}", path)),
+            }
+        };
+        count_outcome(&mut counts, outcome.name());
+        let (kind, detail) = (outcome.name().to_string(), outcome.detail());
+        fact!(holmes, emu_verdict(name, src, sink_name, sink, kind, detail))?;
+    }
+    counts.sort();
+    Ok(counts)
+}
+
+/// Why lifting gave up at each live address it couldn't decode. The lift func fills this in as
+/// it runs, and record_lift_errors turns it into lift_error facts once lifting is done.
+#[derive(Debug, Clone, Default)]
+pub struct LiftErrors {
+    errors: Arc<Mutex<Vec<(String, BitVector, String)>>>,
+}
+
+impl LiftErrors {
+    pub fn push(&self, name: &str, addr: &BitVector, reason: String) {
+        self.errors.lock().unwrap().push((name.to_string(), addr.clone(), reason));
+    }
+    pub fn take(&self) -> Vec<(String, BitVector, String)> {
+        ::std::mem::replace(&mut *self.errors.lock().unwrap(), Vec::new())
+    }
+}
+
+pub fn record_lift_errors(holmes: &mut Engine, errors: &LiftErrors) -> Result<()> {
+    for (name, addr, reason) in errors.take() {
+        fact!(holmes, lift_error(name, addr, reason))?;
+    }
+    Ok(())
+}
+
+// Live addresses lifting gave up on, grouped by binary and sorted by address
+pub fn lift_error_summary(holmes: &mut Engine) -> Result<Vec<(String, Vec<(BitVector, String)>)>> {
+    let mut by_bin: Vec<(String, Vec<(BitVector, String)>)> = Vec::new();
+    for row in query!(holmes, lift_error(name, addr, reason))? {
+        let name = row[0].get().downcast_ref::<String>().unwrap().clone();
+        let addr = row[1].get().downcast_ref::<BitVector>().unwrap().clone();
+        let reason = row[2].get().downcast_ref::<String>().unwrap().clone();
+        match by_bin.iter().position(|&(ref bin, _)| bin == &name) {
+            Some(idx) => by_bin[idx].1.push((addr, reason)),
+            None => by_bin.push((name, vec![(addr, reason)])),
+        }
+    }
+    for &mut (_, ref mut errs) in by_bin.iter_mut() {
+        errs.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+    Ok(by_bin)
+}
+
+// Flows found by the flow stage that the trace stage never confirmed. When tracing was
+// truncated these are reported as unconfirmed rather than silently dropped.
+pub fn unconfirmed(holmes: &mut Engine) -> Result<Vec<Vec<Value>>> {
+    let confirmed = query!(holmes, use_after_free {source_binary = name, source = src, alias_set = sa})?;
+    let mut flows = query!(holmes, use_after_free_flow {source_binary = name, source = src, alias_set = sa})?;
+    flows.sort();
+    flows.dedup();
+    Ok(flows.into_iter().filter(|flow| !confirmed.contains(flow)).collect())
+}
+
+// Allocation sites some path from which was stopped by a context-sensitivity bound, as
+// (bound, binary, site)
+pub fn cut_sites(holmes: &mut Engine) -> Result<Vec<(String, String, BitVector)>> {
+    let mut sites: Vec<_> = query!(holmes, cutoff(bound, name, src))?
+        .into_iter()
+        .map(|row| {
+            (
+                row[0].get().downcast_ref::<String>().unwrap().clone(),
+                row[1].get().downcast_ref::<String>().unwrap().clone(),
+                row[2].get().downcast_ref::<BitVector>().unwrap().clone(),
+            )
+        })
+        .collect();
+    sites.sort();
+    sites.dedup();
+    Ok(sites)
+}
+
+// For each context-sensitivity bound, how many of cut_sites it stopped
+pub fn cutoffs(sites: &[(String, String, BitVector)]) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for &(ref bound, _, _) in sites.iter() {
+        match counts.iter().position(|&(ref b, _)| b == bound) {
+            Some(idx) => counts[idx].1 += 1,
+            None => counts.push((bound.clone(), 1)),
+        }
+    }
+    counts.sort();
+    counts
+}
+
+// Functions findings were reported under, as true positives and then as false positives.
+// Grading only judges unsuppressed findings, so suppressed ones don't count.
+pub fn graded(holmes: &mut Engine) -> Result<(HashSet<String>, HashSet<String>)> {
+    let mut true_positives = HashSet::new();
+    for row in query!(holmes, true_positive([_], [_], parent))? {
+        true_positives.insert(row[0].get().downcast_ref::<String>().unwrap().clone());
+    }
+    let mut false_positives = HashSet::new();
+    for row in query!(holmes, false_positive([_], [_], parent))? {
+        false_positives.insert(row[0].get().downcast_ref::<String>().unwrap().clone());
+    }
+    Ok((true_positives, false_positives))
+}
+
+// Allocation sites with findings that no truth annotation labels, counted per checker and binary
+// path. Like graded, sites whose every finding is suppressed don't count.
+pub fn unmatched(holmes: &mut Engine) -> Result<Vec<(String, String, usize)>> {
+    use std::collections::HashMap;
+    use num::ToPrimitive;
+    let to_u64 = |v: &Value| v.get().downcast_ref::<BitVector>().unwrap().to_u64().unwrap_or(0);
+    let as_str = |v: &Value| v.get().downcast_ref::<String>().unwrap().clone();
+    let mut labelled = HashSet::new();
+    let mut labels = query!(holmes, true_positive(name, src, [_]))?;
+    labels.extend(query!(holmes, false_positive(name, src, [_]))?);
+    for row in labels {
+        labelled.insert((as_str(&row[0]), to_u64(&row[1])));
+    }
+    let mut sites = HashSet::new();
+    for row in query!(holmes, unsuppressed(name, src, [_], [_]))? {
+        let site = (as_str(&row[0]), to_u64(&row[1]));
+        if !labelled.contains(&site) {
+            sites.insert(site);
+        }
+    }
+    let mut paths = HashMap::new();
+    for row in query!(holmes, binary(id, path, [_], [_]))? {
+        paths.insert(as_str(&row[0]), as_str(&row[1]));
+    }
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for (bin, _) in sites {
+        *counts.entry(paths.get(&bin).cloned().unwrap_or(bin)).or_insert(0) += 1;
+    }
+    let mut out: Vec<(String, String, usize)> = counts.into_iter().map(|(path, n)| ("uaf".to_string(), path, n)).collect();
+    out.sort();
+    Ok(out)
+}
+
+// Location-independent signatures of every use-after-free, sorted
+pub fn signatures(holmes: &mut Engine) -> Result<Vec<signature::Signature>> {
+    let mut sigs: Vec<signature::Signature> = signature::collect(holmes)?.into_iter().map(|(_, sig)| sig).collect();
+    sigs.sort();
+    sigs.dedup();
+    Ok(sigs)
+}
+
+// Asserts a suppressed fact for each finding one of the rules matches and an unsuppressed fact
+// for every other, and gives how many were suppressed
+pub fn suppress(holmes: &mut Engine, rules: &[suppress::Suppression]) -> Result<usize> {
+    if rules.is_empty() {
+        for row in query!(holmes, use_after_free(name, src, [_], sink_name, sink))? {
+            let name = row[0].get().downcast_ref::<String>().unwrap().clone();
+            let src = row[1].get().downcast_ref::<BitVector>().unwrap().clone();
+            let sink_name = row[2].get().downcast_ref::<String>().unwrap().clone();
+            let sink = row[3].get().downcast_ref::<BitVector>().unwrap().clone();
+            fact!(holmes, unsuppressed(name, src, sink_name, sink))?;
+        }
+        return Ok(0);
+    }
+    let mut count = 0;
+    for (key, sig) in signature::collect(holmes)? {
+        let name = key[0].get().downcast_ref::<String>().unwrap().clone();
+        let src = key[1].get().downcast_ref::<BitVector>().unwrap().clone();
+        let sink_name = key[2].get().downcast_ref::<String>().unwrap().clone();
+        let sink = key[3].get().downcast_ref::<BitVector>().unwrap().clone();
+        match suppress::find(rules, &sig) {
+            Some(rule) => {
+                let why = rule.justification.clone();
+                fact!(holmes, suppressed(name, src, sink_name, sink, why))?;
+                count += 1;
+            }
+            None => fact!(holmes, unsuppressed(name, src, sink_name, sink))?,
+        }
+    }
+    Ok(count)
+}
+
+// Source binary, source, sink binary and sink of each suppressed finding
+pub fn suppressed(holmes: &mut Engine) -> Result<Vec<Vec<Value>>> {
+    let mut rows = query!(holmes, suppressed(name, src, sink_name, sink))?;
+    rows.sort();
+    rows.dedup();
+    Ok(rows)
 }
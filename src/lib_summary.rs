@@ -0,0 +1,207 @@
+use holmes::simple::*;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use elf;
+use store;
+use var::{self, HVar};
+
+// What a library function does with its arguments, by argument index
+#[derive(Debug, Clone, Default, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct FuncSummary {
+    pub frees: Vec<u8>,
+    pub returns: Vec<u8>,
+    pub derefs: Vec<u8>,
+    // Held on to by the container argument 0 points to
+    pub keeps: Vec<u8>,
+    // Containers whose contents may come back as the return value
+    pub yields: Vec<u8>,
+}
+
+/// Function summaries for one shared object, as derived by a `Pipeline::summaries` run over it.
+#[derive(Debug, Clone, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct LibSummary {
+    pub build_id: String,
+    pub path: String,
+    pub funcs: BTreeMap<String, FuncSummary>,
+}
+
+/// Directory of library summaries, one file per build-id.
+pub struct SummaryStore {
+    dir: PathBuf,
+}
+
+impl SummaryStore {
+    pub fn new(dir: PathBuf) -> Self {
+        SummaryStore { dir: dir }
+    }
+    // TIAMAT_SUMMARIES picks the directory, otherwise ~/.tiamat/summaries
+    pub fn from_env() -> Option<Self> {
+        match env::var("TIAMAT_SUMMARIES") {
+            Ok(dir) => Some(SummaryStore::new(PathBuf::from(dir))),
+            Err(_) => {
+                env::home_dir().map(|home| SummaryStore::new(home.join(".tiamat").join("summaries")))
+            }
+        }
+    }
+    fn path(&self, build_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", build_id))
+    }
+    pub fn get(&self, build_id: &str) -> Option<LibSummary> {
+        let mut text = String::new();
+        match File::open(self.path(build_id)) {
+            Ok(mut fd) => {
+                if fd.read_to_string(&mut text).is_err() {
+                    return None;
+                }
+            }
+            Err(_) => return None,
+        }
+        store::from_text(&text)
+    }
+    // Accepts either the library itself, whose build-id is read out of it, or a bare build-id
+    pub fn lookup(&self, lib: &str) -> Option<LibSummary> {
+        let mut contents = Vec::new();
+        match File::open(lib).and_then(|mut fd| fd.read_to_end(&mut contents)) {
+            Ok(_) => elf::build_id(&contents).and_then(|id| self.get(&id)),
+            Err(_) => self.get(lib),
+        }
+    }
+    pub fn put(&self, summary: &LibSummary) -> io::Result<()> {
+        let path = self.path(&summary.build_id);
+        let tmp = path.with_extension(format!("tmp.{}", ::std::process::id()));
+        fs::create_dir_all(&self.dir)?;
+        File::create(&tmp)?.write_all(store::to_text(summary).as_bytes())?;
+        fs::rename(&tmp, &path)
+    }
+}
+
+// Shipped description of libc, POSIX and GLib, see the file itself for the format
+const BUNDLED_MODEL: &'static str = include_str!("../models/libc.model");
+
+/// Reads a hand-written model file into the same shape as a derived summary.
+pub fn parse_model(path: &str, text: &str) -> ::std::result::Result<LibSummary, String> {
+    let mut funcs = BTreeMap::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let mut words = line.split('#').next().unwrap().split_whitespace();
+        let name = match words.next() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let mut func = FuncSummary::default();
+        let mut effect = None;
+        for word in words {
+            match word {
+                "uses" | "frees" | "returns" | "keeps" | "yields" => effect = Some(word),
+                _ => {
+                    let idx = word.parse::<u8>().map_err(|_| {
+                        format!("{}:{}: expected an argument number, got {}", path, lineno + 1, word)
+                    })?;
+                    match effect {
+                        Some("uses") => func.derefs.push(idx),
+                        Some("frees") => func.frees.push(idx),
+                        Some("returns") => func.returns.push(idx),
+                        Some("keeps") => func.keeps.push(idx),
+                        Some(_) => func.yields.push(idx),
+                        None => {
+                            return Err(format!("{}:{}: argument {} given before an effect", path, lineno + 1, idx))
+                        }
+                    }
+                }
+            }
+        }
+        funcs.insert(name, func);
+    }
+    Ok(LibSummary {
+        build_id: String::new(),
+        path: path.to_string(),
+        funcs: funcs,
+    })
+}
+
+pub fn bundled_model() -> LibSummary {
+    parse_model("models/libc.model", BUNDLED_MODEL).unwrap()
+}
+
+/// Gathers the `summary_*` facts of every loaded binary that has a build-id, keyed by the name
+/// of the function's entry symbol.
+pub fn collect(holmes: &mut Engine) -> Result<Vec<LibSummary>> {
+    let mut libs: Vec<(String, LibSummary)> = Vec::new();
+    for row in query!(holmes, binary(id, path, [_], build_id))? {
+        let build_id = row[2].get().downcast_ref::<String>().unwrap().clone();
+        if build_id.is_empty() {
+            continue;
+        }
+        libs.push((
+            row[0].get().downcast_ref::<String>().unwrap().clone(),
+            LibSummary {
+                build_id: build_id,
+                path: row[1].get().downcast_ref::<String>().unwrap().clone(),
+                funcs: BTreeMap::new(),
+            },
+        ));
+    }
+    let frees = query!(holmes, summary_frees(bin, addr, arg) & entry(bin, name, addr, [_]))?;
+    let returns = query!(holmes, summary_returns(bin, addr, arg) & entry(bin, name, addr, [_]))?;
+    let derefs = query!(holmes, summary_derefs(bin, addr, arg) & entry(bin, name, addr, [_]))?;
+    for (kind, rows) in vec![frees, returns, derefs].into_iter().enumerate() {
+        for row in rows {
+            let bin = row[0].get().downcast_ref::<String>().unwrap();
+            let idx = match var::arg_index(row[2].get().downcast_ref::<HVar>().unwrap()) {
+                Some(idx) => idx,
+                None => continue,
+            };
+            let name = row[3].get().downcast_ref::<String>().unwrap().clone();
+            if let Some(&mut (_, ref mut lib)) = libs.iter_mut().find(|&&mut (ref id, _)| id == bin) {
+                let func = lib.funcs.entry(name).or_insert_with(FuncSummary::default);
+                let args = match kind {
+                    0 => &mut func.frees,
+                    1 => &mut func.returns,
+                    _ => &mut func.derefs,
+                };
+                if !args.contains(&idx) {
+                    args.push(idx);
+                    args.sort();
+                }
+            }
+        }
+    }
+    Ok(libs.into_iter().map(|(_, lib)| lib).collect())
+}
+
+/// Asserts the summaries of libraries that are not loaded themselves, so calls into them through
+/// the link table free, return, use or keep their arguments as the real code would. Models and
+/// stored summaries go through here together, as the rules may only be registered once.
+pub fn apply(holmes: &mut Engine, libs: &[LibSummary]) -> Result<()> {
+    for lib in libs {
+        for (name, func) in lib.funcs.iter() {
+            let kinds = [(0, &func.frees), (1, &func.returns), (2, &func.derefs), (3, &func.keeps), (4, &func.yields)];
+            for &(kind, ref args) in kinds.iter() {
+                for &idx in args.iter() {
+                    if idx > 5 {
+                        warn!("{} in {}: argument {} is not passed in a register", name, lib.path, idx);
+                        continue;
+                    }
+                    let name = name.clone();
+                    let arg = var::get_arg_n(idx);
+                    match kind {
+                        0 => fact!(holmes, lib_frees(name, arg))?,
+                        1 => fact!(holmes, lib_returns(name, arg))?,
+                        2 => fact!(holmes, lib_derefs(name, arg))?,
+                        3 => fact!(holmes, lib_keeps(name, arg))?,
+                        _ => fact!(holmes, lib_yields(name, arg))?,
+                    }
+                }
+            }
+        }
+    }
+    holmes_exec!(holmes, {
+        rule!(lib_call_frees: call_frees(name, addr, arg) <= lib_frees(func_name, arg) & link_pad(name, func_name, tgt) & succ(name, addr, tgt, (true)));
+        rule!(lib_call_returns: call_returns(name, addr, arg) <= lib_returns(func_name, arg) & link_pad(name, func_name, tgt) & succ(name, addr, tgt, (true)));
+        rule!(lib_call_derefs: func_uses(name, addr, arg) <= lib_derefs(func_name, arg) & link_pad(name, func_name, tgt) & succ(name, addr, tgt, (true)));
+        rule!(lib_call_keeps: call_keeps(name, addr, arg) <= lib_keeps(func_name, arg) & link_pad(name, func_name, tgt) & succ(name, addr, tgt, (true)));
+        rule!(lib_call_yields: call_yields(name, addr, arg) <= lib_yields(func_name, arg) & link_pad(name, func_name, tgt) & succ(name, addr, tgt, (true)))
+    })
+}
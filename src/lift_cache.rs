@@ -0,0 +1,73 @@
+use bap::basic::Arch;
+use bap::high::bil::Statement;
+use bap::high::bitvector::BitVector;
+use sha2::{Sha256, Digest};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use ids;
+use store;
+
+// Bump whenever Insn or the lifter's output changes shape, so stale entries are just misses
+const CACHE_VERSION: u32 = 2;
+
+// Everything lift_wrap needs from a single decoded instruction
+#[derive(Debug, Clone, RustcDecodable, RustcEncodable)]
+pub struct Insn {
+    pub stmts: Vec<Statement>,
+    pub len: usize,
+    pub disasm: String,
+    pub is_call: bool,
+    pub is_ret: bool,
+    pub may_jump: bool,
+}
+
+/// Content-addressed store of lifted instructions, shared between databases.
+///
+/// Entries are keyed by (arch, byte window, address), so a library seen by an earlier run is
+/// lifted without going back into BAP. Only instructions that decoded are stored; a window BAP
+/// gave up on is tried again next time. Nothing is cached unless a run asks for it, see
+/// Pipeline::lift_cache.
+#[derive(Debug, Clone)]
+pub struct LiftCache {
+    dir: PathBuf,
+}
+
+impl LiftCache {
+    pub fn new(dir: PathBuf) -> Self {
+        LiftCache { dir: dir }
+    }
+    pub fn key(arch: &Arch, window: &[u8], addr: &BitVector) -> String {
+        let mut hasher = Sha256::default();
+        hasher.input(format!("{}:{}:{}:", CACHE_VERSION, *arch as u32, addr).as_bytes());
+        hasher.input(window);
+        ids::hex(&hasher.result())
+    }
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[0..2]).join(key)
+    }
+    pub fn get(&self, key: &str) -> Option<Insn> {
+        let mut text = String::new();
+        match File::open(self.path(key)) {
+            Ok(mut fd) => {
+                if fd.read_to_string(&mut text).is_err() {
+                    return None;
+                }
+            }
+            Err(_) => return None,
+        }
+        store::from_text(&text)
+    }
+    pub fn put(&self, key: &str, insn: &Insn) {
+        let path = self.path(key);
+        // Write then rename, so concurrent runs never see a partial entry
+        let tmp = path.with_extension(format!("tmp.{}", ::std::process::id()));
+        let res = fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| File::create(&tmp))
+            .and_then(|mut fd| fd.write_all(store::to_text(insn).as_bytes()))
+            .and_then(|_| fs::rename(&tmp, &path));
+        if let Err(e) = res {
+            warn!("Could not write lift cache entry {}: {}", path.display(), e);
+        }
+    }
+}
@@ -6,6 +6,7 @@ use ubvs::UBVSType;
 use bvlist::BVListType;
 use chop::ChopType;
 use sema::SemaType;
+use cond::CondType;
 
 pub fn setup(holmes: &mut Engine) -> Result<()> {
     try!(holmes.add_type(Arc::new(BitVectorType)));
@@ -15,9 +16,11 @@ pub fn setup(holmes: &mut Engine) -> Result<()> {
     try!(holmes.add_type(Arc::new(SemaType)));
     try!(holmes.add_type(Arc::new(VarType)));
     try!(holmes.add_type(Arc::new(ChopType)));
+    try!(holmes.add_type(Arc::new(CondType)));
     try!(holmes_exec!(holmes, {
-        predicate!(file(string, largebytes));
-        // Filename, contents, start addr, end addr, r, w, x
+        predicate!(binary([id string "SHA-256 of the contents, used as the binary name everywhere else"], [path string], [size uint64], [build_id string "GNU build-id, or empty if there is none"]) : "Loaded binaries");
+        predicate!(file([binary string], [contents largebytes]));
+        // Binary, id, contents, start addr, end addr, r, w, x
         predicate!(segment(string,
                            uint64,
                            largebytes,
@@ -36,33 +39,68 @@ pub fn setup(holmes: &mut Engine) -> Result<()> {
                 [is_call bool],
                 [is_ret bool]));
 
-        predicate!(succ(string, bitvector, bitvector, bool));
+        predicate!(shingle(
+                [binary string],
+                [address bitvector],
+                [decoded bool "False where the bytes don't decode, the instruction runs off the segment, or at the segment's end"],
+                [bil sema],
+                [fallthrough bitvector],
+                [disassembly largestring],
+                [is_call bool],
+                [is_ret bool],
+                [may_jump bool]) : "Instruction decoded at every offset of an executable segment when shingling");
+        predicate!(shingle_block([binary string], [address bitvector], [bil sema], [disassembly largestring], [fallthrough bitvector], [is_call bool], [is_ret bool], [open bool "The last instruction falls through, so the block may go on"]) : "Prefixes of the block at a live address, built out of shingles");
+        predicate!(succ([binary string], [src bitvector], [dst bitvector], [is_call bool], [cond cond "Holds on entry to src whenever the edge is taken"]));
         predicate!(succ_over(string, bitvector, bitvector));
         predicate!(live(string, bitvector));
+        // Binary, segment id, addr, offset of addr in segment, offset of segment end
         predicate!(seglive(string, uint64, bitvector, uint64, uint64));
+        predicate!(lift_error([binary string], [addr bitvector], [reason string]) : "Live addresses that could not be lifted");
         predicate!(arch(string, arch));
         predicate!(link_pad(string, string, bitvector));
         predicate!(stack([id uint64], [prev uint64], [bin string], [addr bitvector], [len uint64]));
         // Filename, malloc_site, exit, var, freed
         predicate!(path_alias([source_binary string], [malloc_site bitvector], [alias_set uint64], [stack uint64], [chop chop], [cur_binary string], [def_site bitvector], [def_var var], [freed bool]));
-        predicate!(path_alias_trace([source_binary string], [malloc_site bitvector], [alias_set uint64], [stack uint64], [cur_binary string], [def_site bitvector], [def_var var], [freed bool], [steps uint64]));
+        predicate!(path_alias_trace([source_binary string], [malloc_site bitvector], [alias_set uint64], [stack uint64], [cur_binary string], [def_site bitvector], [def_var var], [freed bool], [steps uint64], [cond cond "Branch conditions taken along the trace, relative to the current block"]));
+        predicate!(summary_arg(var));
+        // Binary, function entry, argument, current addr, current var, freed
+        predicate!(summary_alias([binary string], [entry bitvector], [arg var], [addr bitvector], [var var], [freed bool]));
+        predicate!(summary_frees([binary string], [entry bitvector], [arg var]) : "The function may free what arg points to");
+        predicate!(summary_returns([binary string], [entry bitvector], [arg var]) : "arg may flow to the function's return value");
+        predicate!(summary_derefs([binary string], [entry bitvector], [arg var]) : "The function may dereference arg");
+        predicate!(call_frees([binary string], [addr bitvector], [arg var]));
+        predicate!(call_returns([binary string], [addr bitvector], [arg var]));
+        predicate!(call_keeps([binary string], [addr bitvector], [arg var]) : "The call stores arg in the container its first argument points to");
+        predicate!(call_yields([binary string], [addr bitvector], [arg var]) : "The call may return something stored in the container arg points to");
+        predicate!(lib_frees([func_name string], [arg var]) : "From a library summary");
+        predicate!(lib_returns([func_name string], [arg var]) : "From a library summary");
+        predicate!(lib_derefs([func_name string], [arg var]) : "From a library summary");
+        predicate!(lib_keeps([func_name string], [arg var]) : "From a library summary");
+        predicate!(lib_yields([func_name string], [arg var]) : "From a library summary");
         predicate!(free_call(string, bitvector));
         predicate!(malloc_call(string, bitvector));
         // filename, source, errpoint, errvar
         predicate!(use_after_free_flow([source_binary string], [source bitvector "Allocation site for the use-after-free"], [alias_set uint64], [stack uint64 "callstack at time of use"], [sink_binary string], [sink bitvector "Use site for the use after free"], [loc var "Where the pointer was when it was dereferenced"]) : "Possible use-after-free paths");
         predicate!(use_after_free([source_binary string], [source bitvector "Allocation site for the use-after-free"], [alias_set uint64], [sink_binary string], [sink bitvector "Use site for the use after free"], [loc var "Where the pointer was when it was dereferenced"], [stack uint64 "Callstack at time of use"], [len uint64]) : "Possible use-after-free paths");
+        predicate!(symex_verdict([source_binary string], [source bitvector], [sink_binary string], [sink bitvector], [verdict string "feasible, infeasible or unknown"], [detail string "Registers on entry to the allocation site for a feasible path, or why it is unknown"]) : "Symbolic execution of a use-after-free from allocation to use");
+        predicate!(emu_verdict([source_binary string], [source bitvector], [sink_binary string], [sink bitvector], [outcome string "confirmed, forced, refuted or unreached"], [detail string]) : "Emulation of the binary along a use-after-free's trace");
         predicate!(func([binary string], [entry bitvector], [addr bitvector]) : "addr is reachable from the function at entry without a return");
         predicate!(call_site([source_binary string], [source_addr bitvector], [dest_binary string], [dest_addr bitvector]));
         predicate!(path_step([source_binary string], [source_addr bitvector], [dest_binary string], [dest_addr bitvector]));
         predicate!(true_positive([binary string], [addr bitvector], string));
         predicate!(false_positive([binary string], [addr bitvector], string));
         predicate!(deb_file([deb_name string], [contents largebytes]));
+        predicate!(suppressed([source_binary string], [source bitvector], [sink_binary string], [sink bitvector], [justification string]) : "A use-after-free reviewed and accepted, left out of grading and reports");
+        predicate!(unsuppressed([source_binary string], [source bitvector], [sink_binary string], [sink bitvector]) : "A use-after-free no suppression matches, the ones grading judges");
+        predicate!(expected([binary string], [checker string], [bad bool "A flaw to report, rather than code to leave alone"], [label string "Where the annotation came from"], [alloc_lo bitvector], [alloc_hi bitvector], [use_lo bitvector], [use_hi bitvector]) : "Ground truth: a finding whose allocation and use blocks overlap these ranges");
         predicate!(skip_func(string, bitvector));
         predicate!(poss_const(string, bitvector, var, bitvector));
+        predicate!(const_jump([binary string], [addr bitvector], [target bitvector]) : "Computed jump or call at addr whose target is constant");
         predicate!(poss_string(string, bitvector, var, string));
         predicate!(func_uses(string, bitvector, var));
         predicate!(printf_like(string));
         predicate!(is_normal(string, bitvector));
+        predicate!(cutoff([bound string], [source_binary string], [source bitvector]) : "A path from this allocation site was stopped by a context-sensitivity bound");
         predicate!(bad_stack(uint64, string));
         predicate!(good_stack(uint64, string))
     }));
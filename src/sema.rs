@@ -6,8 +6,8 @@ use holmes::pg::RowIter;
 use holmes::pg::dyn::{Type, Value};
 use std::any::Any;
 use std::sync::Arc;
-use rustc_serialize::json::{Json, Decoder, ToJson, encode};
-use rustc_serialize::Decodable;
+use rustc_serialize::json::{Json, ToJson};
+use store;
 
 #[derive(Debug, Clone, Hash, PartialOrd, PartialEq, RustcDecodable, RustcEncodable)]
 pub struct Sema {
@@ -16,8 +16,7 @@ pub struct Sema {
 
 impl ToJson for Sema {
     fn to_json(&self) -> Json {
-        let buf = encode(self).unwrap();
-        Json::from_str(&buf).unwrap()
+        store::encode(self)
     }
 }
 
@@ -34,10 +33,7 @@ impl TypeT for SemaType {
     }
     fn extract(&self, rows: &mut RowIter) -> Option<Value> {
         let raw: Json = rows.next().unwrap();
-        let typed: Sema = {
-            let mut decoder = Decoder::new(raw);
-            Sema::decode(&mut decoder).unwrap()
-        };
+        let typed: Sema = store::decode(raw).unwrap();
         Some(Arc::new(typed))
     }
     fn repr(&self) -> &'static str {
@@ -0,0 +1,261 @@
+use holmes::simple::*;
+use holmes::pg::dyn::Value;
+use bap::high::bitvector::BitVector;
+use num::ToPrimitive;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use ids;
+use symbols;
+
+// Findings as they can be recognised from one run to the next. Fact IDs and addresses move
+// whenever the binary or the analysis changes, so a finding is described instead by the functions
+// it allocates and uses in, where in them, the instructions there, the calls the allocation was
+// returned out of and the calls still open when it gets to the use.
+
+/// One end of a finding
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, RustcDecodable, RustcEncodable)]
+pub struct Place {
+    /// Function holding the block, empty if no symbol covers it
+    pub func: String,
+    /// Offset of the block in the function, or its address if there is no function
+    pub offset: u64,
+    /// Mnemonics of the block's instructions
+    pub mnemonics: Vec<String>,
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}+{:#x} [{}]", self.func, self.offset, self.mnemonics.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, RustcDecodable, RustcEncodable)]
+pub struct Signature {
+    pub checker: String,
+    /// File name of the binary allocating
+    pub binary: String,
+    pub alloc: Place,
+    pub use_: Place,
+    /// Functions whose calls led to the allocation, from the one the use side shares down to the
+    /// one calling the allocating function. Empty when the allocation and the use share a frame.
+    pub alloc_chain: Vec<String>,
+    /// Functions making the calls that are still open at the use, outermost first
+    pub chain: Vec<String>,
+}
+
+impl Signature {
+    /// Hash of everything but the offsets, so it survives code moving around it
+    pub fn fingerprint(&self) -> String {
+        let mut text = String::new();
+        for part in [&self.checker, &self.binary, &self.alloc.func, &self.use_.func].iter() {
+            text.push_str(part);
+            text.push('\0');
+        }
+        for mnemonics in [&self.alloc.mnemonics, &self.use_.mnemonics, &self.alloc_chain, &self.chain].iter() {
+            text.push_str(&mnemonics.join(" "));
+            text.push('\0');
+        }
+        ids::hex(&ids::digest(text.as_bytes()))[..16].to_string()
+    }
+    fn same_funcs(&self, other: &Signature) -> bool {
+        self.checker == other.checker && self.binary == other.binary && self.alloc.func == other.alloc.func &&
+            self.use_.func == other.use_.func && self.alloc_chain == other.alloc_chain && self.chain == other.chain
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} alloc {}", self.fingerprint(), self.checker, self.binary, self.alloc)?;
+        if !self.alloc_chain.is_empty() {
+            write!(f, " from {}", self.alloc_chain.join(" > "))?;
+        }
+        write!(f, " use {}", self.use_)?;
+        if !self.chain.is_empty() {
+            write!(f, " via {}", self.chain.join(" > "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Mnemonic of each line of a block's disassembly
+pub fn mnemonics(disasm: &str) -> Vec<String> {
+    disasm.lines()
+        .filter_map(|line| line.split(|c: char| c.is_whitespace() || c == '(').find(|w| !w.is_empty()))
+        .map(|m| m.to_lowercase())
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Diff {
+    /// Only in the new run
+    pub added: Vec<Signature>,
+    /// Only in the old run
+    pub removed: Vec<Signature>,
+    /// Old and new signature of findings in both
+    pub persisting: Vec<(Signature, Signature)>,
+}
+
+fn distance(a: &Signature, b: &Signature) -> u64 {
+    let d = |x: u64, y: u64| if x > y { x - y } else { y - x };
+    d(a.alloc.offset, b.alloc.offset) + d(a.use_.offset, b.use_.offset)
+}
+
+/// Pairs old findings with new ones, first those that are identical, then those with the same
+/// fingerprint, then those in the same functions whose code has changed. Among candidates the
+/// one with the closest offsets wins.
+pub fn diff(old: &[Signature], new: &[Signature]) -> Diff {
+    let mut out = Diff::default();
+    let mut old_used = vec![false; old.len()];
+    let mut new_used = vec![false; new.len()];
+    let tiers: [&Fn(&Signature, &Signature) -> bool; 3] = [
+        &|a: &Signature, b: &Signature| a == b,
+        &|a: &Signature, b: &Signature| a.fingerprint() == b.fingerprint(),
+        &|a: &Signature, b: &Signature| a.same_funcs(b),
+    ];
+    for matches in tiers.iter() {
+        for (i, o) in old.iter().enumerate() {
+            if old_used[i] {
+                continue;
+            }
+            let best = new.iter()
+                .enumerate()
+                .filter(|&(j, n)| !new_used[j] && matches(o, n))
+                .min_by_key(|&(_, n)| distance(o, n));
+            if let Some((j, n)) = best {
+                old_used[i] = true;
+                new_used[j] = true;
+                out.persisting.push((o.clone(), n.clone()));
+            }
+        }
+    }
+    out.removed = old.iter().enumerate().filter(|&(i, _)| !old_used[i]).map(|(_, o)| o.clone()).collect();
+    out.added = new.iter().enumerate().filter(|&(j, _)| !new_used[j]).map(|(_, n)| n.clone()).collect();
+    out
+}
+
+/// Each distinct use-after-free, by source binary, source, sink binary and sink, with its
+/// signature
+pub fn collect(holmes: &mut Engine) -> Result<Vec<(Vec<Value>, Signature)>> {
+    let to_u64 = |v: &Value| v.get().downcast_ref::<BitVector>().unwrap().to_u64().unwrap_or(0);
+    let as_str = |v: &Value| v.get().downcast_ref::<String>().unwrap().clone();
+    let as_id = |v: &Value| *v.get().downcast_ref::<u64>().unwrap();
+    let symbols = symbols::load(holmes)?;
+    let mut files = HashMap::new();
+    for row in query!(holmes, binary(id, path, [_], [_]))? {
+        let path = as_str(&row[1]);
+        let file = Path::new(&path).file_name().map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+        files.insert(as_str(&row[0]), file);
+    }
+    let mut disasm = HashMap::new();
+    for row in query!(holmes, lift(name, addr, [_], [_], disasm))? {
+        let text = row[2].get().downcast_ref::<String>().cloned().unwrap_or_default();
+        disasm.insert((as_str(&row[0]), to_u64(&row[1])), text);
+    }
+    let mut stacks = HashMap::new();
+    for row in query!(holmes, stack(id, prev, bin, addr))? {
+        stacks.insert(as_id(&row[0]), (as_id(&row[1]), as_str(&row[2]), to_u64(&row[3])));
+    }
+    // Functions each block is in, and the functions each function calls
+    let mut funcs: HashMap<(String, u64), Vec<u64>> = HashMap::new();
+    for row in query!(holmes, func(bin, entry, addr))? {
+        funcs.entry((as_str(&row[0]), to_u64(&row[2]))).or_insert_with(Vec::new).push(to_u64(&row[1]));
+    }
+    let mut callees: HashMap<(String, u64), Vec<(String, u64)>> = HashMap::new();
+    for row in query!(holmes, call_site(src_bin, src_addr, dst_bin, dst_addr))? {
+        for &caller in funcs.get(&(as_str(&row[0]), to_u64(&row[1]))).map_or(&[][..], |f| &f[..]) {
+            callees.entry((as_str(&row[0]), caller)).or_insert_with(Vec::new).push((as_str(&row[2]), to_u64(&row[3])));
+        }
+    }
+    // Functions the trace of each allocation reaches in the allocating frame or one it returned
+    // to, with the fewest steps it took to get there
+    let mut outward: HashMap<(String, u64, u64), HashMap<(String, u64), u64>> = HashMap::new();
+    for row in query!(holmes, path_alias_trace(name, src, sa, stack, cur_name, cur, [_], [_], steps))? {
+        if as_id(&row[3]) != 0 {
+            continue;
+        }
+        let cur_name = as_str(&row[4]);
+        let steps = as_id(&row[6]);
+        let reached = outward.entry((as_str(&row[0]), to_u64(&row[1]), as_id(&row[2]))).or_insert_with(HashMap::new);
+        for &entry in funcs.get(&(cur_name.clone(), to_u64(&row[5]))).map_or(&[][..], |f| &f[..]) {
+            let least = reached.entry((cur_name.clone(), entry)).or_insert(steps);
+            if steps < *least {
+                *least = steps;
+            }
+        }
+    }
+    let place = |bin: &str, addr: u64| {
+        let (func, offset) = match symbols.get(bin).and_then(|syms| symbols::locate(syms, addr)) {
+            Some((name, offset)) => (name.to_string(), offset),
+            None => (String::new(), addr),
+        };
+        let text = disasm.get(&(bin.to_string(), addr)).cloned().unwrap_or_default();
+        Place {
+            func: func,
+            offset: offset,
+            mnemonics: mnemonics(&text),
+        }
+    };
+    let no_funcs = Vec::new();
+    let mut seen = HashSet::new();
+    let mut sigs = Vec::new();
+    for row in query!(holmes, use_after_free(name, src, sa, sink_name, sink, [_], stack))? {
+        let (name, src, sink_name, sink) = (as_str(&row[0]), to_u64(&row[1]), as_str(&row[3]), to_u64(&row[4]));
+        // Each frame holds the return address of its call, innermost first
+        let mut frames = Vec::new();
+        let mut cur = as_id(&row[5]);
+        while let Some(&(prev, ref bin, ret)) = stacks.get(&cur) {
+            if cur == 0 || frames.len() > stacks.len() {
+                break;
+            }
+            frames.push((bin.clone(), ret));
+            cur = prev;
+        }
+        frames.reverse();
+        let chain = frames.iter().map(|&(ref bin, ret)| place(bin, ret).func).collect();
+        // The frame both sides share is the one the outermost open call was made from, or the
+        // use's own. From there, walk down calls to functions the allocation's trace reached
+        // sooner, until the allocating function.
+        let mut alloc_chain = Vec::new();
+        if let Some(reached) = outward.get(&(name.clone(), src, as_id(&row[2]))) {
+            let (top_bin, top_addr) = frames.first().cloned().unwrap_or((sink_name.clone(), sink));
+            let allocating = funcs.get(&(name.clone(), src)).unwrap_or(&no_funcs);
+            let shared = funcs.get(&(top_bin.clone(), top_addr))
+                .unwrap_or(&no_funcs)
+                .iter()
+                .map(|&entry| (top_bin.clone(), entry))
+                .filter(|func| reached.contains_key(func))
+                .min_by_key(|func| reached[func]);
+            let mut walk = Vec::new();
+            let mut cur = shared;
+            while let Some(func) = cur.take() {
+                if func.0 == name && allocating.contains(&func.1) {
+                    alloc_chain = walk.iter().map(|&(ref bin, entry)| place(bin, entry).func).collect();
+                    break;
+                }
+                if walk.len() > reached.len() {
+                    break;
+                }
+                cur = callees.get(&func)
+                    .unwrap_or(&Vec::new())
+                    .iter()
+                    .filter(|callee| reached.get(*callee).map_or(false, |&steps| steps < reached[&func]))
+                    .min_by_key(|callee| reached[*callee])
+                    .cloned();
+                walk.push(func);
+            }
+        }
+        let sig = Signature {
+            checker: "uaf".to_string(),
+            binary: files.get(&name).cloned().unwrap_or_default(),
+            alloc: place(&name, src),
+            use_: place(&sink_name, sink),
+            alloc_chain: alloc_chain,
+            chain: chain,
+        };
+        if seen.insert((name, src, sink_name, sink)) {
+            sigs.push((vec![row[0].clone(), row[1].clone(), row[3].clone(), row[4].clone()], sig));
+        }
+    }
+    Ok(sigs)
+}
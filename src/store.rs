@@ -0,0 +1,32 @@
+use rustc_serialize::json::{self, Json, Decoder};
+use rustc_serialize::{Decodable, Encodable};
+use std::fs::File;
+use std::io::Read;
+
+// JSON encoding shared by the fact types stored as jsonb (sema, var, chop, cond) and by the files
+// tiamat reads and writes itself.
+
+pub fn encode<T: Encodable>(val: &T) -> Json {
+    let buf = json::encode(val).unwrap();
+    Json::from_str(&buf).unwrap()
+}
+
+pub fn decode<T: Decodable>(raw: Json) -> Option<T> {
+    let mut decoder = Decoder::new(raw);
+    T::decode(&mut decoder).ok()
+}
+
+pub fn to_text<T: Encodable>(val: &T) -> String {
+    json::encode(val).unwrap()
+}
+
+pub fn from_text<T: Decodable>(text: &str) -> Option<T> {
+    json::decode(text).ok()
+}
+
+/// The contents of a file named on the command line, panicking with its path if it can't be read
+pub fn read_text(path: &str) -> String {
+    let mut text = String::new();
+    File::open(path).and_then(|mut fd| fd.read_to_string(&mut text)).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    text
+}
@@ -0,0 +1,69 @@
+use rustc_serialize::json;
+use signature::Signature;
+
+// Findings reviewed and accepted, so that scans stop reporting them. A suppression file is a JSON
+// list of entries such as
+//
+//   [{"fingerprint": "3f09c2a1d4b87e65", "justification": "freed pointer only compared"},
+//    {"alloc_func": "g_hash_table_*", "use_func": "*_foreach", "justification": "..."}]
+//
+// An entry matches a finding when every key it gives does: fingerprint exactly, as tiamat diff
+// prints it, and alloc_func and use_func as patterns where * stands for any run of characters.
+
+#[derive(Debug, Clone, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct Suppression {
+    pub fingerprint: Option<String>,
+    pub alloc_func: Option<String>,
+    pub use_func: Option<String>,
+    pub justification: String,
+}
+
+pub fn parse(path: &str, text: &str) -> Result<Vec<Suppression>, String> {
+    let rules: Vec<Suppression> = json::decode(text).map_err(|e| format!("{}: {}", path, e))?;
+    for (idx, rule) in rules.iter().enumerate() {
+        if rule.fingerprint.is_none() && rule.alloc_func.is_none() && rule.use_func.is_none() {
+            return Err(format!("{}: entry {} would suppress everything", path, idx));
+        }
+        if rule.justification.trim().is_empty() {
+            return Err(format!("{}: entry {} has no justification", path, idx));
+        }
+    }
+    Ok(rules)
+}
+
+/// Whether name matches pattern, where * matches any run of characters
+pub fn glob(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap();
+    if !name.starts_with(first) {
+        return false;
+    }
+    let mut rest = &name[first.len()..];
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        // No * at all
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle.iter() {
+                match rest.find(part) {
+                    Some(at) => rest = &rest[at + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(last)
+        }
+    }
+}
+
+impl Suppression {
+    pub fn matches(&self, sig: &Signature) -> bool {
+        self.fingerprint.as_ref().map_or(true, |fp| *fp == sig.fingerprint()) &&
+            self.alloc_func.as_ref().map_or(true, |pat| glob(pat, &sig.alloc.func)) &&
+            self.use_func.as_ref().map_or(true, |pat| glob(pat, &sig.use_.func))
+    }
+}
+
+/// The first entry suppressing a finding
+pub fn find<'a>(rules: &'a [Suppression], sig: &Signature) -> Option<&'a Suppression> {
+    rules.iter().find(|rule| rule.matches(sig))
+}
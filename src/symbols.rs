@@ -0,0 +1,27 @@
+use holmes::simple::*;
+use holmes::pg::dyn::Value;
+use bap::high::bitvector::BitVector;
+use num::ToPrimitive;
+use std::collections::HashMap;
+
+// Function symbols of the loaded binaries, as (name, start, end) with end inclusive, the way the
+// entry facts give them. Anything that reports addresses as function plus offset looks them up
+// here.
+
+/// Symbols of every loaded binary, by binary id
+pub fn load(holmes: &mut Engine) -> Result<HashMap<String, Vec<(String, u64, u64)>>> {
+    let to_u64 = |v: &Value| v.get().downcast_ref::<BitVector>().unwrap().to_u64().unwrap_or(0);
+    let as_str = |v: &Value| v.get().downcast_ref::<String>().unwrap().clone();
+    let mut symbols: HashMap<String, Vec<(String, u64, u64)>> = HashMap::new();
+    for row in query!(holmes, entry(id, name, start, end))? {
+        symbols.entry(as_str(&row[0])).or_insert_with(Vec::new).push((as_str(&row[1]), to_u64(&row[2]), to_u64(&row[3])));
+    }
+    Ok(symbols)
+}
+
+/// Which of symbols holds addr, and how far into it addr is
+pub fn locate(symbols: &[(String, u64, u64)], addr: u64) -> Option<(&str, u64)> {
+    symbols.iter()
+        .find(|&&(_, start, end)| start <= addr && addr <= end)
+        .map(|&(ref name, start, _)| (name.as_str(), addr - start))
+}
@@ -0,0 +1,633 @@
+use bap::basic::{Cast, UnOp};
+use bap::high::bil::{Statement, Expression, Variable, Type, BinOp};
+use bap::high::bitvector::BitVector;
+use num::{BigUint, Num};
+use std::collections::HashMap;
+use std::env;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use arith;
+use sema::Sema;
+use var::HVar;
+
+// Symbolic execution of lifted blocks, written out as an SMT-LIB2 script. Every intermediate value
+// gets its own define-fun so the script stays linear in the length of the path, and memory is a
+// single byte array threaded through stores.
+
+/// A bit-vector term of the script
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sym {
+    pub term: String,
+    pub width: usize,
+}
+
+fn lit(bv: &BitVector) -> Sym {
+    let width = arith::width(bv);
+    Sym {
+        term: format!("(_ bv{} {})", bv.unum(), width),
+        width: width,
+    }
+}
+
+fn bit(term: String) -> Sym {
+    Sym {
+        term: format!("(ite {} #b1 #b0)", term),
+        width: 1,
+    }
+}
+
+// term resized to width, zero-extending or truncating
+fn fit(s: &Sym, width: usize) -> String {
+    if s.width == width {
+        s.term.clone()
+    } else if s.width < width {
+        format!("((_ zero_extend {}) {})", width - s.width, s.term)
+    } else {
+        format!("((_ extract {} 0) {})", width - 1, s.term)
+    }
+}
+
+fn binop(op: BinOp, l: &Sym, r: &Sym) -> Sym {
+    let r = fit(r, l.width);
+    let bv = |f: &str| {
+        Sym {
+            term: format!("({} {} {})", f, l.term, r),
+            width: l.width,
+        }
+    };
+    match op {
+        BinOp::Add => bv("bvadd"),
+        BinOp::Sub => bv("bvsub"),
+        BinOp::Mul => bv("bvmul"),
+        BinOp::Div => bv("bvudiv"),
+        BinOp::SDiv => bv("bvsdiv"),
+        BinOp::Mod => bv("bvurem"),
+        BinOp::SMod => bv("bvsrem"),
+        BinOp::LShift => bv("bvshl"),
+        BinOp::RShift => bv("bvlshr"),
+        BinOp::ARShift => bv("bvashr"),
+        BinOp::And => bv("bvand"),
+        BinOp::Or => bv("bvor"),
+        BinOp::Xor => bv("bvxor"),
+        BinOp::Eq => bit(format!("(= {} {})", l.term, r)),
+        BinOp::Neq => bit(format!("(not (= {} {}))", l.term, r)),
+        BinOp::Lt => bit(format!("(bvult {} {})", l.term, r)),
+        BinOp::Le => bit(format!("(bvule {} {})", l.term, r)),
+        BinOp::SLt => bit(format!("(bvslt {} {})", l.term, r)),
+        BinOp::SLe => bit(format!("(bvsle {} {})", l.term, r)),
+    }
+}
+
+/// Where control goes when a block (or one path through it) ends
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exit {
+    Fall,
+    To(BitVector),
+    Computed,
+}
+
+/// Registers, memory and path condition so far, along with the script defining them.
+#[derive(Debug, Clone)]
+pub struct State {
+    regs: HashMap<String, Sym>,
+    mem: String,
+    script: Vec<String>,
+    // Registers read before anything on the path wrote them, so the model can report them
+    inputs: Vec<(String, Sym)>,
+    fresh: usize,
+}
+
+// System V caller-saved registers, which a call we step over may leave anything in
+const CLOBBERED: &'static [&'static str] = &["RAX", "RCX", "RDX", "RSI", "RDI", "R8", "R9", "R10", "R11"];
+
+impl State {
+    pub fn new() -> Self {
+        State {
+            regs: HashMap::new(),
+            mem: "|mem|".to_string(),
+            script: vec!["(declare-const |mem| (Array (_ BitVec 64) (_ BitVec 8)))".to_string()],
+            inputs: Vec::new(),
+            fresh: 0,
+        }
+    }
+    pub fn script(&self) -> &[String] {
+        &self.script
+    }
+    pub fn inputs(&self) -> &[(String, Sym)] {
+        &self.inputs
+    }
+    fn define(&mut self, s: Sym) -> Sym {
+        self.fresh += 1;
+        let name = format!("|t{}|", self.fresh);
+        self.script.push(format!("(define-fun {} () (_ BitVec {}) {})", name, s.width, s.term));
+        Sym {
+            term: name,
+            width: s.width,
+        }
+    }
+    /// A new unconstrained value
+    pub fn fresh(&mut self, hint: &str, width: usize) -> Sym {
+        self.fresh += 1;
+        let name = format!("|{}@{}|", hint, self.fresh);
+        self.script.push(format!("(declare-const {} (_ BitVec {}))", name, width));
+        Sym {
+            term: name,
+            width: width,
+        }
+    }
+    pub fn reg(&mut self, name: &str, width: usize) -> Sym {
+        if let Some(s) = self.regs.get(name) {
+            return s.clone();
+        }
+        let s = self.fresh(name, width);
+        self.inputs.push((name.to_string(), s.clone()));
+        self.regs.insert(name.to_string(), s.clone());
+        s
+    }
+    pub fn set_reg(&mut self, name: &str, s: Sym) {
+        self.regs.insert(name.to_string(), s);
+    }
+    /// Restricts the state to paths where bit is set
+    pub fn assume(&mut self, bit: &Sym) {
+        self.script.push(format!("(assert (= {} #b1))", bit.term));
+    }
+    pub fn assert(&mut self, prop: String) {
+        self.script.push(format!("(assert {})", prop));
+    }
+    pub fn load(&mut self, addr: &Sym, bytes: usize) -> Sym {
+        let addr = fit(addr, 64);
+        // Little-endian, so the last byte is the most significant
+        let mut term = String::new();
+        for k in (0..bytes).rev() {
+            let byte = format!("(select {} (bvadd {} (_ bv{} 64)))", self.mem, addr, k);
+            term = if k + 1 == bytes { byte } else { format!("(concat {} {})", term, byte) };
+        }
+        self.define(Sym {
+            term: term,
+            width: bytes * 8,
+        })
+    }
+    pub fn store(&mut self, addr: &Sym, val: &Sym) {
+        let addr = fit(addr, 64);
+        let mut term = self.mem.clone();
+        for k in 0..(val.width + 7) / 8 {
+            let hi = ::std::cmp::min(k * 8 + 7, val.width - 1);
+            term = format!("(store {} (bvadd {} (_ bv{} 64)) ((_ zero_extend {}) ((_ extract {} {}) {})))",
+                           term, addr, k, 7 - (hi - k * 8), hi, k * 8, val.term);
+        }
+        self.fresh += 1;
+        let name = format!("|m{}|", self.fresh);
+        self.script.push(format!("(define-fun {} () (Array (_ BitVec 64) (_ BitVec 8)) {})", name, term));
+        self.mem = name;
+    }
+    /// Models returning from a call we don't follow: the return address is popped and the
+    /// caller-saved registers and flags hold anything.
+    pub fn ret_from_call(&mut self) {
+        let rsp = self.reg("RSP", 64);
+        let rsp = binop(BinOp::Add, &rsp, &Sym { term: "(_ bv8 64)".to_string(), width: 64 });
+        let rsp = self.define(rsp);
+        self.set_reg("RSP", rsp);
+        for name in CLOBBERED.iter() {
+            let s = self.fresh(name, 64);
+            self.set_reg(name, s);
+        }
+        let flags: Vec<String> = self.regs.iter().filter(|&(_, s)| s.width == 1).map(|(n, _)| n.clone()).collect();
+        for name in flags {
+            let s = self.fresh(&name, 1);
+            self.set_reg(&name, s);
+        }
+    }
+    pub fn eval(&mut self, e: &Expression) -> Result<Sym, String> {
+        let s = match *e {
+            Expression::Var(ref v) => {
+                return match v.type_ {
+                    Type::Immediate(width) => Ok(self.reg(&v.name, width as usize)),
+                    _ => Err(format!("memory-valued {}", v.name)),
+                }
+            }
+            Expression::Const(ref bv) => return Ok(lit(bv)),
+            Expression::Load { ref index, size, .. } => {
+                let addr = self.eval(index)?;
+                return Ok(self.load(&addr, size as usize / 8));
+            }
+            Expression::Store { .. } => return Err("nested store".to_string()),
+            Expression::BinOp { op, ref lhs, ref rhs } => {
+                let l = self.eval(lhs)?;
+                let r = self.eval(rhs)?;
+                binop(op, &l, &r)
+            }
+            Expression::UnOp { op, ref arg } => {
+                let a = self.eval(arg)?;
+                let f = match op {
+                    UnOp::Neg => "bvneg",
+                    UnOp::Not => "bvnot",
+                };
+                Sym {
+                    term: format!("({} {})", f, a.term),
+                    width: a.width,
+                }
+            }
+            Expression::Cast { kind, width, ref arg } => {
+                let a = self.eval(arg)?;
+                let width = width as usize;
+                let term = match kind {
+                    Cast::Low => fit(&a, width),
+                    Cast::High if width <= a.width => format!("((_ extract {} {}) {})", a.width - 1, a.width - width, a.term),
+                    Cast::Unsigned | Cast::High => fit(&a, width),
+                    Cast::Signed if width > a.width => format!("((_ sign_extend {}) {})", width - a.width, a.term),
+                    Cast::Signed => fit(&a, width),
+                };
+                Sym {
+                    term: term,
+                    width: width,
+                }
+            }
+            Expression::Let { ref bind, ref value, ref body } => {
+                let v = self.eval(value)?;
+                let old = self.regs.insert(bind.name.clone(), v);
+                let res = self.eval(body);
+                match old {
+                    Some(old) => self.regs.insert(bind.name.clone(), old),
+                    None => self.regs.remove(&bind.name),
+                };
+                return res;
+            }
+            Expression::IfThenElse { ref cond, ref true_expr, ref false_expr } => {
+                let c = self.eval(cond)?;
+                let t = self.eval(true_expr)?;
+                let f = self.eval(false_expr)?;
+                Sym {
+                    term: format!("(ite (= {} #b1) {} {})", c.term, t.term, fit(&f, t.width)),
+                    width: t.width,
+                }
+            }
+            Expression::Extract { low_bit, high_bit, ref arg } => {
+                let a = self.eval(arg)?;
+                Sym {
+                    term: format!("((_ extract {} {}) {})", high_bit, low_bit, a.term),
+                    width: high_bit as usize + 1 - low_bit as usize,
+                }
+            }
+            Expression::Concat { ref low, ref high } => {
+                let h = self.eval(high)?;
+                let l = self.eval(low)?;
+                Sym {
+                    term: format!("(concat {} {})", h.term, l.term),
+                    width: h.width + l.width,
+                }
+            }
+            _ => return Err(format!("unsupported expression {:?}", e)),
+        };
+        Ok(self.define(s))
+    }
+    fn step(
+        mut self,
+        stmt: &Statement,
+        live: &mut Vec<State>,
+        done: &mut Vec<(State, Exit)>,
+    ) -> Result<(), String> {
+        match *stmt {
+            Statement::Move { ref lhs, rhs: Expression::Store { ref index, ref value, .. } } if is_mem(lhs) => {
+                let addr = self.eval(index)?;
+                let val = self.eval(value)?;
+                self.store(&addr, &val);
+                live.push(self);
+            }
+            Statement::Move { ref lhs, ref rhs } if !is_mem(lhs) => {
+                let v = self.eval(rhs)?;
+                self.set_reg(&lhs.name, v);
+                live.push(self);
+            }
+            Statement::Jump(Expression::Const(ref tgt)) => done.push((self, Exit::To(tgt.clone()))),
+            Statement::Jump(ref tgt) => {
+                self.eval(tgt)?;
+                done.push((self, Exit::Computed))
+            }
+            Statement::IfThenElse { ref cond, ref then_clause, ref else_clause } => {
+                let c = self.eval(cond)?;
+                for &(clause, taken) in [(then_clause, "#b1"), (else_clause, "#b0")].iter() {
+                    let mut arm = self.clone();
+                    arm.assert(format!("(= {} {})", c.term, taken));
+                    for (st, exit) in arm.exec(clause)? {
+                        match exit {
+                            Exit::Fall => live.push(st),
+                            exit => done.push((st, exit)),
+                        }
+                    }
+                }
+            }
+            _ => return Err(format!("unsupported statement {:?}", stmt)),
+        }
+        Ok(())
+    }
+    /// Runs stmts, splitting at each branch. Gives each resulting state with where it goes next.
+    pub fn exec(self, stmts: &[Statement]) -> Result<Vec<(State, Exit)>, String> {
+        let mut live = vec![self];
+        let mut done = Vec::new();
+        for stmt in stmts.iter() {
+            let mut next = Vec::new();
+            for st in live {
+                st.step(stmt, &mut next, &mut done)?;
+            }
+            live = next;
+        }
+        done.extend(live.into_iter().map(|st| (st, Exit::Fall)));
+        Ok(done)
+    }
+}
+
+fn is_mem(v: &Variable) -> bool {
+    match v.type_ {
+        Type::Memory { .. } => true,
+        _ => false,
+    }
+}
+
+/// An SMT-LIB2 solver run as a separate process, `z3 -in` unless TIAMAT_SOLVER says otherwise.
+pub struct Solver {
+    cmd: String,
+    args: Vec<String>,
+}
+
+impl Solver {
+    pub fn new(cmd: &str) -> Self {
+        let mut words = cmd.split_whitespace().map(|w| w.to_string());
+        Solver {
+            cmd: words.next().unwrap_or_default(),
+            args: words.collect(),
+        }
+    }
+    pub fn from_env() -> Self {
+        Solver::new(&env::var("TIAMAT_SOLVER").unwrap_or("z3 -in -T:10".to_string()))
+    }
+    /// Whether the solver can be run at all
+    pub fn available(&self) -> bool {
+        self.check(&State::new()).is_ok()
+    }
+    /// Some model of the inputs if the state is reachable, None if it isn't, and an error when
+    /// the solver can't tell or can't be run.
+    pub fn check(&self, st: &State) -> Result<Option<Vec<(String, BitVector)>>, String> {
+        let mut query = st.script.join("\n");
+        query.push_str("\n(check-sat)\n");
+        if !st.inputs.is_empty() {
+            let names: Vec<&str> = st.inputs.iter().map(|&(_, ref s)| s.term.as_str()).collect();
+            query.push_str(&format!("(get-value ({}))\n", names.join(" ")));
+        }
+        let mut child = Command::new(&self.cmd)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("couldn't run {}: {}", self.cmd, e))?;
+        child.stdin.take().unwrap().write_all(query.as_bytes()).map_err(|e| e.to_string())?;
+        let mut out = String::new();
+        child.stdout.take().unwrap().read_to_string(&mut out).map_err(|e| e.to_string())?;
+        let _ = child.wait();
+        let mut lines = out.lines();
+        match lines.next().map(|l| l.trim()) {
+            Some("sat") => (),
+            Some("unsat") => return Ok(None),
+            Some(other) => return Err(format!("solver said {}", other)),
+            None => return Err("no answer from solver".to_string()),
+        }
+        let rest: String = lines.collect::<Vec<_>>().join(" ");
+        let mut model = Vec::new();
+        for &(ref name, ref s) in st.inputs.iter() {
+            if let Some(v) = model_value(&rest, &s.term, s.width) {
+                model.push((name.clone(), v));
+            }
+        }
+        Ok(Some(model))
+    }
+}
+
+// Value of term in a get-value response, written as #x.., #b.. or (_ bvN w)
+fn model_value(out: &str, term: &str, width: usize) -> Option<BitVector> {
+    let after = match out.find(term) {
+        Some(at) => out[at + term.len()..].trim_left(),
+        None => return None,
+    };
+    let (digits, radix) = if after.starts_with("#x") {
+        (&after[2..], 16)
+    } else if after.starts_with("#b") {
+        (&after[2..], 2)
+    } else if after.starts_with("(_ bv") {
+        (&after[5..], 10)
+    } else {
+        return None;
+    };
+    let end = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+    BigUint::from_str_radix(&digits[..end], radix).ok().map(|n| BitVector::new_unsigned(n, width))
+}
+
+/// A lifted block, as in the `lift` predicate
+pub struct Block {
+    pub sema: Sema,
+    pub fall: BitVector,
+    pub is_call: bool,
+    pub is_ret: bool,
+}
+
+/// The blocks of one binary, plus the calls that free something and which arguments they free,
+/// whether by calling free itself or as call_frees says
+#[derive(Default)]
+pub struct Program {
+    pub blocks: HashMap<BitVector, Block>,
+    pub frees: HashMap<BitVector, Vec<HVar>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// With the registers on entry to the allocation site that lead to the use
+    Feasible(Vec<(String, BitVector)>),
+    Infeasible,
+    /// With why it couldn't be decided
+    Unknown(String),
+}
+
+impl Verdict {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Verdict::Feasible(_) => "feasible",
+            Verdict::Infeasible => "infeasible",
+            Verdict::Unknown(_) => "unknown",
+        }
+    }
+    pub fn detail(&self) -> String {
+        match *self {
+            Verdict::Feasible(ref regs) => {
+                regs.iter().map(|&(ref name, ref v)| format!("{}={}", name, v)).collect::<Vec<_>>().join(" ")
+            }
+            Verdict::Infeasible => String::new(),
+            Verdict::Unknown(ref why) => why.clone(),
+        }
+    }
+}
+
+// Most solver queries spent on one finding
+const MAX_QUERIES: usize = 32;
+// Most blocks executed for one finding, across all paths
+const MAX_BLOCKS: usize = 4096;
+
+struct Path {
+    state: State,
+    addr: BitVector,
+    stack: Vec<BitVector>,
+    freed: bool,
+    steps: usize,
+}
+
+// The value held in loc, if it is somewhere the state can name
+fn loc_value(st: &mut State, loc: &HVar) -> Option<Sym> {
+    if loc.inner.name.contains(':') {
+        // Heap cells aren't anywhere we can name without knowing which object they are in
+        return None;
+    }
+    let base = if loc.inner == ::var::globals() {
+        Sym { term: "(_ bv0 64)".to_string(), width: 64 }
+    } else {
+        st.reg(&loc.inner.name, 64)
+    };
+    match (&loc.offset, &loc.array) {
+        (&None, &None) => Some(base),
+        (&Some(ref off), _) => {
+            let addr = binop(BinOp::Add, &base, &lit(off));
+            let addr = st.define(addr);
+            Some(st.load(&addr, 8))
+        }
+        _ => None,
+    }
+}
+
+/// Looks for a concrete path from the allocation at source, through a free of what it returned,
+/// to sink with loc pointing into the freed object. Calls other than free are followed up to
+/// depth calls deep and stepped over past that, and paths are cut at max_steps blocks.
+pub fn confirm(
+    prog: &Program,
+    source: &BitVector,
+    sink: &BitVector,
+    loc: &HVar,
+    solver: &Solver,
+    max_steps: usize,
+    depth: usize,
+) -> Verdict {
+    let block = match prog.blocks.get(source) {
+        Some(block) => block,
+        None => return Verdict::Unknown("allocation site not lifted".to_string()),
+    };
+    let mut start = State::new();
+    let size = start.reg("RDI", 64);
+    let exits = match start.exec(&block.sema.stmts) {
+        Ok(exits) => exits,
+        Err(why) => return Verdict::Unknown(why),
+    };
+    let mut todo = Vec::new();
+    for (mut st, _) in exits {
+        st.ret_from_call();
+        let alloc = st.reg("RAX", 64);
+        st.set_reg("alloc", alloc.clone());
+        st.set_reg("size", size.clone());
+        // Only a successful allocation can be used after free
+        st.assert(format!("(not (= {} (_ bv0 64)))", alloc.term));
+        todo.push(Path { state: st, addr: block.fall.clone(), stack: Vec::new(), freed: false, steps: 0 });
+    }
+    let mut incomplete: Option<String> = None;
+    let mut queries = 0;
+    let mut blocks = 0;
+    while let Some(mut path) = todo.pop() {
+        if path.freed && path.addr == *sink {
+            if queries == MAX_QUERIES {
+                incomplete = Some("too many candidate paths".to_string());
+                break;
+            }
+            queries += 1;
+            let alloc = path.state.regs["alloc"].clone();
+            let size = path.state.regs["size"].clone();
+            if let Some(ptr) = loc_value(&mut path.state, loc) {
+                path.state.assert(format!("(bvult (bvsub {} {}) {})", ptr.term, alloc.term, size.term));
+            }
+            match solver.check(&path.state) {
+                Ok(Some(model)) => return Verdict::Feasible(model),
+                Ok(None) => (),
+                Err(why) => incomplete = Some(why),
+            }
+            continue;
+        }
+        if path.steps == max_steps || blocks == MAX_BLOCKS {
+            incomplete = Some("path bound reached".to_string());
+            continue;
+        }
+        let block = match prog.blocks.get(&path.addr) {
+            Some(block) => block,
+            None => {
+                incomplete = Some(format!("{} not lifted", path.addr));
+                continue;
+            }
+        };
+        blocks += 1;
+        let exits = match path.state.clone().exec(&block.sema.stmts) {
+            Ok(exits) => exits,
+            Err(why) => {
+                incomplete = Some(why);
+                continue;
+            }
+        };
+        for (mut st, exit) in exits {
+            let mut stack = path.stack.clone();
+            let next = if block.is_call {
+                match exit {
+                    Exit::To(ref tgt) if prog.blocks.contains_key(tgt) && stack.len() < depth &&
+                                         !prog.frees.contains_key(&path.addr) => {
+                        stack.push(block.fall.clone());
+                        tgt.clone()
+                    }
+                    _ => {
+                        match prog.frees.get(&path.addr) {
+                            Some(args) if !path.freed => {
+                                // Either this frees our object, or something else
+                                let alloc = st.regs["alloc"].clone();
+                                for arg in args.iter() {
+                                    if arg.offset.is_some() || arg.array.is_some() {
+                                        incomplete = Some(format!("{} frees what {} points to", path.addr, arg));
+                                        continue;
+                                    }
+                                    let arg = st.reg(&arg.inner.name, 64);
+                                    let mut ours = st.clone();
+                                    ours.assert(format!("(= {} {})", arg.term, alloc.term));
+                                    ours.ret_from_call();
+                                    todo.push(Path { state: ours, addr: block.fall.clone(), stack: stack.clone(), freed: true, steps: path.steps + 1 });
+                                    st.assert(format!("(not (= {} {}))", arg.term, alloc.term));
+                                }
+                            }
+                            _ => (),
+                        }
+                        st.ret_from_call();
+                        block.fall.clone()
+                    }
+                }
+            } else if block.is_ret {
+                match stack.pop() {
+                    Some(ret) => ret,
+                    None => {
+                        incomplete = Some("returned past the allocating function".to_string());
+                        continue;
+                    }
+                }
+            } else {
+                match exit {
+                    Exit::Fall => block.fall.clone(),
+                    Exit::To(tgt) => tgt,
+                    Exit::Computed => {
+                        incomplete = Some(format!("computed jump at {}", path.addr));
+                        continue;
+                    }
+                }
+            };
+            todo.push(Path { state: st, addr: next, stack: stack, freed: path.freed, steps: path.steps + 1 });
+        }
+    }
+    match incomplete {
+        Some(why) => Verdict::Unknown(why),
+        None => Verdict::Infeasible,
+    }
+}
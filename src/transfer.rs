@@ -0,0 +1,3 @@
+// The block-level abstract transfer functions, exposed so they can be checked against the
+// concrete interpreter in interp, and the address abstraction they share.
+pub use analyses::{const_block, deref_var, heap_init, promote_array, xfer_taint};
@@ -0,0 +1,137 @@
+use std::path::Path;
+use std::process::Command;
+
+// Ground truth for corpora other than Juliet, where function names say nothing about where the
+// flaws are. A truth file has one expected finding per line:
+//
+//   # binary  checker  kind  sites
+//   simple    uaf      bad   alloc main+0x8  free main+0x1c  use main
+//   safe      uaf      good  alloc main  use main
+//
+// binary is the file name of the analysed binary. kind is bad for a flaw the checker should
+// report, good for one it should not. Each site is a symbol (anywhere in that function), a
+// symbol+0xoffset, or a file.c:line from the binary's DWARF line table. A finding matches when
+// its allocation and use blocks overlap the alloc and use sites, whichever are given. Findings
+// don't record which call frees, so free sites are kept for readers but not matched.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Site {
+    Symbol(String),
+    Offset(String, u64),
+    Line(String, u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// file:line of the annotation, which findings matching it are graded under
+    pub label: String,
+    pub binary: String,
+    pub checker: String,
+    pub bad: bool,
+    pub alloc: Option<Site>,
+    pub free: Option<Site>,
+    pub use_: Option<Site>,
+}
+
+fn parse_site(word: &str) -> Option<Site> {
+    if let Some(colon) = word.rfind(':') {
+        if let Ok(line) = word[colon + 1..].parse::<u32>() {
+            return Some(Site::Line(word[..colon].to_string(), line));
+        }
+    }
+    match word.find("+0x") {
+        Some(plus) => {
+            u64::from_str_radix(&word[plus + 3..], 16).ok().map(|off| Site::Offset(word[..plus].to_string(), off))
+        }
+        None => Some(Site::Symbol(word.to_string())),
+    }
+}
+
+pub fn parse(path: &str, text: &str) -> Result<Vec<Annotation>, String> {
+    let mut out = Vec::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let at = format!("{}:{}", path, lineno + 1);
+        let words: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        if words.len() < 5 || words.len() % 2 == 0 {
+            return Err(format!("{}: expected binary, checker, kind, then site pairs", at));
+        }
+        let bad = match words[2] {
+            "bad" => true,
+            "good" => false,
+            kind => return Err(format!("{}: kind must be bad or good, got {}", at, kind)),
+        };
+        let mut ann = Annotation {
+            label: at.clone(),
+            binary: words[0].to_string(),
+            checker: words[1].to_string(),
+            bad: bad,
+            alloc: None,
+            free: None,
+            use_: None,
+        };
+        for pair in words[3..].chunks(2) {
+            let site = match parse_site(pair[1]) {
+                Some(site) => site,
+                None => return Err(format!("{}: can't read site {}", at, pair[1])),
+            };
+            match pair[0] {
+                "alloc" => ann.alloc = Some(site),
+                "free" => ann.free = Some(site),
+                "use" => ann.use_ = Some(site),
+                which => return Err(format!("{}: expected alloc, free or use, got {}", at, which)),
+            }
+        }
+        if ann.alloc.is_none() && ann.use_.is_none() {
+            return Err(format!("{}: needs an alloc or a use site", at));
+        }
+        out.push(ann);
+    }
+    Ok(out)
+}
+
+/// (file, line, address) rows of objdump --dwarf=decodedline output
+pub fn line_table(dump: &str) -> Vec<(String, u32, u64)> {
+    let mut rows = Vec::new();
+    for line in dump.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.len() < 3 || !words[2].starts_with("0x") {
+            continue;
+        }
+        if let (Ok(lineno), Ok(addr)) = (words[1].parse::<u32>(), u64::from_str_radix(&words[2][2..], 16)) {
+            rows.push((words[0].to_string(), lineno, addr));
+        }
+    }
+    rows
+}
+
+/// The line table of a binary, empty if it has none or objdump isn't around
+pub fn lines(binary: &Path) -> Vec<(String, u32, u64)> {
+    match Command::new("objdump").arg("--dwarf=decodedline").arg(binary).output() {
+        Ok(out) => line_table(&String::from_utf8_lossy(&out.stdout)),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Addresses a site covers, inclusive, given the binary's symbols (name, start, end) and line
+/// table. A line spans from its first instruction to the start of its last.
+pub fn resolve(site: &Site, symbols: &[(String, u64, u64)], lines: &[(String, u32, u64)]) -> Option<(u64, u64)> {
+    let sym = |name: &str| symbols.iter().find(|&&(ref sym, _, _)| sym == name).map(|&(_, start, end)| (start, end));
+    match *site {
+        Site::Symbol(ref name) => sym(name),
+        Site::Offset(ref name, off) => sym(name).map(|(start, _)| (start + off, start + off)),
+        Site::Line(ref file, line) => {
+            let want = Path::new(file).file_name();
+            let addrs: Vec<u64> = lines.iter()
+                .filter(|&&(ref f, l, _)| l == line && Path::new(f).file_name() == want)
+                .map(|&(_, _, addr)| addr)
+                .collect();
+            match (addrs.iter().min(), addrs.iter().max()) {
+                (Some(&lo), Some(&hi)) => Some((lo, hi)),
+                _ => None,
+            }
+        }
+    }
+}
@@ -8,13 +8,16 @@ use holmes::pg::RowIter;
 use holmes::pg::dyn::{Type, Value};
 use std::any::Any;
 use std::sync::Arc;
-use rustc_serialize::json::{Json, Decoder, ToJson, encode};
-use rustc_serialize::Decodable;
+use rustc_serialize::json::{Json, ToJson};
+use store;
+use vsa::StridedInterval;
 
 #[derive(Debug, Clone, Hash, PartialOrd, PartialEq, RustcDecodable, RustcEncodable, Eq)]
 pub struct HVar {
     pub inner: Variable,
     pub offset: Option<BitVector>,
+    // Set for a whole array: memory at inner plus any of these offsets
+    pub array: Option<StridedInterval>,
 }
 
 impl HVar {
@@ -25,8 +28,7 @@ impl HVar {
 
 impl ToJson for HVar {
     fn to_json(&self) -> Json {
-        let buf = encode(self).unwrap();
-        Json::from_str(&buf).unwrap()
+        store::encode(self)
     }
 }
 
@@ -39,6 +41,7 @@ pub fn get_arg0() -> HVar {
             index: 0,
         },
         offset: None,
+        array: None,
     }
 }
 
@@ -60,9 +63,48 @@ pub fn get_arg_n(n: u8) -> HVar {
             index: 0,
         },
         offset: None,
+        array: None,
     }
 }
 
+// Abstract heap cell: field `field` of whatever object was allocated at `site` in `bin`. These
+// never name a real register, so statements pass them through untouched and they follow the
+// path of the pointer stored in them like any other alias.
+pub fn heap_cell(bin: &str, site: &BitVector, field: BitVector) -> HVar {
+    HVar {
+        inner: Variable {
+            name: format!("heap:{}:{}", bin, site),
+            type_: bap::high::bil::Type::Immediate(64),
+            tmp: false,
+            index: 0,
+        },
+        offset: Some(field),
+        array: None,
+    }
+}
+
+// Whatever a library container allocated at `site` holds, as stored by call_keeps. The field is
+// one no load or store in the binary itself will name.
+pub fn kept_cell(bin: &str, site: &BitVector) -> HVar {
+    heap_cell(bin, site, BitVector::from_u64(u64::max_value(), 64))
+}
+
+// Base of absolute addresses, so stores to globals are tracked the same way as those relative
+// to a register
+pub fn globals() -> Variable {
+    Variable {
+        name: "globals".to_string(),
+        type_: bap::high::bil::Type::Immediate(64),
+        tmp: false,
+        index: 0,
+    }
+}
+
+// Inverse of get_arg_n
+pub fn arg_index(var: &HVar) -> Option<u8> {
+    (0..6).find(|&n| get_arg_n(n) == *var)
+}
+
 pub fn get_ret() -> HVar {
     HVar {
         inner: Variable {
@@ -72,6 +114,7 @@ pub fn get_ret() -> HVar {
             index: 0,
         },
         offset: None,
+        array: None,
     }
 }
 
@@ -83,8 +126,7 @@ impl TypeT for VarType {
     }
     fn extract(&self, rows: &mut RowIter) -> Option<Value> {
         let raw: Json = rows.next().unwrap();
-        let mut decoder = Decoder::new(raw);
-        Some(Arc::new(HVar::decode(&mut decoder).unwrap()))
+        Some(Arc::new(store::decode::<HVar>(raw).unwrap()))
     }
     fn repr(&self) -> &'static str {
         "jsonb"
@@ -127,9 +169,10 @@ impl ToValue for HVar {
 impl ::std::fmt::Display for HVar {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::result::Result<(), ::std::fmt::Error> {
         write!(f, "{}", self.inner)?;
-        match self.offset {
-            Some(ref off) => write!(f, "+{}", off),
-            None => Ok(()),
+        match (&self.offset, &self.array) {
+            (&Some(ref off), _) => write!(f, "+{}", off),
+            (&None, &Some(ref array)) => write!(f, "+[{}]", array),
+            (&None, &None) => Ok(()),
         }
     }
 }
@@ -0,0 +1,297 @@
+use bap::basic::Cast;
+use bap::high::bil::{Statement, Expression, Variable, Type, BinOp};
+use std::collections::HashMap;
+use std::fmt;
+use num::ToPrimitive;
+
+// Strided intervals: the values lo, lo + stride, ..., hi, for the offsets an indexed access may
+// reach. Offsets are read as signed, so [rbp - 0x10] is at -16 rather than near 2^64, and
+// anything that would overflow an i64 just loses that bound.
+
+/// A set of integers `{ n | lo <= n <= hi, n = residue (mod stride) }`. A stride of 0 is a single
+/// value, and a missing bound is unbounded on that side.
+#[derive(Debug, Clone, Hash, PartialOrd, PartialEq, Eq, RustcDecodable, RustcEncodable)]
+pub struct StridedInterval {
+    pub stride: u64,
+    pub residue: u64,
+    pub lo: Option<i64>,
+    pub hi: Option<i64>,
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// a mod m, always in 0..m
+fn modulo(a: i64, m: u64) -> u64 {
+    if m == 0 {
+        a as u64
+    } else if a >= 0 {
+        (a as u64) % m
+    } else {
+        match (a as u64).wrapping_neg() % m {
+            0 => 0,
+            r => m - r,
+        }
+    }
+}
+
+fn distance(a: i64, b: i64) -> u64 {
+    if a >= b {
+        (a as u64).wrapping_sub(b as u64)
+    } else {
+        (b as u64).wrapping_sub(a as u64)
+    }
+}
+
+fn checked(a: Option<i64>, b: Option<i64>, f: fn(i64, i64) -> Option<i64>) -> Option<i64> {
+    match (a, b) {
+        (Some(a), Some(b)) => f(a, b),
+        _ => None,
+    }
+}
+
+impl StridedInterval {
+    pub fn constant(n: i64) -> Self {
+        StridedInterval {
+            stride: 0,
+            residue: 0,
+            lo: Some(n),
+            hi: Some(n),
+        }
+    }
+    pub fn top() -> Self {
+        StridedInterval::multiples(1)
+    }
+    /// Every multiple of k, as for an unknown index scaled by k
+    pub fn multiples(k: u64) -> Self {
+        if k == 0 {
+            return StridedInterval::constant(0);
+        }
+        StridedInterval {
+            stride: k,
+            residue: 0,
+            lo: None,
+            hi: None,
+        }
+    }
+    pub fn range(lo: i64, hi: i64) -> Self {
+        if lo == hi {
+            return StridedInterval::constant(lo);
+        }
+        StridedInterval {
+            stride: 1,
+            residue: 0,
+            lo: Some(lo),
+            hi: Some(hi),
+        }
+    }
+    pub fn as_constant(&self) -> Option<i64> {
+        if self.stride == 0 { self.lo } else { None }
+    }
+    // Some member's residue, or the value itself for a constant
+    fn rep(&self) -> i64 {
+        match self.as_constant() {
+            Some(n) => n,
+            None => self.residue as i64,
+        }
+    }
+    pub fn add(&self, other: &Self) -> Self {
+        if let (Some(a), Some(b)) = (self.as_constant(), other.as_constant()) {
+            return StridedInterval::constant(a.wrapping_add(b));
+        }
+        let stride = gcd(self.stride, other.stride);
+        StridedInterval {
+            stride: stride,
+            residue: modulo(self.rep().wrapping_add(other.rep()), stride),
+            lo: checked(self.lo, other.lo, i64::checked_add),
+            hi: checked(self.hi, other.hi, i64::checked_add),
+        }
+    }
+    pub fn scale(&self, k: i64) -> Self {
+        if k == 0 {
+            return StridedInterval::constant(0);
+        }
+        if let Some(n) = self.as_constant() {
+            return StridedInterval::constant(n.wrapping_mul(k));
+        }
+        let stride = self.stride.saturating_mul(distance(k, 0));
+        let (lo, hi) = (self.lo.and_then(|n| n.checked_mul(k)), self.hi.and_then(|n| n.checked_mul(k)));
+        let (lo, hi) = if k < 0 { (hi, lo) } else { (lo, hi) };
+        StridedInterval {
+            stride: stride,
+            residue: modulo(self.rep().wrapping_mul(k), stride),
+            lo: lo,
+            hi: hi,
+        }
+    }
+    /// The values of `x & mask` for x in self
+    pub fn mask(&self, mask: u64) -> Self {
+        match self.as_constant() {
+            Some(n) => StridedInterval::constant(((n as u64) & mask) as i64),
+            None if (mask as i64) >= 0 => StridedInterval::range(0, mask as i64),
+            None => StridedInterval::top(),
+        }
+    }
+    /// Smallest strided interval holding both
+    pub fn join(&self, other: &Self) -> Self {
+        if self == other {
+            return self.clone();
+        }
+        let stride = gcd(gcd(self.stride, other.stride), distance(self.rep(), other.rep()));
+        StridedInterval {
+            stride: stride,
+            residue: modulo(self.rep(), stride),
+            lo: checked(self.lo, other.lo, |a, b| Some(a.min(b))),
+            hi: checked(self.hi, other.hi, |a, b| Some(a.max(b))),
+        }
+    }
+    pub fn contains(&self, n: i64) -> bool {
+        self.lo.map_or(true, |lo| lo <= n) && self.hi.map_or(true, |hi| n <= hi)
+            && modulo(n, self.stride) == modulo(self.rep(), self.stride)
+    }
+    /// Whether the two may share a member. Bounds and residues are checked separately, so this
+    /// can say yes for sets whose common residues all fall outside the common range.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        let lo = checked(self.lo, other.lo, |a, b| Some(a.max(b))).or(self.lo).or(other.lo);
+        let hi = checked(self.hi, other.hi, |a, b| Some(a.min(b))).or(self.hi).or(other.hi);
+        if let (Some(lo), Some(hi)) = (lo, hi) {
+            if lo > hi {
+                return false;
+            }
+        }
+        let g = gcd(self.stride, other.stride);
+        modulo(self.rep(), g) == modulo(other.rep(), g)
+    }
+}
+
+impl fmt::Display for StridedInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(n) = self.as_constant() {
+            return write!(f, "{}", n);
+        }
+        write!(f, "{}n+{}", self.stride, self.residue)?;
+        match (self.lo, self.hi) {
+            (None, None) => Ok(()),
+            (lo, hi) => {
+                write!(f, " in [{}, {}]",
+                       lo.map_or("-inf".to_string(), |n| n.to_string()),
+                       hi.map_or("inf".to_string(), |n| n.to_string()))
+            }
+        }
+    }
+}
+
+/// An address as a base register plus a set of offsets from it. Addresses with no register in
+/// them are absolute, i.e. globals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub base: Option<Variable>,
+    pub offsets: StridedInterval,
+}
+
+/// What is known of each register's value within a block, as an offset from some other
+/// register's value on entry or as plain numbers.
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    vals: HashMap<Variable, Region>,
+}
+
+fn const_val(bv: &::bap::high::bitvector::BitVector) -> Option<i64> {
+    bv.to_u64().map(|n| n as i64)
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Env::default()
+    }
+    /// Abstractly evaluates an address expression. A register with no known value stands for
+    /// itself as the base, and at most one such register is kept; any other is an unknown index.
+    pub fn region(&self, e: &Expression) -> Option<Region> {
+        match *e {
+            Expression::Var(ref v) => {
+                Some(self.vals.get(v).cloned().unwrap_or_else(|| {
+                    Region { base: Some(v.clone()), offsets: StridedInterval::constant(0) }
+                }))
+            }
+            Expression::Cast { kind: Cast::Low, ref arg, .. } |
+            Expression::Cast { kind: Cast::Unsigned, ref arg, .. } |
+            Expression::Cast { kind: Cast::Signed, ref arg, .. } => self.region(arg),
+            Expression::BinOp { op: BinOp::Add, ref lhs, ref rhs } => {
+                match (self.region(lhs), self.region(rhs)) {
+                    (Some(l), Some(r)) => {
+                        let (base, offsets) = match (l.base, r.base) {
+                            // Two unknown registers, so take the first as the base
+                            (Some(b), Some(_)) => (Some(b), l.offsets.add(&StridedInterval::top())),
+                            (Some(b), None) | (None, Some(b)) => (Some(b), l.offsets.add(&r.offsets)),
+                            (None, None) => (None, l.offsets.add(&r.offsets)),
+                        };
+                        Some(Region { base: base, offsets: offsets })
+                    }
+                    _ => None,
+                }
+            }
+            Expression::BinOp { op: BinOp::Sub, ref lhs, ref rhs } => {
+                self.region(lhs).map(|l| {
+                    Region { base: l.base, offsets: l.offsets.add(&self.value(rhs).scale(-1)) }
+                })
+            }
+            _ => {
+                let v = self.value(e);
+                if v == StridedInterval::top() {
+                    None
+                } else {
+                    Some(Region { base: None, offsets: v })
+                }
+            }
+        }
+    }
+    /// The values e may take, for use as an index
+    pub fn value(&self, e: &Expression) -> StridedInterval {
+        let top = StridedInterval::top();
+        match *e {
+            Expression::Var(ref v) => {
+                match self.vals.get(v) {
+                    Some(&Region { base: None, ref offsets }) => offsets.clone(),
+                    _ => top,
+                }
+            }
+            Expression::Const(ref bv) => const_val(bv).map_or(top, StridedInterval::constant),
+            Expression::Cast { kind: Cast::Low, ref arg, .. } |
+            Expression::Cast { kind: Cast::Unsigned, ref arg, .. } |
+            Expression::Cast { kind: Cast::Signed, ref arg, .. } => self.value(arg),
+            Expression::BinOp { op, ref lhs, ref rhs } => {
+                let (l, r) = (self.value(lhs), self.value(rhs));
+                match (op, l.as_constant(), r.as_constant()) {
+                    (BinOp::Add, _, _) => l.add(&r),
+                    (BinOp::Sub, _, _) => l.add(&r.scale(-1)),
+                    (BinOp::Mul, _, Some(k)) => l.scale(k),
+                    (BinOp::Mul, Some(k), _) => r.scale(k),
+                    (BinOp::LShift, _, Some(k)) if 0 <= k && k < 63 => l.scale(1 << k),
+                    (BinOp::And, _, Some(m)) => l.mask(m as u64),
+                    (BinOp::And, Some(m), _) => r.mask(m as u64),
+                    _ => top,
+                }
+            }
+            _ => top,
+        }
+    }
+    /// Updates the environment past stmt
+    pub fn step(&mut self, stmt: &Statement) {
+        if let Statement::Move { lhs: ref reg, rhs: ref e } = *stmt {
+            if let Type::Immediate(_) = reg.type_ {
+                let r = self.region(e);
+                // Anything relative to the old value of reg no longer means anything
+                self.vals.retain(|_, known| known.base.as_ref() != Some(reg));
+                self.vals.remove(reg);
+                match r {
+                    Some(ref r) if r.base.as_ref() == Some(reg) => (),
+                    Some(r) => {
+                        self.vals.insert(reg.clone(), r);
+                    }
+                    None => (),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+extern crate tiamat;
+extern crate bap;
+extern crate num;
+use bap::basic::{BinOp, UnOp, Cast};
+use bap::high::bitvector::BitVector;
+use num::{BigUint, FromPrimitive};
+use tiamat::arith;
+
+fn bv(n: u64, width: usize) -> BitVector {
+    BitVector::new_unsigned(BigUint::from_u64(n).unwrap(), width)
+}
+
+fn op(op: BinOp, l: u64, r: u64, width: usize) -> u64 {
+    arith::binop(op, &bv(l, width), &bv(r, width)).unwrap().to_u64().unwrap()
+}
+
+#[test]
+pub fn add_sub_wrap() {
+    // lea rax, [rdi+0x10]
+    assert_eq!(op(BinOp::Add, 0x400000, 0x10, 64), 0x400010);
+    // add eax, 1 at 0xffffffff
+    assert_eq!(op(BinOp::Add, 0xffffffff, 1, 32), 0);
+    // sub rsp, 8
+    assert_eq!(op(BinOp::Sub, 0x7fffffffe000, 8, 64), 0x7fffffffdff8);
+    // sub eax, 1 at 0
+    assert_eq!(op(BinOp::Sub, 0, 1, 32), 0xffffffff);
+    assert_eq!(op(BinOp::Sub, 0, 1, 64), 0xffffffffffffffff);
+}
+
+#[test]
+pub fn mul_div() {
+    // imul eax, ecx keeps the low half
+    assert_eq!(op(BinOp::Mul, 0x10000, 0x10000, 32), 0);
+    assert_eq!(op(BinOp::Mul, 6, 7, 64), 42);
+    assert_eq!(op(BinOp::Div, 100, 7, 64), 14);
+    assert_eq!(op(BinOp::Mod, 100, 7, 64), 2);
+    // idiv: -7 / 2 = -3 rem -1
+    assert_eq!(op(BinOp::SDiv, (-7i64) as u64, 2, 64), (-3i64) as u64);
+    assert_eq!(op(BinOp::SMod, (-7i64) as u64, 2, 64), (-1i64) as u64);
+    assert_eq!(op(BinOp::SDiv, 7, (-2i64) as u64, 64), (-3i64) as u64);
+    assert_eq!(op(BinOp::SMod, 7, (-2i64) as u64, 64), 1);
+    assert!(arith::binop(BinOp::Div, &bv(1, 64), &bv(0, 64)).is_none());
+    assert!(arith::binop(BinOp::SMod, &bv(1, 64), &bv(0, 64)).is_none());
+}
+
+#[test]
+pub fn bitwise() {
+    // and rsp, -16
+    assert_eq!(op(BinOp::And, 0x7fffffffe008, 0xfffffffffffffff0, 64), 0x7fffffffe000);
+    assert_eq!(op(BinOp::Or, 0xf0, 0x0f, 8), 0xff);
+    // xor eax, eax
+    assert_eq!(op(BinOp::Xor, 0xdeadbeef, 0xdeadbeef, 32), 0);
+    assert_eq!(arith::unop(UnOp::Not, &bv(0, 32)).to_u64().unwrap(), 0xffffffff);
+    assert_eq!(arith::unop(UnOp::Not, &bv(0x0f, 8)).to_u64().unwrap(), 0xf0);
+    // neg rax
+    assert_eq!(arith::unop(UnOp::Neg, &bv(1, 64)).to_u64().unwrap(), 0xffffffffffffffff);
+    assert_eq!(arith::unop(UnOp::Neg, &bv(0, 64)).to_u64().unwrap(), 0);
+    assert_eq!(arith::unop(UnOp::Neg, &bv(0x80, 8)).to_u64().unwrap(), 0x80);
+}
+
+#[test]
+pub fn shifts() {
+    // shl rax, 4
+    assert_eq!(op(BinOp::LShift, 0x1234, 4, 64), 0x12340);
+    assert_eq!(op(BinOp::LShift, 0x80000000, 1, 32), 0);
+    // shr
+    assert_eq!(op(BinOp::RShift, 0x80000000, 31, 32), 1);
+    // sar keeps the sign
+    assert_eq!(op(BinOp::ARShift, 0x80000000, 31, 32), 0xffffffff);
+    assert_eq!(op(BinOp::ARShift, (-16i64) as u64, 2, 64), (-4i64) as u64);
+    assert_eq!(op(BinOp::ARShift, 16, 2, 64), 4);
+    // Shifting by the width or more clears (or sign-fills) everything
+    assert_eq!(op(BinOp::LShift, 1, 64, 64), 0);
+    assert_eq!(op(BinOp::RShift, 0xffffffffffffffff, 100, 64), 0);
+    assert_eq!(op(BinOp::ARShift, 0x8000000000000000, 100, 64), 0xffffffffffffffff);
+}
+
+#[test]
+pub fn comparisons() {
+    assert_eq!(op(BinOp::Eq, 5, 5, 64), 1);
+    assert_eq!(op(BinOp::Neq, 5, 5, 64), 0);
+    // jb / jae use unsigned comparison
+    assert_eq!(op(BinOp::Lt, 1, 0xffffffffffffffff, 64), 1);
+    assert_eq!(op(BinOp::Le, 3, 3, 64), 1);
+    // jl / jge use signed comparison
+    assert_eq!(op(BinOp::SLt, 1, 0xffffffffffffffff, 64), 0);
+    assert_eq!(op(BinOp::SLt, 0xffffffffffffffff, 1, 64), 1);
+    assert_eq!(op(BinOp::SLe, 0x80, 0x7f, 8), 1);
+    assert_eq!(arith::width(&arith::binop(BinOp::Eq, &bv(1, 64), &bv(1, 64)).unwrap()), 1);
+}
+
+#[test]
+pub fn casts() {
+    // movsx rax, byte
+    assert_eq!(arith::cast(Cast::Signed, 64, &bv(0xff, 8)).to_u64().unwrap(), 0xffffffffffffffff);
+    assert_eq!(arith::cast(Cast::Signed, 64, &bv(0x7f, 8)).to_u64().unwrap(), 0x7f);
+    // movzx eax, byte
+    assert_eq!(arith::cast(Cast::Unsigned, 32, &bv(0xff, 8)).to_u64().unwrap(), 0xff);
+    // mov eax, ecx truncates
+    assert_eq!(arith::cast(Cast::Low, 32, &bv(0x123456789, 64)).to_u64().unwrap(), 0x23456789);
+    // high half, as for mul's rdx
+    assert_eq!(arith::cast(Cast::High, 32, &bv(0x123456789abcdef0, 64)).to_u64().unwrap(), 0x12345678);
+    assert_eq!(arith::width(&arith::cast(Cast::Low, 8, &bv(0x1ff, 64))), 8);
+}
+
+#[test]
+pub fn extract_concat() {
+    // ah is bits 8..15 of rax
+    assert_eq!(arith::extract(8, 15, &bv(0x1234, 64)).to_u64().unwrap(), 0x12);
+    assert_eq!(arith::width(&arith::extract(8, 15, &bv(0x1234, 64))), 8);
+    // Writing al keeps the rest of rax
+    let rest = arith::extract(8, 63, &bv(0x1122334455667788, 64));
+    let rax = arith::concat(&rest, &bv(0xff, 8));
+    assert_eq!(rax.to_u64().unwrap(), 0x11223344556677ff);
+    assert_eq!(arith::width(&rax), 64);
+    // edx:eax
+    assert_eq!(arith::concat(&bv(1, 32), &bv(2, 32)).to_u64().unwrap(), 0x100000002);
+}
@@ -0,0 +1,59 @@
+extern crate tiamat;
+use std::path::Path;
+use tiamat::asan::{self, Loc, Sites};
+
+const REPORT: &'static str = "\
+=================================================================
+==4242==ERROR: AddressSanitizer: heap-use-after-free on address 0x602000000010 at pc 0x0000004011f3 bp 0x7ffd3c0e8a30 sp 0x7ffd3c0e8a28
+WRITE of size 1 at 0x602000000010 thread T0
+    #0 0x4011f2  (/src/samples/use_after_free/simple.asan+0x4011f2)
+    #1 0x7f2a1c02d082  (/lib/x86_64-linux-gnu/libc.so.6+0x24082)
+
+0x602000000010 is located 0 bytes inside of 1-byte region [0x602000000010,0x602000000011)
+freed by thread T0 here:
+    #0 0x7f2a1c2b540f  (/lib/x86_64-linux-gnu/libasan.so.5+0x10d40f)
+    #1 0x4011bb  (/src/samples/use_after_free/simple.asan+0x4011bb)
+
+previously allocated by thread T0 here:
+    #0 0x7f2a1c2b5808  (/lib/x86_64-linux-gnu/libasan.so.5+0x10d808)
+    #1 0x401177  (/src/samples/use_after_free/simple.asan+0x401177)
+
+SUMMARY: AddressSanitizer: heap-use-after-free (/src/samples/use_after_free/simple.asan+0x4011f2)
+";
+
+fn loc(symbol: &str, offset: u64) -> Loc {
+    Loc { symbol: symbol.to_string(), offset: offset }
+}
+
+#[test]
+pub fn parse() {
+    let reports = asan::parse(REPORT);
+    assert_eq!(reports.len(), 1);
+    let bin = Path::new("samples/use_after_free/simple.asan");
+    // The interceptors are in libasan, so the sites are the first frames in the binary
+    assert_eq!(asan::site(&reports[0].use_, bin), Some(0x4011f2));
+    assert_eq!(asan::site(&reports[0].free, bin), Some(0x4011bb));
+    assert_eq!(asan::site(&reports[0].alloc, bin), Some(0x401177));
+    let syms = vec![("main".to_string(), 0x401156, 0x401220)];
+    assert_eq!(Loc::at(&syms, 0x401177), Some(loc("main", 0x21)));
+    assert_eq!(Loc::at(&syms, 0x401300), None);
+}
+
+#[test]
+pub fn compare() {
+    let report = Sites { alloc: loc("main", 0x21), free: Some(loc("main", 0x65)), use_: loc("main", 0x9c) };
+    let near = Sites { alloc: loc("main", 0x18), free: None, use_: loc("main", 0x80) };
+    let far = Sites { alloc: loc("main", 0x18), free: None, use_: loc("main", 0x10) };
+    let other = Sites { alloc: loc("helper", 0x4), free: None, use_: loc("main", 0x80) };
+    let cmp = asan::compare(&[report.clone()], &[far.clone(), near.clone(), other.clone()]);
+    assert_eq!(cmp.agreed, vec![(report.clone(), near)]);
+    assert!(cmp.missed.is_empty());
+    assert_eq!(cmp.extra, vec![far, other]);
+    let cmp = asan::compare(&[report.clone()], &[]);
+    assert_eq!(cmp.missed, vec![report.clone()]);
+    // A finding is only paired once, even when it is the closest for both reports
+    let again = Sites { alloc: loc("main", 0x21), free: Some(loc("main", 0x65)), use_: loc("main", 0x90) };
+    let cmp = asan::compare(&[report.clone(), again.clone()], &[near.clone(), far.clone()]);
+    assert_eq!(cmp.agreed, vec![(report, near), (again, far)]);
+    assert!(cmp.extra.is_empty());
+}
@@ -0,0 +1,71 @@
+// BIL builders shared by the tests that run transfer functions, the interpreter and the symbolic
+// executor over hand-written blocks. Not every test file uses all of them.
+#![allow(dead_code)]
+
+use bap::basic::Endian;
+use bap::high::bil::{BinOp, Expression, Statement, Type, Variable};
+use bap::high::bitvector::BitVector;
+
+pub fn reg(name: &str) -> Variable {
+    Variable {
+        name: name.to_string(),
+        type_: Type::Immediate(64),
+        tmp: false,
+        index: 0,
+    }
+}
+
+pub fn mem() -> Variable {
+    Variable {
+        name: "mem".to_string(),
+        type_: Type::Memory { addr_size: 64, cell_size: 8 },
+        tmp: false,
+        index: 0,
+    }
+}
+
+pub fn konst(n: u64) -> Expression {
+    Expression::Const(BitVector::from_u64(n, 64))
+}
+
+pub fn var(name: &str) -> Expression {
+    Expression::Var(reg(name))
+}
+
+pub fn binop(op: BinOp, lhs: Expression, rhs: Expression) -> Expression {
+    Expression::BinOp {
+        op: op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    }
+}
+
+pub fn add(lhs: Expression, rhs: Expression) -> Expression {
+    binop(BinOp::Add, lhs, rhs)
+}
+
+pub fn load(idx: Expression) -> Expression {
+    Expression::Load {
+        memory: Box::new(Expression::Var(mem())),
+        index: Box::new(idx),
+        endian: Endian::Little,
+        size: 64,
+    }
+}
+
+pub fn store(idx: Expression, val: Expression) -> Statement {
+    Statement::Move {
+        lhs: mem(),
+        rhs: Expression::Store {
+            memory: Box::new(Expression::Var(mem())),
+            index: Box::new(idx),
+            value: Box::new(val),
+            endian: Endian::Little,
+            size: 64,
+        },
+    }
+}
+
+pub fn mov(dst: &str, e: Expression) -> Statement {
+    Statement::Move { lhs: reg(dst), rhs: e }
+}
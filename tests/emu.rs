@@ -0,0 +1,51 @@
+extern crate tiamat;
+use std::collections::HashSet;
+use std::io::Read;
+use tiamat::emu::{Emulator, Outcome, Witness};
+
+fn load(path: &str, args: &[&str]) -> Emulator {
+    let mut contents = Vec::new();
+    std::fs::File::open(path).unwrap().read_to_end(&mut contents).unwrap();
+    let mut argv = vec![path.to_string()];
+    argv.extend(args.iter().map(|a| a.to_string()));
+    Emulator::load(&contents, &argv).unwrap()
+}
+
+#[test]
+pub fn runs_to_exit() {
+    let mut emu = load("./samples/use_after_free/simple", &[]);
+    // Nothing to steer towards, so it just runs main to the end
+    assert_eq!(emu.run(&Witness::default(), 100000), Outcome::Unreached("main returned".to_string()));
+    assert_eq!(emu.heap().len(), 1);
+    assert!(emu.heap()[0].freed);
+    assert!(emu.stubbed.iter().any(|name| name == "printf"));
+}
+
+#[test]
+pub fn argc() {
+    // Freed only with no arguments, so an argument leaves the object alive
+    let mut emu = load("./samples/use_after_free/correlated", &["x"]);
+    emu.run(&Witness::default(), 100000);
+    assert_eq!(emu.heap().len(), 1);
+    assert!(!emu.heap()[0].freed);
+}
+
+#[test]
+pub fn single_trace() {
+    // Two traces from the allocation at 0x10: 0x10 -> 0x30 -> 0x40 where nothing is freed, and
+    // 0x10 -> 0x20 (free) -> 0x40 (use). Only the second gets to the use freed.
+    let facts = vec![
+        (0x10, 0, false),
+        (0x30, 1, false),
+        (0x20, 1, false),
+        (0x40, 2, false),
+        (0x40, 2, true),
+    ];
+    let edges = [(0x10, 0x20), (0x10, 0x30), (0x20, 0x40), (0x30, 0x40)];
+    let adjacent = |from: u64, to: u64| edges.contains(&(from, to));
+    let frees: HashSet<u64> = vec![0x20].into_iter().collect();
+    let blocks = tiamat::emu::single_trace(&facts, 0x40, &frees, &adjacent);
+    assert_eq!(blocks, vec![0x10, 0x20, 0x40].into_iter().collect());
+    // Never freed at the sink, so there is no trace to follow
+    assert!(tiamat::emu::single_trace(&facts[..4], 0x40, &frees, &adjacent).is_empty());
+}
@@ -0,0 +1,82 @@
+extern crate tiamat;
+use std::collections::HashSet;
+use tiamat::grade::{self, Row, Testcase, CHECKERS};
+
+fn names(funcs: &[&str]) -> Vec<String> {
+    funcs.iter().map(|f| f.to_string()).collect()
+}
+
+fn hits(funcs: &[&str]) -> HashSet<String> {
+    funcs.iter().map(|f| f.to_string()).collect()
+}
+
+#[test]
+pub fn parse() {
+    assert_eq!(
+        grade::parse("CWE416_Use_After_Free__malloc_free_char_01_bad"),
+        Some(Testcase {
+            cwe: "CWE416".to_string(),
+            name: "CWE416_Use_After_Free__malloc_free_char_01".to_string(),
+            variant: 1,
+            bad: true,
+        })
+    );
+    // The second file of a two-file testcase names the same testcase
+    let sink = grade::parse("CWE416_Use_After_Free__malloc_free_char_54b_goodG2BSink").unwrap();
+    assert_eq!(sink.name, "CWE416_Use_After_Free__malloc_free_char_54");
+    assert_eq!(sink.variant, 54);
+    assert!(!sink.bad);
+    assert_eq!(grade::parse("goodG2B"), None);
+    assert_eq!(grade::parse("main"), None);
+}
+
+#[test]
+pub fn score() {
+    let funcs = names(&[
+        "CWE416_Use_After_Free__malloc_free_char_01_bad",
+        "CWE416_Use_After_Free__malloc_free_char_01_good",
+        "CWE416_Use_After_Free__malloc_free_int_01_bad",
+        "CWE416_Use_After_Free__malloc_free_int_01_good",
+        "CWE416_Use_After_Free__malloc_free_char_54a_bad",
+        "CWE416_Use_After_Free__malloc_free_char_54b_badSink",
+        "CWE416_Use_After_Free__malloc_free_char_54a_good",
+        "CWE415_Double_Free__malloc_free_char_01_bad",
+        "main",
+    ]);
+    let bad = hits(&[
+        "CWE416_Use_After_Free__malloc_free_char_01_bad",
+        "CWE416_Use_After_Free__malloc_free_char_54b_badSink",
+        "CWE415_Double_Free__malloc_free_char_01_bad",
+    ]);
+    let good = hits(&["CWE416_Use_After_Free__malloc_free_int_01_good"]);
+    let rows = grade::score(&CHECKERS[0], &funcs, &bad, &good);
+    assert_eq!(rows.len(), 2);
+    assert_eq!((rows[0].variant, rows[0].true_pos, rows[0].false_pos, rows[0].false_neg, rows[0].true_neg), (Some(1), 1, 1, 1, 1));
+    assert_eq!((rows[1].variant, rows[1].true_pos, rows[1].false_pos, rows[1].false_neg, rows[1].true_neg), (Some(54), 1, 0, 0, 1));
+    assert_eq!(rows[0].precision(), Some(0.5));
+    let table = grade::table(&rows);
+    let total: Vec<&str> = table.lines().last().unwrap().split_whitespace().collect();
+    assert_eq!(&total[..7], &["uaf", "CWE416", "all", "2", "1", "1", "2"]);
+}
+
+#[test]
+pub fn regressions() {
+    let row = |tp, fp| Row {
+        checker: "uaf".to_string(),
+        cwe: "CWE416".to_string(),
+        variant: Some(1),
+        binary: None,
+        true_pos: tp,
+        false_pos: fp,
+        false_neg: 2 - tp,
+        true_neg: 2 - fp,
+    };
+    assert!(grade::regressions(&[row(1, 1)], &[row(2, 0)]).is_empty());
+    assert_eq!(grade::regressions(&[row(1, 1)], &[row(0, 2)]).len(), 2);
+    assert_eq!(grade::regressions(&[row(1, 1)], &[]), vec!["uaf CWE416 variant 01: no longer scored".to_string()]);
+    let truth = Row { variant: None, binary: Some("simple".to_string()), ..row(1, 0) };
+    assert_eq!(grade::regressions(&[truth], &[]), vec!["uaf CWE416 binary simple: no longer scored".to_string()]);
+    // Baselines round-trip through the same encoding the grade command writes
+    let text = tiamat::store::to_text(&vec![row(1, 0)]);
+    assert_eq!(tiamat::store::from_text::<Vec<Row>>(&text), Some(vec![row(1, 0)]));
+}
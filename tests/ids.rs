@@ -0,0 +1,23 @@
+extern crate tiamat;
+extern crate bap;
+use bap::high::bitvector::BitVector;
+use tiamat::ids;
+
+#[test]
+pub fn segment_ids_stable() {
+    let digest = ids::digest(b"not really an elf");
+    assert_eq!(ids::segment_id(&digest, 0), ids::segment_id(&ids::digest(b"not really an elf"), 0));
+    assert!(ids::segment_id(&digest, 0) != ids::segment_id(&digest, 1));
+    assert!(ids::segment_id(&digest, 0) != ids::segment_id(&ids::digest(b"another file"), 0));
+}
+
+#[test]
+pub fn stack_ids_chain() {
+    let bin = "a.out".to_string();
+    let addr = BitVector::from_u64(0x400500, 64);
+    let frame = ids::stack_id((&0, &bin, &addr));
+    assert_eq!(frame, ids::stack_id((&0, &bin, &addr)));
+    assert!(frame != 0);
+    assert!(ids::stack_id((&frame, &bin, &addr)) != frame);
+    assert!(ids::stack_id((&0, &"b.out".to_string(), &addr)) != frame);
+}
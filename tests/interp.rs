@@ -0,0 +1,268 @@
+extern crate tiamat;
+extern crate bap;
+extern crate num;
+use bap::basic::Endian;
+use bap::high::bil::{Statement, Expression, Variable, Type, BinOp};
+use bap::high::bitvector::BitVector;
+use num::ToPrimitive;
+use tiamat::interp::{Fault, Machine, Next};
+use tiamat::sema::Sema;
+use tiamat::transfer;
+use tiamat::var::HVar;
+
+mod common;
+use common::*;
+
+fn u64_of(bv: &BitVector) -> u64 {
+    bv.unum().to_u64().unwrap()
+}
+
+#[test]
+pub fn memory() {
+    let mut m = Machine::new();
+    m.set_reg("RSP", BitVector::from_u64(0x1000, 64));
+    let block = vec![
+        mov("RAX", konst(0x1122334455667788)),
+        store(add(var("RSP"), konst(8)), var("RAX")),
+        mov("RBX", load(add(var("RSP"), konst(8)))),
+        Statement::Move {
+            lhs: Variable { name: "ZF".to_string(), type_: Type::Immediate(1), tmp: false, index: 0 },
+            rhs: Expression::BinOp {
+                op: BinOp::Eq,
+                lhs: Box::new(var("RAX")),
+                rhs: Box::new(var("RBX")),
+            },
+        },
+    ];
+    assert_eq!(m.exec(&block), Ok(Next::Fall));
+    assert_eq!(u64_of(m.reg("RBX").unwrap()), 0x1122334455667788);
+    // Little endian, so the low byte comes first
+    assert_eq!(m.byte(0x1008), Some(0x88));
+    assert_eq!(m.byte(0x100f), Some(0x11));
+    assert_eq!(m.flag("ZF"), Some(true));
+    assert_eq!(m.accesses.len(), 2);
+    // Nothing was ever stored just below the stack pointer
+    assert_eq!(m.eval(&load(var("RSP"))), Err(Fault::UnsetMem(0x1000)));
+    assert_eq!(m.eval(&var("RCX")), Err(Fault::UnsetReg("RCX".to_string())));
+}
+
+#[test]
+pub fn branch() {
+    let mut m = Machine::new();
+    m.set_reg("RAX", BitVector::from_u64(3, 64));
+    let block = vec![
+        Statement::IfThenElse {
+            cond: Expression::BinOp {
+                op: BinOp::Lt,
+                lhs: Box::new(var("RAX")),
+                rhs: Box::new(konst(5)),
+            },
+            then_clause: vec![Statement::Jump(konst(0x40))],
+            else_clause: vec![],
+        },
+        mov("RAX", konst(0)),
+    ];
+    assert_eq!(m.exec(&block), Ok(Next::Jump(BitVector::from_u64(0x40, 64))));
+    // The jump ends the block, so RAX is untouched
+    assert_eq!(u64_of(m.reg("RAX").unwrap()), 3);
+}
+
+// Random blocks of 64-bit moves, adds, and aligned stack loads and stores. Only RSP and RBP are
+// used as bases and neither is ever written, so every access through them is at a fixed place.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+    fn pick<'a>(&mut self, from: &[&'a str]) -> &'a str {
+        from[(self.next() as usize) % from.len()]
+    }
+}
+
+const GPRS: &'static [&'static str] = &["RAX", "RBX", "RCX", "RDX"];
+const BASES: &'static [&'static str] = &["RSP", "RBP"];
+const SLOTS: u64 = 4;
+
+fn random_stmt(rng: &mut Rng) -> Statement {
+    let dst = rng.pick(GPRS);
+    let slot = add(var(rng.pick(BASES)), konst(8 * (rng.next() % SLOTS)));
+    match rng.next() % 5 {
+        0 => mov(dst, konst(rng.next())),
+        1 => mov(dst, var(rng.pick(GPRS))),
+        2 => mov(dst, add(var(rng.pick(GPRS)), konst(1 + rng.next() % 15))),
+        3 => mov(dst, load(slot)),
+        _ => store(slot, var(rng.pick(GPRS))),
+    }
+}
+
+fn random_block(rng: &mut Rng) -> Sema {
+    let len = 1 + rng.next() % 8;
+    let stmts = (0..len).map(|_| random_stmt(rng)).collect();
+    Sema { stmts: stmts }
+}
+
+// As random_block, but also loading from a heap object HEAP points to, which is never written
+const HEAP: &'static str = "RSI";
+const HEAP_AT: u64 = 0x600000;
+
+fn heap_load(rng: &mut Rng) -> Statement {
+    let dst = rng.pick(GPRS);
+    mov(dst, load(add(var(HEAP), konst(8 * (rng.next() % SLOTS)))))
+}
+
+fn random_heap_block(rng: &mut Rng) -> Sema {
+    let len = 1 + rng.next() % 8;
+    let stmts = (0..len)
+        .map(|_| if rng.next() % 3 == 0 { heap_load(rng) } else { random_stmt(rng) })
+        .collect();
+    Sema { stmts: stmts }
+}
+
+fn random_machine(rng: &mut Rng) -> Machine {
+    let mut m = Machine::new();
+    for r in GPRS.iter() {
+        m.set_reg(r, BitVector::from_u64(rng.next() << 16, 64));
+    }
+    m.set_reg("RSP", BitVector::from_u64(0x7fff0000, 64));
+    m.set_reg("RBP", BitVector::from_u64(0x7fff1000, 64));
+    for base in [0x7fff0000u64, 0x7fff1000].iter() {
+        for i in 0..SLOTS * 8 {
+            m.map(base + i, &[rng.next() as u8]);
+        }
+    }
+    m
+}
+
+fn reg_val(m: &Machine, name: &str) -> u64 {
+    u64_of(m.reg(name).unwrap())
+}
+
+// The stack slots random blocks use, with what they hold
+fn slot_vals(m: &mut Machine) -> Vec<(&'static str, u64, u64)> {
+    let mut out = Vec::new();
+    for base in BASES.iter() {
+        let at = reg_val(m, base);
+        for i in 0..SLOTS {
+            let val = u64_of(&m.read(at + 8 * i, 8, &Endian::Little).unwrap());
+            out.push((*base, 8 * i, val));
+        }
+    }
+    out
+}
+
+fn is_slot(hv: &HVar, base: &str, off: u64) -> bool {
+    hv.inner.name == base && hv.offset.as_ref().map(u64_of) == Some(off)
+}
+
+#[test]
+pub fn const_block_sound() {
+    let mut rng = Rng(1);
+    for _ in 0..500 {
+        let block = random_block(&mut rng);
+        let consts = transfer::const_block(&block);
+        // Whatever the starting state, the constants found have to come out the same
+        for _ in 0..4 {
+            let mut m = random_machine(&mut rng);
+            let start = m.clone();
+            m.run(&block).unwrap();
+            for &(ref hv, ref k) in consts.iter() {
+                let got = match hv.offset {
+                    None => u64_of(m.reg(&hv.inner.name).unwrap()),
+                    Some(ref off) => {
+                        let addr = reg_val(&start, &hv.inner.name) + u64_of(off);
+                        u64_of(&m.read(addr, 8, &Endian::Little).unwrap())
+                    }
+                };
+                assert_eq!(got, u64_of(k), "{} in {:?}", hv, block.stmts);
+            }
+        }
+    }
+}
+
+#[test]
+pub fn xfer_taint_over_approximates() {
+    let mut rng = Rng(2);
+    for _ in 0..500 {
+        let block = random_block(&mut rng);
+        let src = rng.pick(GPRS);
+        let tainted = transfer::xfer_taint((&block, &HVar { inner: reg(src), offset: None, array: None }));
+        let mut m = random_machine(&mut rng);
+        // The pointer we follow, distinct from everything else the block starts with
+        let ptr = 0xdead0000u64;
+        m.set_reg(src, BitVector::from_u64(ptr, 64));
+        m.run(&block).unwrap();
+        for r in GPRS.iter() {
+            if reg_val(&m, r) == ptr {
+                assert!(tainted.iter().any(|hv| hv.inner.name == *r && hv.offset.is_none()),
+                        "{} holds {} after {:?}", r, src, block.stmts);
+            }
+        }
+        for (base, off, val) in slot_vals(&mut m) {
+            if val == ptr {
+                assert!(tainted.iter().any(|hv| is_slot(hv, base, off)),
+                        "{}+{} holds {} after {:?}", base, off, src, block.stmts);
+            }
+        }
+    }
+}
+
+#[test]
+pub fn heap_init_over_approximates() {
+    let mut rng = Rng(4);
+    for _ in 0..500 {
+        let block = random_heap_block(&mut rng);
+        let sets = transfer::heap_init(&block);
+        let mut m = random_machine(&mut rng);
+        m.set_reg(HEAP, BitVector::from_u64(HEAP_AT, 64));
+        for i in 0..SLOTS * 8 {
+            m.map(HEAP_AT + i, &[rng.next() as u8]);
+        }
+        // Every pointer loaded out of the heap object, stepping a statement at a time to see them
+        let mut loaded = Vec::new();
+        for stmt in block.stmts.iter() {
+            m.exec(&[stmt.clone()]).unwrap();
+            if let Statement::Move { ref lhs, rhs: Expression::Load { ref index, .. } } = *stmt {
+                if let Expression::BinOp { lhs: ref base, .. } = **index {
+                    if let Expression::Var(ref base) = **base {
+                        if base.name == HEAP {
+                            loaded.push(reg_val(&m, &lhs.name));
+                        }
+                    }
+                }
+            }
+        }
+        let in_some = |f: &Fn(&HVar) -> bool| sets.iter().any(|&(_, ref set)| set.iter().any(|hv| f(hv)));
+        for &ptr in loaded.iter() {
+            for r in GPRS.iter() {
+                if reg_val(&m, r) == ptr {
+                    assert!(in_some(&|hv| hv.inner.name == *r && hv.offset.is_none()),
+                            "{} holds a heap load after {:?}", r, block.stmts);
+                }
+            }
+            for (base, off, val) in slot_vals(&mut m) {
+                if val == ptr {
+                    assert!(in_some(&|hv| is_slot(hv, base, off)),
+                            "{}+{} holds a heap load after {:?}", base, off, block.stmts);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+pub fn deref_var_over_approximates() {
+    let mut rng = Rng(3);
+    for _ in 0..500 {
+        let block = random_block(&mut rng);
+        let base = rng.pick(BASES);
+        let derefs = transfer::deref_var((&block, &HVar { inner: reg(base), offset: None, array: None }));
+        let mut m = random_machine(&mut rng);
+        let at = reg_val(&m, base);
+        m.run(&block).unwrap();
+        if m.accesses.iter().any(|a| at <= a.addr && a.addr < at + SLOTS * 8) {
+            assert!(derefs, "{} dereferenced in {:?}", base, block.stmts);
+        }
+    }
+}
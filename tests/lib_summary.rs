@@ -0,0 +1,50 @@
+extern crate tiamat;
+use std::collections::BTreeMap;
+use tiamat::lib_summary::{FuncSummary, LibSummary, SummaryStore};
+
+#[test]
+pub fn store_roundtrip() {
+    let dir = std::env::temp_dir().join(format!("tiamat-summaries-{}", std::process::id()));
+    let store = SummaryStore::new(dir.clone());
+    let mut funcs = BTreeMap::new();
+    funcs.insert(
+        "g_free".to_string(),
+        FuncSummary {
+            frees: vec![0],
+            returns: vec![],
+            derefs: vec![],
+            keeps: vec![],
+            yields: vec![],
+        },
+    );
+    let summary = LibSummary {
+        build_id: "0123abcd".to_string(),
+        path: "libglib-2.0.so.0".to_string(),
+        funcs: funcs,
+    };
+    store.put(&summary).unwrap();
+    assert_eq!(store.get("0123abcd"), Some(summary.clone()));
+    assert_eq!(store.lookup("0123abcd"), Some(summary));
+    assert_eq!(store.get("ffff"), None);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+pub fn bundled_model() {
+    let model = tiamat::lib_summary::bundled_model();
+    assert_eq!(model.funcs["puts"].derefs, vec![0]);
+    assert_eq!(model.funcs["memcpy"].derefs, vec![0, 1]);
+    assert_eq!(model.funcs["memcpy"].returns, vec![0]);
+    assert_eq!(model.funcs["fclose"].frees, vec![0]);
+    assert_eq!(model.funcs["realloc"].frees, vec![0]);
+    assert_eq!(model.funcs["g_hash_table_insert"].keeps, vec![1, 2]);
+    assert_eq!(model.funcs["g_hash_table_lookup"].yields, vec![0]);
+}
+
+#[test]
+pub fn model_errors() {
+    assert!(tiamat::lib_summary::parse_model("bad", "strlen 0").is_err());
+    assert!(tiamat::lib_summary::parse_model("bad", "strlen uses x").is_err());
+    let ok = tiamat::lib_summary::parse_model("ok", "# comment\n\nstrlen uses 0 # trailing\n").unwrap();
+    assert_eq!(ok.funcs.len(), 1);
+}
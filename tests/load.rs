@@ -0,0 +1,36 @@
+#[macro_use]
+extern crate holmes;
+use holmes::simple::*;
+extern crate tiamat;
+
+#[test]
+pub fn reload_same_content() {
+    single(&|holmes, core| {
+        tiamat::schema::setup(holmes)?;
+        tiamat::load_files(
+            holmes,
+            &["./samples/use_after_free/simple".to_string(),
+              "samples/use_after_free/simple".to_string()],
+        )?;
+        tiamat::load_files(holmes, &["./samples/use_after_free/simple".to_string()])?;
+        core.run(holmes.quiesce()).unwrap();
+        assert_eq!(query!(holmes, binary([_], [_], [_], [_]))?.len(), 1);
+        assert_eq!(query!(holmes, file([_], [_]))?.len(), 1);
+        Ok(())
+    })
+}
+
+#[test]
+pub fn distinct_content() {
+    single(&|holmes, core| {
+        tiamat::schema::setup(holmes)?;
+        tiamat::load_files(
+            holmes,
+            &["./samples/use_after_free/simple".to_string(),
+              "./samples/use_after_free/safe".to_string()],
+        )?;
+        core.run(holmes.quiesce()).unwrap();
+        assert_eq!(query!(holmes, binary([_], [_], [_], [_]))?.len(), 2);
+        Ok(())
+    })
+}
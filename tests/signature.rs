@@ -0,0 +1,70 @@
+extern crate tiamat;
+use tiamat::signature::{self, Place, Signature};
+
+fn place(func: &str, offset: u64, mnemonics: &[&str]) -> Place {
+    Place {
+        func: func.to_string(),
+        offset: offset,
+        mnemonics: mnemonics.iter().map(|m| m.to_string()).collect(),
+    }
+}
+
+fn sig(alloc: Place, use_: Place) -> Signature {
+    Signature {
+        checker: "uaf".to_string(),
+        binary: "simple".to_string(),
+        alloc: alloc,
+        use_: use_,
+        alloc_chain: vec![],
+        chain: vec!["main".to_string()],
+    }
+}
+
+#[test]
+pub fn mnemonics() {
+    assert_eq!(signature::mnemonics("movq %rsp, %rbp\nCALL64pcrel32(0x400400)\n\n  retq"), vec!["movq", "call64pcrel32", "retq"]);
+}
+
+#[test]
+pub fn fingerprint() {
+    let a = sig(place("f", 0x8, &["mov", "call"]), place("g", 0x10, &["movb"]));
+    let moved = sig(place("f", 0x18, &["mov", "call"]), place("g", 0x24, &["movb"]));
+    let changed = sig(place("f", 0x8, &["mov", "call"]), place("g", 0x10, &["movl"]));
+    assert_eq!(a.fingerprint(), moved.fingerprint());
+    assert!(a.fingerprint() != changed.fingerprint());
+    // The same code reached through other calls, or in another binary, is another finding
+    let mut deeper = a.clone();
+    deeper.alloc_chain = vec!["main".to_string()];
+    assert!(a.fingerprint() != deeper.fingerprint());
+    let mut elsewhere = a.clone();
+    elsewhere.binary = "other".to_string();
+    assert!(a.fingerprint() != elsewhere.fingerprint());
+    assert!(deeper.to_string().contains(" simple alloc f+0x8 [mov call] from main use "));
+}
+
+#[test]
+pub fn diff() {
+    let old = vec![
+        sig(place("f", 0x8, &["call"]), place("g", 0x10, &["movb"])),
+        sig(place("f", 0x8, &["call"]), place("g", 0x40, &["movb"])),
+        sig(place("h", 0x8, &["call"]), place("h", 0x20, &["movb"])),
+        sig(place("k", 0x8, &["call"]), place("k", 0x20, &["movb"])),
+    ];
+    let new = vec![
+        // Both of g's uses moved down by 0x10
+        sig(place("f", 0x8, &["call"]), place("g", 0x50, &["movb"])),
+        sig(place("f", 0x8, &["call"]), place("g", 0x20, &["movb"])),
+        // h's use block now does something else, but it is the same pair of functions
+        sig(place("h", 0x8, &["call"]), place("h", 0x28, &["movl", "movb"])),
+        sig(place("m", 0x8, &["call"]), place("m", 0x20, &["movb"])),
+    ];
+    let d = signature::diff(&old, &new);
+    assert_eq!(d.persisting.len(), 3);
+    // The nearest offsets pair up, so the two moved uses keep their order
+    assert!(d.persisting.iter().any(|&(ref o, ref n)| o.use_.offset == 0x10 && n.use_.offset == 0x20));
+    assert!(d.persisting.iter().any(|&(ref o, ref n)| o.use_.offset == 0x40 && n.use_.offset == 0x50));
+    assert_eq!(d.removed.len(), 1);
+    assert_eq!(d.removed[0].alloc.func, "k");
+    assert_eq!(d.added.len(), 1);
+    assert_eq!(d.added[0].alloc.func, "m");
+}
@@ -0,0 +1,64 @@
+extern crate tiamat;
+use tiamat::signature::{Place, Signature};
+use tiamat::suppress::{self, Suppression};
+
+fn sig(alloc: &str, use_: &str) -> Signature {
+    let place = |func: &str| Place { func: func.to_string(), offset: 0x10, mnemonics: vec!["movb".to_string()] };
+    Signature {
+        checker: "uaf".to_string(),
+        binary: "simple".to_string(),
+        alloc: place(alloc),
+        use_: place(use_),
+        alloc_chain: vec![],
+        chain: vec![],
+    }
+}
+
+#[test]
+pub fn glob() {
+    assert!(suppress::glob("g_hash_table_*", "g_hash_table_insert"));
+    assert!(suppress::glob("*_foreach", "g_list_foreach"));
+    assert!(suppress::glob("g_*_free*", "g_list_free_full"));
+    assert!(suppress::glob("*", ""));
+    assert!(suppress::glob("main", "main"));
+    assert!(!suppress::glob("main", "main2"));
+    assert!(!suppress::glob("ab*b", "ab"));
+}
+
+#[test]
+pub fn parse() {
+    let text = r#"[
+        {"fingerprint": "0123456789abcdef", "justification": "reviewed"},
+        {"alloc_func": "parse_*", "use_func": "log_*", "justification": "pointer only printed"}
+    ]"#;
+    let rules = suppress::parse("s.json", text).unwrap();
+    assert_eq!(rules.len(), 2);
+    assert_eq!(rules[0].alloc_func, None);
+    assert_eq!(rules[1].use_func, Some("log_*".to_string()));
+    assert!(suppress::parse("s.json", r#"[{"justification": "everything"}]"#).is_err());
+    assert!(suppress::parse("s.json", r#"[{"use_func": "main", "justification": " "}]"#).is_err());
+    assert!(suppress::parse("s.json", "not json").is_err());
+}
+
+#[test]
+pub fn matches() {
+    let found = sig("parse_line", "log_line");
+    let by_funcs = Suppression {
+        fingerprint: None,
+        alloc_func: Some("parse_*".to_string()),
+        use_func: Some("log_*".to_string()),
+        justification: "pointer only printed".to_string(),
+    };
+    assert!(by_funcs.matches(&found));
+    assert!(!by_funcs.matches(&sig("parse_line", "main")));
+    let by_fingerprint = Suppression {
+        fingerprint: Some(found.fingerprint()),
+        alloc_func: None,
+        use_func: None,
+        justification: "reviewed".to_string(),
+    };
+    assert!(by_fingerprint.matches(&found));
+    assert!(!by_fingerprint.matches(&sig("parse_line", "log_other")));
+    let rules = vec![by_fingerprint, by_funcs];
+    assert_eq!(suppress::find(&rules, &sig("parse_x", "log_y")).map(|r| r.justification.as_str()), Some("pointer only printed"));
+}
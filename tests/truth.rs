@@ -0,0 +1,71 @@
+extern crate tiamat;
+use std::collections::HashSet;
+use tiamat::grade;
+use tiamat::truth::{self, Site};
+
+const TRUTH: &'static str = "\
+# binary  checker  kind  sites
+simple    uaf      bad   alloc main+0x8  free main+0x1c  use simple.c:8
+safe      uaf      good  use main  # only the use matters
+";
+
+const DUMP: &'static str = "\
+Contents of the .debug_line section:
+
+CU: ./simple.c:
+File name                            Line number    Starting address    View    Stmt
+simple.c                                       4            0x1139               x
+simple.c                                       8            0x1160               x
+simple.c                                       8            0x116b               x
+simple.c                                       9            0x1172               x
+simple.c                                       -            0x1190
+";
+
+#[test]
+pub fn parse() {
+    let anns = truth::parse("t.truth", TRUTH).unwrap();
+    assert_eq!(anns.len(), 2);
+    assert_eq!(anns[0].label, "t.truth:2");
+    assert!(anns[0].bad);
+    assert_eq!(anns[0].alloc, Some(Site::Offset("main".to_string(), 8)));
+    assert_eq!(anns[0].free, Some(Site::Offset("main".to_string(), 0x1c)));
+    assert_eq!(anns[0].use_, Some(Site::Line("simple.c".to_string(), 8)));
+    assert!(!anns[1].bad);
+    assert_eq!(anns[1].alloc, None);
+    assert_eq!(anns[1].use_, Some(Site::Symbol("main".to_string())));
+    assert!(truth::parse("t.truth", "simple uaf bad free main").is_err());
+    assert!(truth::parse("t.truth", "simple uaf maybe use main").is_err());
+    assert!(truth::parse("t.truth", "simple uaf bad use").is_err());
+}
+
+#[test]
+pub fn resolve() {
+    let lines = truth::line_table(DUMP);
+    assert_eq!(lines.len(), 4);
+    let symbols = vec![("main".to_string(), 0x1139, 0x1190)];
+    assert_eq!(truth::resolve(&Site::Symbol("main".to_string()), &symbols, &lines), Some((0x1139, 0x1190)));
+    assert_eq!(truth::resolve(&Site::Offset("main".to_string(), 8), &symbols, &lines), Some((0x1141, 0x1141)));
+    assert_eq!(truth::resolve(&Site::Line("src/simple.c".to_string(), 8), &symbols, &lines), Some((0x1160, 0x116b)));
+    assert_eq!(truth::resolve(&Site::Line("simple.c".to_string(), 5), &symbols, &lines), None);
+    assert_eq!(truth::resolve(&Site::Symbol("f".to_string()), &symbols, &lines), None);
+}
+
+#[test]
+pub fn score() {
+    let anns = truth::parse("t.truth", TRUTH).unwrap();
+    let bad: HashSet<String> = vec!["t.truth:2".to_string()].into_iter().collect();
+    let rows = grade::score_truth(&anns, &bad, &HashSet::new(), &[]);
+    assert_eq!(rows.len(), 2);
+    assert_eq!((rows[0].binary.as_ref().unwrap().as_str(), rows[0].true_pos, rows[0].false_neg), ("safe", 0, 0));
+    assert_eq!(rows[0].true_neg, 1);
+    assert_eq!((rows[1].binary.as_ref().unwrap().as_str(), rows[1].true_pos, rows[1].false_neg), ("simple", 1, 0));
+    assert_eq!((rows[1].cwe.as_str(), rows[1].variant), ("CWE416", None));
+    // Findings no annotation covers are false positives, but only in binaries with annotations
+    let unmatched = vec![
+        ("uaf".to_string(), "samples/simple".to_string(), 2),
+        ("uaf".to_string(), "samples/other".to_string(), 1),
+    ];
+    let rows = grade::score_truth(&anns, &bad, &HashSet::new(), &unmatched);
+    assert_eq!((rows[0].false_pos, rows[1].false_pos), (0, 2));
+    assert_eq!(rows[1].true_pos, 1);
+}
@@ -85,6 +85,184 @@ pub fn inf_trace() {
     })
 }
 
+#[test]
+pub fn truncated_trace() {
+    single(&|holmes, core| {
+        let mut budgets = tiamat::budget::Budgets::default();
+        budgets.trace.facts = Some(0);
+        let report = tiamat::Pipeline::new(vec!["./samples/use_after_free/simple".to_string()])
+            .trace_len(9)
+            .const_prop(false)
+            .budgets(budgets)
+            .run(holmes, core)?;
+        assert!(report.budget.truncated(tiamat::budget::Stage::Trace));
+        assert!(!report.budget.truncated(tiamat::budget::Stage::Flow));
+        assert_eq!(query!(holmes, use_after_free([_]))?.len(), 0);
+        assert!(tiamat::unconfirmed(holmes)?.len() >= 1);
+        Ok(())
+    })
+}
+
+#[test]
+pub fn shingled() {
+    single(&|holmes, core| {
+        tiamat::Pipeline::new(vec!["./samples/use_after_free/simple".to_string()])
+            .trace_len(9)
+            .const_prop(false)
+            .shingle(true)
+            .run(holmes, core)?;
+        assert!(query!(holmes, use_after_free([_], [_], [_], [_], [_], [_], [_]))?.len() >= 1);
+        Ok(())
+    })
+}
+
+#[test]
+pub fn field() {
+    single(&|holmes, core| {
+        tiamat::uaf(vec!["./samples/use_after_free/field".to_string()], 30, false)(holmes, core)?;
+        assert!(query!(holmes, use_after_free([_], [_], [_], [_], [_], [_], [_]))?.len() >= 1);
+        Ok(())
+    })
+}
+
+#[test]
+pub fn correlated() {
+    // Freed only when argc == 1 and used only when it isn't
+    single(&|holmes, core| {
+        tiamat::uaf(vec!["./samples/use_after_free/correlated".to_string()], 30, false)(holmes, core)?;
+        assert!(query!(holmes, use_after_free_flow([_]))?.len() >= 1);
+        assert_eq!(query!(holmes, use_after_free([_], [_], [_], [_], [_], [_], [_]))?.len(), 0);
+        Ok(())
+    })
+}
+
+#[test]
+pub fn array() {
+    single(&|holmes, core| {
+        tiamat::uaf(vec!["./samples/use_after_free/array".to_string()], 30, false)(holmes, core)?;
+        assert!(query!(holmes, use_after_free([_], [_], [_], [_], [_], [_], [_]))?.len() >= 1);
+        Ok(())
+    })
+}
+
+#[test]
+pub fn symex() {
+    single(&|holmes, core| {
+        let report = tiamat::Pipeline::new(vec!["./samples/use_after_free/simple".to_string()])
+            .trace_len(9)
+            .const_prop(false)
+            .symex(true)
+            .run(holmes, core)?;
+        assert!(report.verdicts.iter().any(|&(_, n)| n > 0));
+        assert_eq!(query!(holmes, symex_verdict([_], [_], [_], [_], ("infeasible")))?.len(), 0);
+        // Only decidable with a solver to hand
+        if tiamat::symex::Solver::from_env().available() {
+            assert!(query!(holmes, symex_verdict([_], [_], [_], [_], ("feasible")))?.len() >= 1);
+        }
+        Ok(())
+    })
+}
+
+#[test]
+pub fn emulate() {
+    single(&|holmes, core| {
+        let report = tiamat::Pipeline::new(vec!["./samples/use_after_free/simple".to_string()])
+            .trace_len(9)
+            .const_prop(false)
+            .emulate(Some(vec![]))
+            .run(holmes, core)?;
+        assert!(report.emulated.iter().any(|&(ref outcome, n)| outcome == "confirmed" && n > 0));
+        Ok(())
+    })
+}
+
+#[test]
+pub fn func_summaries() {
+    single(&|holmes, core| {
+        tiamat::Pipeline::new(vec!["./samples/use_after_free/func".to_string()])
+            .trace_len(18)
+            .const_prop(false)
+            .summaries(true)
+            .run(holmes, core)?;
+        assert!(query!(holmes, use_after_free([_]))?.len() >= 1);
+        Ok(())
+    })
+}
+
+#[test]
+pub fn stack_depth_cutoff() {
+    single(&|holmes, core| {
+        let mut limits = tiamat::budget::Limits::default();
+        limits.stack_depth = 0;
+        let report = tiamat::Pipeline::new(vec!["./samples/use_after_free/func".to_string()])
+            .const_prop(false)
+            .limits(limits)
+            .run(holmes, core)?;
+        assert!(report.cutoffs.iter().any(|&(ref bound, n)| bound == "stack_depth" && n > 0));
+        let sites = report.cut_sites.iter().filter(|&&(ref bound, _, _)| bound == "stack_depth").count();
+        assert!(report.cutoffs.iter().any(|&(ref bound, n)| bound == "stack_depth" && n == sites));
+        Ok(())
+    })
+}
+
+#[test]
+pub fn truth() {
+    single(&|holmes, core| {
+        let path = "samples/use_after_free/uaf.truth";
+        let truth = tiamat::truth::parse(path, &tiamat::store::read_text(path)).unwrap();
+        let report = tiamat::Pipeline::new(vec![
+            "./samples/use_after_free/simple".to_string(),
+            "./samples/use_after_free/safe".to_string(),
+        ]).trace_len(9)
+            .const_prop(false)
+            .truth(truth)
+            .run(holmes, core)?;
+        // Only the annotations for binaries that were loaded resolve
+        assert_eq!(report.unresolved.len(), 8);
+        let hits = query!(holmes, true_positive([_], [_], label))?;
+        assert!(hits.len() >= 1);
+        assert!(hits.iter().all(|row| row[0].get().downcast_ref::<String>().unwrap() == "samples/use_after_free/uaf.truth:5"));
+        assert_eq!(query!(holmes, false_positive([_]))?.len(), 0);
+        Ok(())
+    })
+}
+
+#[test]
+pub fn signatures() {
+    single(&|holmes, core| {
+        tiamat::uaf(vec!["./samples/use_after_free/func".to_string()], 18, false)(holmes, core)?;
+        let sigs = tiamat::signatures(holmes)?;
+        assert!(sigs.len() >= 1);
+        assert!(sigs.iter().all(|sig| sig.alloc.func == "f" && sig.use_.func == "main"));
+        // f's allocation comes back out to main, which the use is in
+        assert!(sigs.iter().all(|sig| sig.binary == "func" && sig.alloc_chain == vec!["main".to_string()]));
+        Ok(())
+    })
+}
+
+#[test]
+pub fn suppressed() {
+    single(&|holmes, core| {
+        let rules = tiamat::suppress::parse(
+            "inline",
+            r#"[{"alloc_func": "main", "use_func": "main", "justification": "the sample is meant to be broken"}]"#,
+        ).unwrap();
+        let report = tiamat::Pipeline::new(vec!["./samples/use_after_free/simple".to_string()])
+            .trace_len(9)
+            .const_prop(false)
+            .suppressions(rules)
+            .emulate(Some(vec![]))
+            .run(holmes, core)?;
+        assert!(report.suppressed >= 1);
+        assert_eq!(tiamat::suppressed(holmes)?.len(), report.suppressed);
+        // Grading only sees what the suppressions leave
+        assert!(query!(holmes, unsuppressed([_], [_], [_], [_]))?.is_empty());
+        // Every finding is in main, so there is nothing left to emulate
+        assert!(report.emulated.is_empty());
+        Ok(())
+    })
+}
+
 fn dump(holmes: &mut Engine, target: &str) {
     let data = holmes.render(&target.to_string()).unwrap();
     let mut out_fd = std::fs::File::create(format!("{}.html", target)).unwrap();
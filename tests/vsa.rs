@@ -0,0 +1,126 @@
+extern crate tiamat;
+extern crate bap;
+use bap::high::bil::{BinOp, Statement};
+use bap::high::bitvector::BitVector;
+use tiamat::sema::Sema;
+use tiamat::transfer;
+use tiamat::var::HVar;
+use tiamat::vsa::{Env, StridedInterval};
+
+mod common;
+use common::*;
+
+fn plain(name: &str) -> HVar {
+    HVar { inner: reg(name), offset: None, array: None }
+}
+
+// Steps through every statement, returning the environment at the end
+fn env_after(stmts: &[Statement]) -> Env {
+    let mut env = Env::new();
+    for stmt in stmts.iter() {
+        env.step(stmt);
+    }
+    env
+}
+
+#[test]
+pub fn scaled_index() {
+    // [rsp + rax*8 + 0x10] with rax unknown
+    let slots = StridedInterval::multiples(8).add(&StridedInterval::constant(0x10));
+    assert_eq!(slots.stride, 8);
+    assert_eq!(slots.residue, 0);
+    assert!(slots.contains(0x10));
+    assert!(slots.contains(-8));
+    assert!(!slots.contains(0x14));
+    // The same array read back, and a misaligned field next to it
+    assert!(slots.overlaps(&StridedInterval::multiples(8)));
+    assert!(!slots.overlaps(&StridedInterval::multiples(8).add(&StridedInterval::constant(4))));
+    assert!(slots.overlaps(&StridedInterval::constant(0x18)));
+    assert!(!slots.overlaps(&StridedInterval::constant(0x1c)));
+}
+
+#[test]
+pub fn bounds() {
+    // and eax, 7 then scaled by 8
+    let idx = StridedInterval::top().mask(7).scale(8);
+    assert_eq!(idx.stride, 8);
+    assert_eq!((idx.lo, idx.hi), (Some(0), Some(56)));
+    assert!(idx.contains(56));
+    assert!(!idx.contains(64));
+    assert!(!idx.overlaps(&StridedInterval::constant(64)));
+    assert!(!idx.overlaps(&StridedInterval::range(-16, -1)));
+    // Negative scaling flips the bounds
+    let neg = idx.scale(-1);
+    assert_eq!((neg.lo, neg.hi), (Some(-56), Some(0)));
+    assert!(neg.contains(-8));
+}
+
+#[test]
+pub fn join() {
+    let a = StridedInterval::constant(8);
+    let b = StridedInterval::constant(24);
+    let both = a.join(&b);
+    assert_eq!(both.stride, 16);
+    assert!(both.contains(8) && both.contains(24));
+    assert!(!both.contains(16));
+    assert_eq!((both.lo, both.hi), (Some(8), Some(24)));
+    // Unbounded on one side stays unbounded
+    let wide = both.join(&StridedInterval::multiples(4));
+    assert_eq!((wide.stride, wide.lo, wide.hi), (4, None, None));
+}
+
+#[test]
+pub fn promote_scaled() {
+    // lea rdx, [rsp + rax*8]; mov rbx, [rdx + 0x10]
+    let env = env_after(&[
+        mov("RCX", binop(BinOp::LShift, var("RAX"), konst(3))),
+        mov("RDX", binop(BinOp::Add, var("RSP"), var("RCX"))),
+    ]);
+    let hv = transfer::promote_array(&binop(BinOp::Add, var("RDX"), konst(0x10)), &env).unwrap();
+    assert_eq!(hv.inner, reg("RSP"));
+    assert_eq!(hv.offset, None);
+    assert_eq!(hv.array, Some(StridedInterval::multiples(8).add(&StridedInterval::constant(0x10))));
+}
+
+#[test]
+pub fn promote_constant() {
+    // Offsets that work out to a single value come back as a plain field
+    let env = env_after(&[mov("RDX", binop(BinOp::Add, var("RSP"), konst(8)))]);
+    let hv = transfer::promote_array(&binop(BinOp::Add, var("RDX"), konst(0x10)), &env).unwrap();
+    assert_eq!(hv.inner, reg("RSP"));
+    assert_eq!(hv.offset, Some(BitVector::from_u64(0x18, 64)));
+    assert_eq!(hv.array, None);
+    // Absolute addresses are relative to the globals
+    let hv = transfer::promote_array(&konst(0x601040), &Env::new()).unwrap();
+    assert_eq!(hv.inner, tiamat::var::globals());
+}
+
+#[test]
+pub fn env_forgets_overwritten_base() {
+    // Once rsp changes, rdx is no longer known relative to it
+    let env = env_after(&[
+        mov("RDX", binop(BinOp::Add, var("RSP"), konst(8))),
+        mov("RSP", load(var("RBX"))),
+    ]);
+    let hv = transfer::promote_array(&var("RDX"), &env).unwrap();
+    assert_eq!(hv.inner, reg("RDX"));
+    assert_eq!(hv.offset, Some(BitVector::from_u64(0, 64)));
+}
+
+#[test]
+pub fn taint_through_array() {
+    // A pointer stored at an unknown slot of a stack array may be the one read back from a
+    // fixed slot, but not from one off the array's stride
+    let block = Sema {
+        stmts: vec![
+            mov("RCX", binop(BinOp::LShift, var("RAX"), konst(3))),
+            store(binop(BinOp::Add, var("RSP"), var("RCX")), var("RDI")),
+            mov("RBX", load(binop(BinOp::Add, var("RSP"), konst(0x10)))),
+            mov("RSI", load(binop(BinOp::Add, var("RSP"), konst(0x14)))),
+        ],
+    };
+    let tainted = transfer::xfer_taint((&block, &plain("RDI")));
+    assert!(tainted.contains(&plain("RBX")));
+    assert!(!tainted.contains(&plain("RSI")));
+    assert!(tainted.iter().any(|hv| hv.inner == reg("RSP") && hv.array == Some(StridedInterval::multiples(8))));
+}